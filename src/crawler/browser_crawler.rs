@@ -1,31 +1,622 @@
-use headless_chrome::Browser;
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use headless_chrome::{
+    browser::tab::RequestPausedDecision,
+    protocol::cdp::{
+        types::Event,
+        Fetch::events::RequestPausedEvent,
+        Network::{events::ResponseReceivedEventParams, GetResponseBodyReturnObject, ResourceType},
+    },
+    Browser, Tab,
+};
 
 use eyre::{eyre, Context, Result};
+use tap::TapFallible;
+use tokio::{sync::Semaphore, task::spawn_blocking};
+
+use url::Url;
+
+use super::{
+    download::DownloadOptions,
+    fetch_error::FetchError,
+    font_usage::{parse_font_usage, FontUsage, FONT_USAGE_SCRIPT},
+    host_limiter::HostLimiter,
+};
+
+// Name of the response handler registered on each tab
+const FONT_RESPONSE_HANDLER: &str = "font_responses";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_NETWORK_IDLE_TIME: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Like Puppeteer's networkidle2, a couple of long polling requests or open
+// connections still count as idle
+const MAX_IDLE_REQUESTS: usize = 2;
+
+// Time on top of the timeout for reading the content and font usage of the page
+const READ_TIME: Duration = Duration::from_secs(10);
+
+// For closing a tab, and for telling a crashed browser from a busy one
+const BROWSER_CALL_TIMEOUT: Duration = Duration::from_secs(5);
 
+// A pool of tabs in one Chrome, shared by cloning. The blocking calls to Chrome
+// are run with spawn_blocking, so they do not hold up the async runtime.
+#[derive(Clone)]
 pub struct BrowserCrawler {
-    client: Browser,
+    browser: Arc<Mutex<BrowserState>>,
+    tabs: Arc<Semaphore>,
+    options: BrowserOptions,
+    host_limiter: Arc<HostLimiter>,
+    // Only the font limit applies, the page and its stylesheets are read by the browser
+    download_options: DownloadOptions,
+}
+
+// Chrome is launched when the first page needs it, and again after it crashes
+#[derive(Default)]
+struct BrowserState {
+    browser: Option<Browser>,
+    // Counts launches, to only relaunch a crashed browser once
+    generation: u64,
+}
+
+// How to render a page, configured per crawl
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowserOptions {
+    pub mode: BrowserMode,
+    pub wait_strategy: WaitStrategy,
+    // For loading the page and waiting, after which the content is read as it is
+    pub timeout: Duration,
+}
+
+impl Default for BrowserOptions {
+    fn default() -> Self {
+        BrowserOptions {
+            mode: BrowserMode::default(),
+            wait_strategy: WaitStrategy::default(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+// When the page is done, after it has navigated. Single page apps often
+// render their content after the load event.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum WaitStrategy {
+    // Read the content right after the load event
+    #[default]
+    Navigation,
+    // Until there has been no network activity for the given time
+    NetworkIdle(Duration),
+    // Until document.fonts.ready resolves
+    FontsReady,
+    // Until an element matching the css selector exists
+    Selector(String),
+    // A fixed time
+    Delay(Duration),
+}
+
+// Requests in flight, to tell when the network is idle
+struct NetworkActivity {
+    requests: HashSet<String>,
+    last_activity: Instant,
+}
+
+// What to read from a rendered page
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BrowserMode {
+    // The html, and the fonts loaded while rendering it
+    #[default]
+    Content,
+    // Also which fonts the text is rendered with, read from the computed styles
+    FontUsage,
+}
+
+// The html of a rendered page, and the fonts the browser loaded while rendering it
+#[derive(Debug, Clone)]
+pub struct BrowserPage {
+    pub content: String,
+    pub loaded_fonts: Vec<LoadedFont>,
+    // Only read in BrowserMode::FontUsage
+    pub font_usage: Option<FontUsage>,
+}
+
+// A font response, which includes fonts added by scripts, like Adobe Fonts kits
+// or the FontFace api, that are not in the html or css
+#[derive(Debug, Clone)]
+pub struct LoadedFont {
+    pub url: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
 }
 
 impl BrowserCrawler {
-    pub fn new() -> Result<Self> {
-        let client = Browser::default().map_err(|err| eyre!(err))?;
-        Ok(BrowserCrawler { client })
+    pub fn new(max_tabs: usize) -> Self {
+        BrowserCrawler {
+            browser: Arc::new(Mutex::new(BrowserState::default())),
+            tabs: Arc::new(Semaphore::new(max_tabs)),
+            options: BrowserOptions::default(),
+            host_limiter: Arc::new(HostLimiter::default()),
+            download_options: DownloadOptions::default(),
+        }
+    }
+
+    pub fn with_options(self, options: BrowserOptions) -> Self {
+        BrowserCrawler { options, ..self }
+    }
+
+    pub fn with_host_limiter(self, host_limiter: Arc<HostLimiter>) -> Self {
+        BrowserCrawler {
+            host_limiter,
+            ..self
+        }
+    }
+
+    pub fn with_download_options(self, download_options: DownloadOptions) -> Self {
+        BrowserCrawler {
+            download_options,
+            ..self
+        }
     }
 
     #[tracing::instrument(skip(self))]
-    pub fn get_page_content(&self, base_url: &str) -> Result<String> {
-        let tab = self
-            .client
-            .new_tab()
+    pub async fn get_page(&self, base_url: &str) -> Result<BrowserPage> {
+        // The page holds a slot of its host while it renders. The stylesheets, scripts
+        // and fonts the browser loads for it only wait for a token of their host.
+        let url = Url::parse(base_url).wrap_err(format!("Unable to parse url {}", base_url))?;
+        let _host_permit = self.host_limiter.acquire(&url).await;
+
+        let _permit = self
+            .tabs
+            .acquire()
+            .await
+            .wrap_err("Browser pool is closed")?;
+
+        let (browser, generation) = self.get_browser().await?;
+
+        match self.render_page(browser.clone(), base_url).await {
+            // The page is tried once more in a new browser, since the crash may be unrelated
+            Err(err) if self.relaunch_if_crashed(browser, generation).await => {
+                tracing::warn!(error = ?err, "Browser crashed. Trying again in a new browser...");
+
+                let (browser, _) = self.get_browser().await?;
+                self.render_page(browser, base_url).await
+            }
+            result => result,
+        }
+    }
+
+    async fn get_browser(&self) -> Result<(Browser, u64)> {
+        let state = self.browser.clone();
+
+        spawn_blocking(move || {
+            let mut state = state.lock().unwrap();
+
+            if let Some(browser) = &state.browser {
+                return Ok((browser.clone(), state.generation));
+            }
+
+            tracing::info!("Launching browser");
+            let browser = Browser::default()
+                .map_err(|err| eyre!(err))
+                .wrap_err("Could not launch browser")?;
+
+            state.generation += 1;
+            state.browser = Some(browser.clone());
+
+            Ok((browser, state.generation))
+        })
+        .await
+        .map_err(|err| eyre!(err))?
+    }
+
+    // Drops a browser that no longer answers, which kills its process, so the
+    // next page launches a new one
+    async fn relaunch_if_crashed(&self, browser: Browser, generation: u64) -> bool {
+        let is_running = tokio::time::timeout(
+            BROWSER_CALL_TIMEOUT,
+            spawn_blocking(move || browser.get_version().is_ok()),
+        )
+        .await
+        .is_ok_and(|is_running| is_running.unwrap_or(false));
+
+        if is_running {
+            return false;
+        }
+
+        let mut state = self.browser.lock().unwrap();
+        if state.generation == generation {
+            state.browser = None;
+        }
+
+        true
+    }
+
+    async fn render_page(&self, browser: Browser, base_url: &str) -> Result<BrowserPage> {
+        let tab = spawn_blocking(move || browser.new_tab())
+            .await
+            .map_err(|err| eyre!(err))?
             .map_err(|err| eyre!(err))
             .wrap_err("Could not open new tab in browser")?;
 
-        // todo: how to ensure all the html is loaded?
-        //       maybe just wait x number of seconds
-        tab.navigate_to(base_url)
-            .and_then(|tab| tab.wait_until_navigated())
-            .and_then(|tab| tab.get_content())
+        let options = self.options.clone();
+        let host_limiter = self.host_limiter.clone();
+        let max_font_size = self.download_options.max_font_size;
+        let url = base_url.to_owned();
+        let page_tab = tab.clone();
+
+        let result = tokio::time::timeout(
+            self.options.timeout + READ_TIME,
+            spawn_blocking(move || {
+                get_page_from_tab(&page_tab, &options, host_limiter, max_font_size, &url)
+            }),
+        )
+        .await;
+
+        // Closing the tab also stops a page that hangs
+        close_tab(tab).await;
+
+        match result {
+            Ok(page) => page.map_err(|err| eyre!(err))?,
+            Err(_) => Err(eyre!("Timed out rendering page in browser")),
+        }
+    }
+}
+
+async fn close_tab(tab: Arc<Tab>) {
+    let closed = tokio::time::timeout(
+        BROWSER_CALL_TIMEOUT,
+        spawn_blocking(move || tab.close(false)),
+    )
+    .await;
+
+    if !matches!(closed, Ok(Ok(Ok(_)))) {
+        tracing::warn!("Failed to close tab. Continuing...");
+    }
+}
+
+fn get_page_from_tab(
+    tab: &Tab,
+    options: &BrowserOptions,
+    host_limiter: Arc<HostLimiter>,
+    max_font_size: u64,
+    base_url: &str,
+) -> Result<BrowserPage> {
+    let loaded_fonts: Arc<Mutex<Vec<LoadedFont>>> = Arc::new(Mutex::new(vec![]));
+    let handler_fonts = loaded_fonts.clone();
+
+    // Called when a response has finished loading, which also enables the network domain
+    tab.register_response_handling(
+        FONT_RESPONSE_HANDLER,
+        Box::new(move |params, get_response_body| {
+            if !is_font_response(&params) {
+                return;
+            }
+
+            match get_response_body()
+                .map_err(|err| eyre!(err))
+                .and_then(|body| decode_response_body(body, max_font_size))
+            {
+                Ok(data) => handler_fonts.lock().unwrap().push(LoadedFont {
+                    url: params.response.url,
+                    mime_type: params.response.mime_type,
+                    data,
+                }),
+                Err(err) => tracing::error!(
+                    error = ?err,
+                    "Failed to get font response body for {}. Continuing...",
+                    params.response.url
+                ),
+            }
+        }),
+    )
+    .map_err(|err| eyre!(err))
+    .wrap_err("Could not listen for responses in browser")?;
+
+    let deadline = Instant::now() + options.timeout;
+    tab.set_default_timeout(options.timeout);
+
+    let network_activity = Arc::new(Mutex::new(NetworkActivity {
+        requests: HashSet::new(),
+        last_activity: Instant::now(),
+    }));
+    let listener_activity = network_activity.clone();
+
+    let listener = tab
+        .add_event_listener(Arc::new(move |event: &Event| {
+            let mut activity = listener_activity.lock().unwrap();
+            match event {
+                Event::NetworkRequestWillBeSent(event) => {
+                    activity.requests.insert(event.params.request_id.to_owned());
+                }
+                Event::NetworkLoadingFinished(event) => {
+                    activity.requests.remove(&event.params.request_id);
+                }
+                Event::NetworkLoadingFailed(event) => {
+                    activity.requests.remove(&event.params.request_id);
+                }
+                _ => return,
+            }
+            activity.last_activity = Instant::now();
+        }))
+        .map_err(|err| eyre!(err))?;
+
+    // Called on the event thread of the tab, before the browser sends each request.
+    // The page already has its token.
+    let page_url = Url::parse(base_url).ok();
+    let interceptor_activity = network_activity.clone();
+    tab.enable_request_interception(Arc::new(
+        move |_transport, _session_id, event: RequestPausedEvent| {
+            let url = Url::parse(&event.params.request.url)
+                .ok()
+                .filter(|url| url.has_host() && Some(url) != page_url.as_ref());
+
+            if let Some(url) = url {
+                let wait = host_limiter.take_token(&url);
+                if !wait.is_zero() {
+                    // Activity until the request is sent, so the network is not idle while it waits
+                    interceptor_activity.lock().unwrap().last_activity = Instant::now() + wait;
+                    std::thread::sleep(wait);
+                }
+            }
+
+            RequestPausedDecision::Continue(None)
+        },
+    ))
+    .and_then(|_| tab.enable_fetch(None, None).map(|_| ()))
+    .map_err(|err| eyre!(err))
+    .wrap_err("Could not intercept requests in browser")?;
+
+    tab.navigate_to(base_url)
+        .and_then(|tab| tab.wait_until_navigated())
+        .map_err(|err| eyre!(err))
+        .wrap_err("Could not navigate to page with browser")?;
+
+    // Reading a page that is not quite done is better than nothing
+    if let Err(err) = wait_for_page(tab, &options.wait_strategy, deadline, &network_activity) {
+        tracing::warn!(error = ?err, "Page was not done before the timeout. Continuing...");
+    }
+
+    tab.remove_event_listener(&listener)
+        .map_err(|err| eyre!(err))?;
+
+    let content = tab
+        .get_content()
+        .map_err(|err| eyre!(err))
+        .wrap_err("Could not fetch content with browser")?;
+
+    // Not worth failing the page for, the declared fonts are still there
+    let font_usage = match options.mode {
+        BrowserMode::Content => None,
+        BrowserMode::FontUsage => tab
+            .evaluate(FONT_USAGE_SCRIPT, true)
             .map_err(|err| eyre!(err))
-            .wrap_err("Could not fetch content with browser")
+            .and_then(|result| match result.value {
+                Some(serde_json::Value::String(json)) => parse_font_usage(&json),
+                _ => Err(eyre!(
+                    "Font usage script did not return a string: {:?}",
+                    result
+                )),
+            })
+            .tap_err(|err| tracing::error!(error = ?err, "Failed to get font usage. Continuing..."))
+            .ok(),
+    };
+
+    tab.deregister_response_handling(FONT_RESPONSE_HANDLER)
+        .map_err(|err| eyre!(err))?;
+
+    let loaded_fonts = std::mem::take(&mut *loaded_fonts.lock().unwrap());
+
+    tracing::info!("Browser loaded {} fonts", loaded_fonts.len());
+
+    Ok(BrowserPage {
+        content,
+        loaded_fonts,
+        font_usage,
+    })
+}
+
+fn wait_for_page(
+    tab: &Tab,
+    wait_strategy: &WaitStrategy,
+    deadline: Instant,
+    network_activity: &Mutex<NetworkActivity>,
+) -> Result<()> {
+    let remaining = || deadline.saturating_duration_since(Instant::now());
+
+    match wait_strategy {
+        WaitStrategy::Navigation => Ok(()),
+        WaitStrategy::NetworkIdle(idle_time) => loop {
+            {
+                let activity = network_activity.lock().unwrap();
+                if activity.requests.len() <= MAX_IDLE_REQUESTS
+                    && activity.last_activity.elapsed() >= *idle_time
+                {
+                    return Ok(());
+                }
+            }
+
+            if remaining().is_zero() {
+                return Err(eyre!("Network was not idle for {:?}", idle_time));
+            }
+            std::thread::sleep(POLL_INTERVAL.min(remaining()));
+        },
+        WaitStrategy::FontsReady => {
+            // document.fonts.ready never resolves if a font keeps loading
+            let script = format!(
+                "Promise.race([document.fonts.ready.then(() => true), new Promise((resolve) => setTimeout(() => resolve(false), {}))])",
+                remaining().as_millis()
+            );
+
+            match tab.evaluate(&script, true).map_err(|err| eyre!(err))?.value {
+                Some(serde_json::Value::Bool(true)) => Ok(()),
+                _ => Err(eyre!("Fonts were not ready")),
+            }
+        }
+        WaitStrategy::Selector(selector) => tab
+            .wait_for_element_with_custom_timeout(selector, remaining())
+            .map(|_| ())
+            .map_err(|err| eyre!(err))
+            .wrap_err(format!("Could not find element matching {}", selector)),
+        WaitStrategy::Delay(delay) => {
+            std::thread::sleep((*delay).min(remaining()));
+            Ok(())
+        }
+    }
+}
+
+// Written like network-idle:500, fonts-ready, selector:#app or delay:2000,
+// with times in milliseconds
+impl FromStr for WaitStrategy {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (name, argument) = match value.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (value, None),
+        };
+
+        let milliseconds = |argument: &str| {
+            argument
+                .parse::<u64>()
+                .map(Duration::from_millis)
+                .wrap_err(format!("Invalid number of milliseconds {}", argument))
+        };
+
+        match (name, argument) {
+            ("navigation", None) => Ok(WaitStrategy::Navigation),
+            ("network-idle", None) => Ok(WaitStrategy::NetworkIdle(DEFAULT_NETWORK_IDLE_TIME)),
+            ("network-idle", Some(argument)) => {
+                Ok(WaitStrategy::NetworkIdle(milliseconds(argument)?))
+            }
+            ("fonts-ready", None) => Ok(WaitStrategy::FontsReady),
+            ("selector", Some(selector)) if !selector.is_empty() => {
+                Ok(WaitStrategy::Selector(selector.to_owned()))
+            }
+            ("delay", Some(argument)) => Ok(WaitStrategy::Delay(milliseconds(argument)?)),
+            _ => Err(eyre!("Unknown wait strategy {}", value)),
+        }
+    }
+}
+
+fn is_font_response(params: &ResponseReceivedEventParams) -> bool {
+    params.Type == ResourceType::Font || is_font_mime_type(&params.response.mime_type)
+}
+
+// font/woff2, and older types like application/font-woff or application/x-font-ttf
+fn is_font_mime_type(mime_type: &str) -> bool {
+    let mime_type = mime_type.trim().to_ascii_lowercase();
+
+    mime_type.starts_with("font/")
+        || mime_type.starts_with("application/font-")
+        || mime_type.starts_with("application/x-font-")
+        || mime_type == "application/vnd.ms-fontobject"
+}
+
+// Bodies larger than max_size are left out, like the http crawler stops downloading them
+fn decode_response_body(body: GetResponseBodyReturnObject, max_size: u64) -> Result<Vec<u8>> {
+    let data = match body.base_64_encoded {
+        true => STANDARD
+            .decode(body.body)
+            .wrap_err("Failed to decode base64 response body")?,
+        false => body.body.into_bytes(),
+    };
+
+    if data.len() as u64 > max_size {
+        return Err(FetchError::TooLarge(max_size).into());
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use headless_chrome::protocol::cdp::Network::GetResponseBodyReturnObject;
+
+    use super::{decode_response_body, is_font_mime_type, WaitStrategy};
+
+    #[test]
+    fn leave_out_bodies_larger_than_max_size() {
+        let body = |body: &str, base_64_encoded: bool| GetResponseBodyReturnObject {
+            body: body.to_owned(),
+            base_64_encoded,
+        };
+
+        // "wOF2font"
+        assert_eq!(
+            decode_response_body(body("d09GMmZvbnQ=", true), 8).ok(),
+            Some(b"wOF2font".to_vec())
+        );
+        assert!(decode_response_body(body("d09GMmZvbnQ=", true), 7).is_err());
+        assert!(decode_response_body(body("wOF2font", false), 7).is_err());
+    }
+
+    #[test]
+    fn recognise_font_mime_types() {
+        let font_mime_types = [
+            "font/woff2",
+            "font/ttf",
+            "application/font-woff",
+            "application/x-font-ttf",
+            "application/x-font-opentype",
+            "application/vnd.ms-fontobject",
+            " Font/WOFF ",
+        ];
+
+        for mime_type in font_mime_types {
+            assert!(is_font_mime_type(mime_type), "{}", mime_type);
+        }
+
+        let other_mime_types = ["text/css", "application/octet-stream", "image/svg+xml"];
+
+        for mime_type in other_mime_types {
+            assert!(!is_font_mime_type(mime_type), "{}", mime_type);
+        }
+    }
+
+    #[test]
+    fn parse_wait_strategies() {
+        let wait_strategies = [
+            ("navigation", WaitStrategy::Navigation),
+            (
+                "network-idle",
+                WaitStrategy::NetworkIdle(Duration::from_millis(500)),
+            ),
+            (
+                "network-idle:1000",
+                WaitStrategy::NetworkIdle(Duration::from_millis(1000)),
+            ),
+            ("fonts-ready", WaitStrategy::FontsReady),
+            (
+                "selector:main > article:first-child",
+                WaitStrategy::Selector("main > article:first-child".to_owned()),
+            ),
+            (
+                "delay:2000",
+                WaitStrategy::Delay(Duration::from_millis(2000)),
+            ),
+        ];
+
+        for (value, expected) in wait_strategies {
+            assert_eq!(value.parse::<WaitStrategy>().ok(), Some(expected));
+        }
+
+        for value in [
+            "idle",
+            "delay",
+            "delay:soon",
+            "selector:",
+            "fonts-ready:100",
+        ] {
+            assert!(value.parse::<WaitStrategy>().is_err(), "{}", value);
+        }
     }
 }
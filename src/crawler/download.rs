@@ -0,0 +1,243 @@
+use std::str::FromStr;
+
+use eyre::{eyre, Result};
+use reqwest::{header::HeaderMap, Response};
+
+use crate::{font_parser::has_font_signature, parsers::content_type::ContentType};
+
+use super::fetch_error::FetchError;
+
+// The signature that tells the format of a font file
+pub const FONT_SIGNATURE_SIZE: usize = 4;
+
+// What is downloaded, which decides what is accepted and how much of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceKind {
+    Html,
+    Css,
+    Font,
+}
+
+impl ResourceKind {
+    pub fn accept(&self) -> &'static str {
+        match self {
+            ResourceKind::Html => "text/html,application/xhtml+xml",
+            ResourceKind::Css => "text/css,*/*;q=0.1",
+            ResourceKind::Font => "*/*",
+        }
+    }
+
+    // Stylesheets and fonts are shared by many sites, pages are not
+    pub fn is_cacheable(&self) -> bool {
+        *self != ResourceKind::Html
+    }
+
+    // Fonts are served with all kinds of content types, so only their content tells
+    pub fn is_accepted(&self, content_type: &ContentType) -> bool {
+        match self {
+            ResourceKind::Html => content_type.is_html(),
+            ResourceKind::Css => content_type.is_css(),
+            ResourceKind::Font => true,
+        }
+    }
+}
+
+// How much is downloaded, so that a url serving a huge file can't take down a task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownloadOptions {
+    // In bytes
+    pub max_html_size: u64,
+    // Stylesheets can embed fonts as data urls, so they get more room than pages
+    pub max_css_size: u64,
+    pub max_font_size: u64,
+    // Fetch only the first bytes of a font before downloading all of it, and
+    // skip it when they are not a font signature
+    pub sniff_fonts: bool,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            max_html_size: 10 * 1024 * 1024,
+            max_css_size: 20 * 1024 * 1024,
+            max_font_size: 50 * 1024 * 1024,
+            sniff_fonts: false,
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn max_size(&self, kind: ResourceKind) -> u64 {
+        match kind {
+            ResourceKind::Html => self.max_html_size,
+            ResourceKind::Css => self.max_css_size,
+            ResourceKind::Font => self.max_font_size,
+        }
+    }
+}
+
+// A number of bytes, optionally in KiB or MiB, e.g. 512k or 20m
+pub fn parse_size(value: &str) -> Result<u64> {
+    let lowercase = value.trim().to_ascii_lowercase();
+
+    let (number, unit) = match lowercase.strip_suffix('k') {
+        Some(number) => (number, 1024),
+        None => match lowercase.strip_suffix('m') {
+            Some(number) => (number, 1024 * 1024),
+            None => (lowercase.as_str(), 1),
+        },
+    };
+
+    u64::from_str(number)
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| eyre!("Invalid size {}", value))
+}
+
+// Streams the body, and stops as soon as it is larger than max_size, or when a
+// font turns out not to start with a font signature
+pub async fn read_body(
+    mut res: Response,
+    kind: ResourceKind,
+    max_size: u64,
+) -> Result<Vec<u8>, FetchError> {
+    if res
+        .content_length()
+        .is_some_and(|content_length| content_length > max_size)
+    {
+        return Err(FetchError::TooLarge(max_size));
+    }
+
+    let mut content: Vec<u8> = vec![];
+    let mut is_checked = kind != ResourceKind::Font;
+
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .map_err(|err| FetchError::from_reqwest(&err))?
+    {
+        content.extend_from_slice(&chunk);
+
+        if content.len() as u64 > max_size {
+            return Err(FetchError::TooLarge(max_size));
+        }
+
+        if !is_checked && content.len() >= FONT_SIGNATURE_SIZE {
+            if !has_font_signature(&content) {
+                return Err(not_a_font(&res));
+            }
+            is_checked = true;
+        }
+    }
+
+    if !is_checked {
+        return Err(not_a_font(&res));
+    }
+
+    Ok(content)
+}
+
+// Reads just enough of the body to tell whether it's a font
+pub async fn sniff_font(mut res: Response) -> Result<(), FetchError> {
+    let mut content: Vec<u8> = vec![];
+
+    while content.len() < FONT_SIGNATURE_SIZE {
+        match res
+            .chunk()
+            .await
+            .map_err(|err| FetchError::from_reqwest(&err))?
+        {
+            Some(chunk) => content.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+
+    match has_font_signature(&content) {
+        true => Ok(()),
+        false => Err(not_a_font(&res)),
+    }
+}
+
+fn not_a_font(res: &Response) -> FetchError {
+    unexpected_content_type(get_content_type(res.headers()).as_ref())
+}
+
+pub fn get_content_type(headers: &HeaderMap) -> Option<ContentType> {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(ContentType::parse)
+}
+
+pub fn unexpected_content_type(content_type: Option<&ContentType>) -> FetchError {
+    FetchError::UnexpectedContentType(content_type.map_or_else(
+        || "none".to_owned(),
+        |content_type| content_type.mime_type.to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+
+    use super::{parse_size, read_body, sniff_font, ResourceKind};
+    use crate::crawler::fetch_error::FetchError;
+
+    fn response(body: Vec<u8>) -> reqwest::Response {
+        http::Response::builder()
+            .header("content-type", "font/woff2")
+            .body(body)
+            .expect("is a valid response")
+            .into()
+    }
+
+    #[test]
+    fn parse_sizes() -> Result<()> {
+        assert_eq!(parse_size("1024")?, 1024);
+        assert_eq!(parse_size("512k")?, 512 * 1024);
+        assert_eq!(parse_size("20M")?, 20 * 1024 * 1024);
+
+        for value in ["", "0", "m", "-1", "1g", "1.5m"] {
+            assert!(parse_size(value).is_err(), "{}", value);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stop_reading_large_or_unexpected_bodies() -> Result<()> {
+        let font = b"wOF2 and the rest of the font".to_vec();
+
+        assert_eq!(
+            read_body(response(font.clone()), ResourceKind::Font, 100).await?,
+            font
+        );
+        assert_eq!(
+            read_body(response(font.clone()), ResourceKind::Font, 10).await,
+            Err(FetchError::TooLarge(10))
+        );
+        assert_eq!(
+            read_body(response(vec![b'a'; 1000]), ResourceKind::Css, 100).await,
+            Err(FetchError::TooLarge(100))
+        );
+        assert_eq!(
+            read_body(response(b"<html>".to_vec()), ResourceKind::Font, 100).await,
+            Err(FetchError::UnexpectedContentType("font/woff2".to_owned()))
+        );
+        assert_eq!(
+            read_body(response(b"<p>".to_vec()), ResourceKind::Font, 100).await,
+            Err(FetchError::UnexpectedContentType("font/woff2".to_owned()))
+        );
+        assert!(
+            read_body(response(b"<html>".to_vec()), ResourceKind::Html, 100)
+                .await
+                .is_ok()
+        );
+
+        assert!(sniff_font(response(font)).await.is_ok());
+        assert!(sniff_font(response(b"<html>".to_vec())).await.is_err());
+
+        Ok(())
+    }
+}
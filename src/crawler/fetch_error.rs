@@ -0,0 +1,365 @@
+use std::{
+    error::Error as StdError,
+    io,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use thiserror::Error;
+
+// Why a url could not be fetched. The transient ones are retried.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum FetchError {
+    #[error("Timed out")]
+    Timeout,
+    #[error("Could not resolve host")]
+    Dns,
+    #[error("TLS handshake failed")]
+    Tls,
+    #[error("Could not connect")]
+    Connect,
+    #[error("Client error. Returned status: {0}")]
+    ClientError(StatusCode),
+    #[error("Server error. Returned status: {0}")]
+    ServerError(StatusCode),
+    #[error("Too many requests. Retry after: {0:?}")]
+    TooManyRequests(Option<Duration>),
+    #[error("Response is larger than {0} bytes")]
+    TooLarge(u64),
+    // E.g. an html error page where a stylesheet or font was expected
+    #[error("Unexpected content type: {0}")]
+    UnexpectedContentType(String),
+    #[error("Request failed: {0}")]
+    Other(String),
+}
+
+impl FetchError {
+    pub fn from_reqwest(err: &reqwest::Error) -> Self {
+        if let Some(status) = err.status() {
+            return FetchError::from_status(status, &HeaderMap::new())
+                .unwrap_or_else(|| FetchError::Other(err.to_string()));
+        }
+
+        if err.is_timeout() {
+            return FetchError::Timeout;
+        }
+
+        if !err.is_connect() {
+            return FetchError::Other(err.to_string());
+        }
+
+        // reqwest only tells that connecting failed, the cause is further down the chain
+        let mut source = err.source();
+        while let Some(cause) = source {
+            if let Some(fetch_error) = from_connect_cause(cause) {
+                return fetch_error;
+            }
+            source = cause.source();
+        }
+
+        FetchError::Connect
+    }
+
+    // None when the status is a success
+    pub fn from_status(status: StatusCode, headers: &HeaderMap) -> Option<Self> {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = headers
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, SystemTime::now()));
+
+            return Some(FetchError::TooManyRequests(retry_after));
+        }
+
+        if status.is_client_error() {
+            Some(FetchError::ClientError(status))
+        } else if status.is_server_error() {
+            Some(FetchError::ServerError(status))
+        } else {
+            None
+        }
+    }
+
+    // Whether another try might succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Timeout | FetchError::Connect | FetchError::TooManyRequests(_) => true,
+            FetchError::ClientError(status) => *status == StatusCode::REQUEST_TIMEOUT,
+            FetchError::ServerError(status) => *status != StatusCode::NOT_IMPLEMENTED,
+            FetchError::Dns
+            | FetchError::Tls
+            | FetchError::TooLarge(_)
+            | FetchError::UnexpectedContentType(_)
+            | FetchError::Other(_) => false,
+        }
+    }
+
+    // A short name for the class, for grouping in the summary
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FetchError::Timeout => "timeout",
+            FetchError::Dns => "dns",
+            FetchError::Tls => "tls",
+            FetchError::Connect => "connect",
+            FetchError::ClientError(_) => "4xx",
+            FetchError::ServerError(_) => "5xx",
+            FetchError::TooManyRequests(_) => "429",
+            FetchError::TooLarge(_) => "too large",
+            FetchError::UnexpectedContentType(_) => "content type",
+            FetchError::Other(_) => "other",
+        }
+    }
+}
+
+// What one error in the chain of a failed connect tells about it, if anything
+fn from_connect_cause(cause: &(dyn StdError + 'static)) -> Option<FetchError> {
+    if cause.is::<native_tls::Error>() {
+        return Some(FetchError::Tls);
+    }
+
+    if let Some(err) = cause.downcast_ref::<hyper::Error>() {
+        return err.is_timeout().then_some(FetchError::Timeout);
+    }
+
+    if let Some(err) = cause.downcast_ref::<io::Error>() {
+        if err.kind() == io::ErrorKind::TimedOut {
+            return Some(FetchError::Timeout);
+        }
+
+        // The source of an io::Error skips the error it wraps
+        return err.get_ref().and_then(|inner| from_connect_cause(inner));
+    }
+
+    // The error of hyper's connector is private, and a failed lookup is only told
+    // by the message it starts with
+    cause
+        .to_string()
+        .starts_with("dns error")
+        .then_some(FetchError::Dns)
+}
+
+// Either a number of seconds or an http date
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = httpdate::parse_http_date(value).ok()?;
+    Some(retry_at.duration_since(now).unwrap_or_default())
+}
+
+// How often and how long to wait before trying a transient error again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    // Doubled for every retry, up to max_delay
+    pub base_delay: Duration,
+    // Servers asking us to wait longer than this with Retry-After are not retried
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // How long to wait before the next try, or None when it should not be retried.
+    // Attempts count from 0.
+    pub fn get_delay(&self, err: &FetchError, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_retries || !err.is_transient() {
+            return None;
+        }
+
+        let backoff = self.get_backoff(attempt);
+
+        match err {
+            FetchError::TooManyRequests(Some(retry_after)) if *retry_after > self.max_delay => None,
+            FetchError::TooManyRequests(Some(retry_after)) => Some(backoff.max(*retry_after)),
+            _ => Some(backoff),
+        }
+    }
+
+    // Exponential, with half of it random so that tasks that failed together
+    // don't retry together
+    fn get_backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        backoff / 2 + jitter(backoff / 2)
+    }
+}
+
+// A random duration up to max
+fn jitter(max: Duration) -> Duration {
+    max.mul_f64(rand::random::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        time::{Duration, SystemTime},
+    };
+
+    use eyre::Result;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::{from_connect_cause, parse_retry_after, FetchError, RetryPolicy};
+
+    #[test]
+    fn classify_status_codes() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(FetchError::from_status(StatusCode::OK, &headers), None);
+        assert_eq!(
+            FetchError::from_status(StatusCode::NOT_FOUND, &headers),
+            Some(FetchError::ClientError(StatusCode::NOT_FOUND))
+        );
+        assert_eq!(
+            FetchError::from_status(StatusCode::SERVICE_UNAVAILABLE, &headers),
+            Some(FetchError::ServerError(StatusCode::SERVICE_UNAVAILABLE))
+        );
+        assert_eq!(
+            FetchError::from_status(StatusCode::TOO_MANY_REQUESTS, &headers),
+            Some(FetchError::TooManyRequests(None))
+        );
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(
+            FetchError::from_status(StatusCode::TOO_MANY_REQUESTS, &headers),
+            Some(FetchError::TooManyRequests(Some(Duration::from_secs(120))))
+        );
+
+        assert!(FetchError::ServerError(StatusCode::BAD_GATEWAY).is_transient());
+        assert!(!FetchError::ServerError(StatusCode::NOT_IMPLEMENTED).is_transient());
+        assert!(FetchError::ClientError(StatusCode::REQUEST_TIMEOUT).is_transient());
+        assert!(!FetchError::ClientError(StatusCode::FORBIDDEN).is_transient());
+        assert!(!FetchError::Dns.is_transient());
+    }
+
+    #[test]
+    fn parse_retry_after_header() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+
+        assert_eq!(parse_retry_after("30", now), Some(Duration::from_secs(30)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        // Already passed
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", SystemTime::now()), None);
+    }
+
+    #[test]
+    fn back_off_with_jitter() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+
+        for (attempt, max) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 10)] {
+            let delay = policy
+                .get_delay(&FetchError::Timeout, attempt)
+                .expect("is retried");
+            let max = Duration::from_secs(max);
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+
+        assert_eq!(policy.get_delay(&FetchError::Timeout, 5), None);
+        assert_eq!(
+            policy.get_delay(&FetchError::ClientError(StatusCode::NOT_FOUND), 0),
+            None
+        );
+
+        // Waits as long as the server asks, unless that's too long
+        assert_eq!(
+            policy.get_delay(
+                &FetchError::TooManyRequests(Some(Duration::from_secs(5))),
+                0
+            ),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            policy.get_delay(
+                &FetchError::TooManyRequests(Some(Duration::from_secs(60))),
+                0
+            ),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn classify_connection_errors() -> Result<()> {
+        // Nothing listens on port 1
+        let err = reqwest::get("http://127.0.0.1:1/")
+            .await
+            .expect_err("connection is refused");
+        assert_eq!(FetchError::from_reqwest(&err), FetchError::Connect);
+
+        // .invalid never resolves
+        let err = reqwest::get("http://fonts.invalid/")
+            .await
+            .expect_err("lookup fails");
+        assert_eq!(FetchError::from_reqwest(&err), FetchError::Dns);
+
+        // Answers everything with plain http, and then keeps the connection open
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n").await;
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    drop(stream);
+                });
+            }
+        });
+
+        let err = reqwest::get(format!("https://{}/", address))
+            .await
+            .expect_err("handshake fails");
+        assert_eq!(FetchError::from_reqwest(&err), FetchError::Tls);
+
+        let err = reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()?
+            .get(format!("http://{}/", address))
+            .send()
+            .await
+            .expect_err("response never ends");
+        assert_eq!(FetchError::from_reqwest(&err), FetchError::Timeout);
+
+        // Connecting can also time out in the os
+        assert_eq!(
+            from_connect_cause(&io::Error::from(io::ErrorKind::TimedOut)),
+            Some(FetchError::Timeout)
+        );
+        assert_eq!(
+            from_connect_cause(&io::Error::from(io::ErrorKind::ConnectionRefused)),
+            None
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+// Evaluated in the page after it has loaded. Resolves to a JSON string with the
+// state of document.fonts, and the fonts the text on the page is rendered with.
+(async () => {
+  // Enough to cover the text of most pages, without stalling on huge ones
+  const MAX_TEXT_NODES = 5000;
+
+  const GENERIC_FAMILIES = [
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+    "ui-serif",
+    "ui-sans-serif",
+    "ui-monospace",
+    "ui-rounded",
+    "emoji",
+    "math",
+    "fangsong",
+    "-apple-system",
+    "blinkmacsystemfont",
+  ];
+
+  const SKIPPED_ELEMENTS = ["SCRIPT", "STYLE", "NOSCRIPT", "TEMPLATE"];
+
+  const unquote = (family) => family.trim().replace(/^["']|["']$/g, "");
+
+  await document.fonts.ready;
+
+  const fontFaces = [];
+  document.fonts.forEach((fontFace) =>
+    fontFaces.push({
+      family: unquote(fontFace.family),
+      weight: fontFace.weight,
+      style: fontFace.style,
+      status: fontFace.status,
+    })
+  );
+
+  const isWebFont = (family) =>
+    fontFaces.some((fontFace) => fontFace.family.toLowerCase() === family.toLowerCase());
+
+  // A font installed on the device changes the width of the text compared to
+  // the generic families it would otherwise fall back to
+  const context = document.createElement("canvas").getContext("2d");
+  const measure = (font) => {
+    context.font = font;
+    return context.measureText("mmmmmmmmmmlli10OQ@#").width;
+  };
+  const installed = new Map();
+  const isInstalled = (family) => {
+    const key = family.toLowerCase();
+    if (!installed.has(key)) {
+      installed.set(
+        key,
+        ["monospace", "serif", "sans-serif"].some(
+          (generic) => measure(`72px "${family}", ${generic}`) !== measure(`72px ${generic}`)
+        )
+      );
+    }
+    return installed.get(key);
+  };
+
+  // The first family in the stack the browser is able to render with, or null
+  // for the browser's default font
+  const getUsedFamily = (families, weight, style, text) => {
+    for (const family of families) {
+      if (GENERIC_FAMILIES.includes(family.toLowerCase())) {
+        return family;
+      }
+
+      if (isWebFont(family)) {
+        try {
+          if (document.fonts.check(`${style} ${weight} 16px "${family}"`, text)) {
+            return family;
+          }
+        } catch (_) {}
+      } else if (isInstalled(family)) {
+        return family;
+      }
+    }
+
+    return null;
+  };
+
+  const usage = new Map();
+  const walker = document.createTreeWalker(
+    document.body ?? document.documentElement,
+    NodeFilter.SHOW_TEXT
+  );
+
+  let sampledTextNodes = 0;
+
+  while (sampledTextNodes < MAX_TEXT_NODES && walker.nextNode()) {
+    const text = walker.currentNode.textContent.replace(/\s+/g, "");
+    const element = walker.currentNode.parentElement;
+
+    // Skip whitespace, and text that is not rendered
+    if (!text || !element || SKIPPED_ELEMENTS.includes(element.tagName)) {
+      continue;
+    }
+    if (element.getClientRects().length === 0) {
+      continue;
+    }
+
+    const computedStyle = getComputedStyle(element);
+    if (computedStyle.visibility === "hidden") {
+      continue;
+    }
+
+    sampledTextNodes += 1;
+
+    const families = computedStyle.fontFamily.split(",").map(unquote).filter(Boolean);
+    const requestedFamily = families[0] ?? null;
+    const weight = computedStyle.fontWeight;
+    const style = computedStyle.fontStyle;
+    const family = getUsedFamily(families, weight, style, text);
+
+    const key = JSON.stringify([requestedFamily, family, weight, style]);
+    if (!usage.has(key)) {
+      usage.set(key, {
+        requestedFamily,
+        family,
+        weight,
+        style,
+        elements: new Set(),
+        characters: 0,
+      });
+    }
+
+    const entry = usage.get(key);
+    entry.elements.add(element);
+    entry.characters += [...text].length;
+  }
+
+  return JSON.stringify({
+    status: document.fonts.status,
+    fontFaces,
+    sampledTextNodes,
+    families: [...usage.values()].map((entry) => ({
+      ...entry,
+      elements: entry.elements.size,
+      fellBack:
+        entry.family === null ||
+        entry.requestedFamily === null ||
+        entry.family.toLowerCase() !== entry.requestedFamily.toLowerCase(),
+    })),
+  });
+})()
@@ -0,0 +1,117 @@
+use eyre::{Context, Result};
+use serde::Deserialize;
+
+// Samples the computed font-family, font-weight and font-style of the text on a page
+pub const FONT_USAGE_SCRIPT: &str = include_str!("font_usage.js");
+
+// Which fonts a rendered page uses, as opposed to the ones it declares
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontUsage {
+    // document.fonts.status, "loaded" or "loading"
+    pub status: String,
+    // Every font face in document.fonts, including the ones added with the FontFace api
+    pub font_faces: Vec<DocumentFontFace>,
+    // Text nodes the computed styles were read from
+    pub sampled_text_nodes: usize,
+    pub families: Vec<FamilyUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentFontFace {
+    pub family: String,
+    pub weight: String,
+    pub style: String,
+    // "unloaded", "loading", "loaded" or "error"
+    pub status: String,
+}
+
+// Text rendered with one family, weight and style
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FamilyUsage {
+    // The first family of the computed font-family
+    pub requested_family: Option<String>,
+    // The family the text is rendered with, None for the browser's default font
+    pub family: Option<String>,
+    pub weight: String,
+    pub style: String,
+    pub elements: usize,
+    pub characters: usize,
+    // Whether the requested family could not be used, e.g. when it failed to load
+    pub fell_back: bool,
+}
+
+pub fn parse_font_usage(json: &str) -> Result<FontUsage> {
+    serde_json::from_str(json).wrap_err("Failed to parse font usage")
+}
+
+impl FontUsage {
+    // Families the text is rendered with, and how many characters use each of them
+    pub fn used_families(&self) -> Vec<(&str, usize)> {
+        let mut used_families: Vec<(&str, usize)> = vec![];
+
+        for family_usage in &self.families {
+            let Some(family) = family_usage.family.as_deref() else {
+                continue;
+            };
+
+            match used_families
+                .iter_mut()
+                .find(|(name, _)| name.eq_ignore_ascii_case(family))
+            {
+                Some((_, characters)) => *characters += family_usage.characters,
+                None => used_families.push((family, family_usage.characters)),
+            }
+        }
+
+        used_families.sort_by_key(|(_, characters)| std::cmp::Reverse(*characters));
+        used_families
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_font_usage;
+
+    #[test]
+    fn get_used_families_from_font_usage() {
+        let json = r#"{
+            "status": "loaded",
+            "fontFaces": [
+                {"family": "NRK Sans Variable", "weight": "1 950", "style": "normal", "status": "loaded"},
+                {"family": "Font Awesome", "weight": "900", "style": "normal", "status": "loaded"},
+                {"family": "Unused", "weight": "700", "style": "normal", "status": "unloaded"}
+            ],
+            "sampledTextNodes": 6,
+            "families": [
+                {"requestedFamily": "NRK Sans Variable", "family": "NRK Sans Variable", "weight": "400", "style": "normal", "elements": 3, "characters": 120, "fellBack": false},
+                {"requestedFamily": "NRK Sans Variable", "family": "NRK Sans Variable", "weight": "700", "style": "normal", "elements": 1, "characters": 30, "fellBack": false},
+                {"requestedFamily": "Font Awesome", "family": "Font Awesome", "weight": "900", "style": "normal", "elements": 1, "characters": 1, "fellBack": false},
+                {"requestedFamily": "Missing", "family": "Arial", "weight": "400", "style": "italic", "elements": 1, "characters": 45, "fellBack": true},
+                {"requestedFamily": "Missing", "family": null, "weight": "400", "style": "normal", "elements": 1, "characters": 2, "fellBack": true}
+            ]
+        }"#;
+
+        let font_usage = parse_font_usage(json).expect("is valid font usage");
+
+        assert_eq!(font_usage.font_faces.len(), 3);
+        assert_eq!(
+            font_usage
+                .families
+                .iter()
+                .filter(|family_usage| family_usage.fell_back)
+                .count(),
+            2
+        );
+        assert_eq!(
+            font_usage.used_families(),
+            vec![
+                ("NRK Sans Variable", 150),
+                ("Arial", 45),
+                ("Font Awesome", 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use eyre::{eyre, Context, Result};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+// Limits for the requests to one host. Every host gets its own limits, so
+// sites that share a CDN, like fonts.gstatic.com, also share its limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostLimits {
+    // Bursts of up to a second's worth of requests are let through at once
+    pub requests_per_second: f64,
+    pub max_in_flight: usize,
+}
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        HostLimits {
+            requests_per_second: 2.0,
+            max_in_flight: 4,
+        }
+    }
+}
+
+// Written like 2:4, for 2 requests per second and at most 4 in flight
+impl FromStr for HostLimits {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (requests_per_second, max_in_flight) = value
+            .split_once(':')
+            .ok_or_else(|| eyre!("Expected requests per second and max in flight, like 2:4"))?;
+
+        let requests_per_second = requests_per_second
+            .parse::<f64>()
+            .ok()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .ok_or_else(|| eyre!("Invalid requests per second {}", requests_per_second))?;
+        let max_in_flight = max_in_flight
+            .parse::<usize>()
+            .ok()
+            .filter(|max_in_flight| *max_in_flight > 0)
+            .ok_or_else(|| eyre!("Invalid max in flight {}", max_in_flight))?;
+
+        Ok(HostLimits {
+            requests_per_second,
+            max_in_flight,
+        })
+    }
+}
+
+// A token bucket and a cap on requests in flight for each host, shared by
+// every crawler in a crawl
+#[derive(Debug, Default)]
+pub struct HostLimiter {
+    default_limits: HostLimits,
+    // By host, which also covers its subdomains
+    host_limits: HashMap<String, HostLimits>,
+    // Only hosts that are busy, or whose bucket is not full yet
+    hosts: Mutex<HashMap<String, Arc<HostState>>>,
+}
+
+#[derive(Debug)]
+struct HostState {
+    limits: HostLimits,
+    bucket: Mutex<TokenBucket>,
+    in_flight: Arc<Semaphore>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    // Negative when requests are waiting for a token
+    tokens: f64,
+    last_refill: Instant,
+}
+
+// Held while the request is in flight
+#[derive(Debug)]
+pub struct HostPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl HostLimiter {
+    pub fn new(default_limits: HostLimits) -> Self {
+        HostLimiter {
+            default_limits,
+            ..HostLimiter::default()
+        }
+    }
+
+    pub fn with_host_limits(mut self, host: &str, limits: HostLimits) -> Self {
+        self.host_limits.insert(host.to_ascii_lowercase(), limits);
+        self
+    }
+
+    // Waits for a free slot and a token for the host of the url
+    pub async fn acquire(&self, url: &Url) -> HostPermit {
+        let state = self.get_host_state(url.host_str().unwrap_or_default());
+
+        let permit = state
+            .in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("host semaphore is never closed");
+
+        let wait = state.take_token(url);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+
+        HostPermit { _permit: permit }
+    }
+
+    // Takes a token for the host of the url, without a slot, and returns how long
+    // to wait before sending the request. For the requests a browser makes for a
+    // page, which can't wait for a slot while the page itself holds one.
+    pub fn take_token(&self, url: &Url) -> Duration {
+        self.get_host_state(url.host_str().unwrap_or_default())
+            .take_token(url)
+    }
+
+    fn get_host_state(&self, host: &str) -> Arc<HostState> {
+        let host = host.to_ascii_lowercase();
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if !hosts.contains_key(&host) {
+            // A host that is seen again after being dropped starts out the same way
+            hosts.retain(|_, state| !state.is_idle());
+        }
+
+        hosts
+            .entry(host.to_owned())
+            .or_insert_with(|| {
+                let limits = self.get_limits(&host);
+
+                Arc::new(HostState {
+                    limits,
+                    bucket: Mutex::new(TokenBucket {
+                        tokens: burst_size(&limits),
+                        last_refill: Instant::now(),
+                    }),
+                    in_flight: Arc::new(Semaphore::new(limits.max_in_flight)),
+                })
+            })
+            .clone()
+    }
+
+    // The limits of the most specific configured host, e.g. use.typekit.net before typekit.net
+    fn get_limits(&self, host: &str) -> HostLimits {
+        self.host_limits
+            .iter()
+            .filter(|(limited_host, _)| {
+                host == limited_host.as_str() || host.ends_with(&format!(".{}", limited_host))
+            })
+            .max_by_key(|(limited_host, _)| limited_host.len())
+            .map_or(self.default_limits, |(_, limits)| *limits)
+    }
+}
+
+impl HostState {
+    fn take_token(&self, url: &Url) -> Duration {
+        let wait = self.bucket.lock().unwrap().take_token(&self.limits);
+        if !wait.is_zero() {
+            tracing::info!(
+                "Waiting {:?} for {}",
+                wait,
+                url.host_str().unwrap_or_default()
+            );
+        }
+        wait
+    }
+
+    // No requests in flight or waiting, and a full bucket
+    fn is_idle(self: &Arc<Self>) -> bool {
+        Arc::strong_count(self) == 1
+            && self.in_flight.available_permits() == self.limits.max_in_flight
+            && self.bucket.lock().unwrap().is_full(&self.limits)
+    }
+}
+
+impl TokenBucket {
+    // Takes a token, and returns how long to wait before it is there
+    fn take_token(&mut self, limits: &HostLimits) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * limits.requests_per_second).min(burst_size(limits));
+        self.last_refill = now;
+        self.tokens -= 1.0;
+
+        match self.tokens < 0.0 {
+            true => Duration::from_secs_f64(-self.tokens / limits.requests_per_second),
+            false => Duration::ZERO,
+        }
+    }
+
+    fn is_full(&self, limits: &HostLimits) -> bool {
+        let elapsed = self.last_refill.elapsed().as_secs_f64();
+        self.tokens + elapsed * limits.requests_per_second >= burst_size(limits)
+    }
+}
+
+fn burst_size(limits: &HostLimits) -> f64 {
+    limits.requests_per_second.ceil().max(1.0)
+}
+
+// Written like fonts.gstatic.com=10:8
+pub fn parse_host_limits(value: &str) -> Result<(String, HostLimits)> {
+    let (host, limits) = value
+        .split_once('=')
+        .ok_or_else(|| eyre!("Expected a host and its limits, like fonts.gstatic.com=10:8"))?;
+
+    let limits = limits
+        .parse::<HostLimits>()
+        .wrap_err(format!("Invalid limits for {}", host))?;
+
+    Ok((host.to_owned(), limits))
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+    use tokio::time::{Duration, Instant};
+    use url::Url;
+
+    use super::{parse_host_limits, HostLimiter, HostLimits};
+
+    #[test]
+    fn parse_limits() -> Result<()> {
+        assert_eq!(
+            parse_host_limits("fonts.gstatic.com=0.5:2")?,
+            (
+                "fonts.gstatic.com".to_owned(),
+                HostLimits {
+                    requests_per_second: 0.5,
+                    max_in_flight: 2
+                }
+            )
+        );
+
+        for value in [
+            "fonts.gstatic.com",
+            "a.com=2",
+            "a.com=0:2",
+            "a.com=2:0",
+            "a.com=x:1",
+        ] {
+            assert!(parse_host_limits(value).is_err(), "{}", value);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limit_requests_per_host() -> Result<()> {
+        let limiter = HostLimiter::new(HostLimits {
+            requests_per_second: 2.0,
+            max_in_flight: 8,
+        })
+        .with_host_limits(
+            "typekit.net",
+            HostLimits {
+                requests_per_second: 1.0,
+                max_in_flight: 1,
+            },
+        );
+
+        let start = Instant::now();
+
+        // A burst of two, and then one every half second
+        let url = Url::parse("https://example.com/fonts.css")?;
+        for _ in 0..4 {
+            limiter.acquire(&url).await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // Other hosts have their own limits
+        let start = Instant::now();
+        let url = Url::parse("https://use.typekit.net/abc1def.css")?;
+        let permit = limiter.acquire(&url).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // Only one in flight, so the next waits for the first to finish
+        let waiting = tokio::spawn({
+            let limiter = std::sync::Arc::new(limiter);
+            async move {
+                let start = Instant::now();
+                limiter.acquire(&url).await;
+                start.elapsed()
+            }
+        });
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        drop(permit);
+
+        assert_eq!(waiting.await?, Duration::from_secs(3));
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_idle_hosts() -> Result<()> {
+        let limiter = HostLimiter::new(HostLimits {
+            requests_per_second: 2.0,
+            max_in_flight: 4,
+        });
+
+        let permit = limiter.acquire(&Url::parse("https://a.com")?).await;
+        limiter.acquire(&Url::parse("https://b.com")?).await;
+        limiter.acquire(&Url::parse("https://c.com")?).await;
+        assert_eq!(limiter.hosts.lock().unwrap().len(), 3);
+
+        // a.com still has a request in flight, and b.com has a token to get back
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        limiter.acquire(&Url::parse("https://d.com")?).await;
+        assert_eq!(limiter.hosts.lock().unwrap().len(), 4);
+
+        // Only the request to a.com is still going
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        limiter.acquire(&Url::parse("https://e.com")?).await;
+        let mut hosts: Vec<String> = limiter.hosts.lock().unwrap().keys().cloned().collect();
+        hosts.sort();
+        assert_eq!(hosts, ["a.com", "e.com"]);
+
+        drop(permit);
+
+        // Subresources of a page only take a token
+        let url = Url::parse("https://e.com/font.woff2")?;
+        assert_eq!(limiter.take_token(&url), Duration::ZERO);
+        assert_eq!(limiter.take_token(&url), Duration::from_millis(500));
+
+        Ok(())
+    }
+}
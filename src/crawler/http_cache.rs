@@ -0,0 +1,720 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicUsize, Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use eyre::{eyre, Context, Result};
+use once_cell::sync::OnceCell;
+use reqwest::header::{HeaderMap, AGE, CACHE_CONTROL, DATE, ETAG, EXPIRES, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::download::ResourceKind;
+
+// https://www.rfc-editor.org/rfc/rfc9111
+
+// Responses with only Last-Modified are fresh for a tenth of their age, up to this
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Shared by every crawler in the process, so a font used by many sites is fetched once
+static HTTP_CACHE: OnceCell<Arc<HttpCache>> = OnceCell::new();
+
+// Sets up the cache every crawler uses. Must be called before the first crawler
+// is created, or the crawlers get a cache with the default options.
+pub fn init_http_cache(options: HttpCacheOptions) -> Result<()> {
+    HTTP_CACHE
+        .set(Arc::new(HttpCache::new(options)))
+        .map_err(|_| eyre!("The http cache is already set up"))
+}
+
+pub fn get_http_cache() -> Arc<HttpCache> {
+    HTTP_CACHE
+        .get_or_init(|| Arc::new(HttpCache::new(HttpCacheOptions::default())))
+        .clone()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpCacheOptions {
+    // In bytes. The least recently used responses are dropped to stay below it.
+    pub memory_size: usize,
+    // Responses are kept in files here as well, so they outlive the process
+    pub disk_dir: Option<PathBuf>,
+    // In bytes. The least recently used files are removed when the dir grows past it.
+    pub disk_size: u64,
+}
+
+impl Default for HttpCacheOptions {
+    fn default() -> Self {
+        HttpCacheOptions {
+            memory_size: 256 * 1024 * 1024,
+            disk_dir: None,
+            disk_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+// A stored response, and what is needed to tell whether it can be used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    // After redirects
+    pub final_url: String,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // When it was stored or last revalidated
+    pub stored_at: SystemTime,
+    // How long after stored_at it's used without asking the server
+    pub fresh_for: Duration,
+    #[serde(skip)]
+    pub content: Vec<u8>,
+}
+
+impl CacheEntry {
+    // None when the response must not be stored, or could never be used again
+    pub fn from_response(
+        final_url: String,
+        headers: &HeaderMap,
+        content: Vec<u8>,
+        now: SystemTime,
+    ) -> Option<Self> {
+        let fresh_for = get_freshness(headers, now)?;
+
+        let get_header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        };
+
+        let entry = CacheEntry {
+            final_url,
+            content_type: get_header(reqwest::header::CONTENT_TYPE),
+            etag: get_header(ETAG),
+            last_modified: get_header(LAST_MODIFIED),
+            stored_at: now,
+            fresh_for,
+            content,
+        };
+
+        match entry.fresh_for.is_zero() && !entry.can_revalidate() {
+            true => None,
+            false => Some(entry),
+        }
+    }
+
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        now.duration_since(self.stored_at)
+            .is_ok_and(|age| age < self.fresh_for)
+    }
+
+    pub fn can_revalidate(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    // The same content, with the headers of the 304 Not Modified response
+    pub fn revalidated(&self, headers: &HeaderMap, now: SystemTime) -> Self {
+        let mut entry = self.clone();
+        entry.stored_at = now;
+        entry.fresh_for = get_freshness(headers, now).unwrap_or_default();
+
+        if let Some(etag) = headers.get(ETAG).and_then(|value| value.to_str().ok()) {
+            entry.etag = Some(etag.to_owned());
+        }
+
+        entry
+    }
+}
+
+// How long a response may be used without revalidating it, or None when it must
+// not be stored at all
+pub fn get_freshness(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let directives: Vec<(String, Option<String>)> = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_ascii_lowercase(),
+                Some(value.trim().trim_matches('"').to_owned()),
+            ),
+            None => (directive.trim().to_ascii_lowercase(), None),
+        })
+        .collect();
+
+    let get_directive = |name: &str| {
+        directives
+            .iter()
+            .find(|(directive, _)| directive == name)
+            .map(|(_, value)| value.as_deref())
+    };
+
+    if get_directive("no-store").is_some() {
+        return None;
+    }
+
+    if get_directive("no-cache").is_some() {
+        return Some(Duration::ZERO);
+    }
+
+    let get_date = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok())
+    };
+    let date = get_date(DATE).unwrap_or(now);
+
+    // s-maxage is only for shared caches, and this one is private to the crawl
+    let max_age = get_directive("max-age")
+        .flatten()
+        .and_then(|seconds| seconds.parse::<u64>().ok())
+        .map(Duration::from_secs);
+
+    let lifetime = max_age
+        .or_else(|| {
+            get_date(EXPIRES).map(|expires| expires.duration_since(date).unwrap_or_default())
+        })
+        .or_else(|| {
+            get_date(LAST_MODIFIED).map(|last_modified| {
+                (date.duration_since(last_modified).unwrap_or_default() / 10)
+                    .min(MAX_HEURISTIC_FRESHNESS)
+            })
+        })
+        .unwrap_or_default();
+
+    let age = headers
+        .get(AGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default();
+
+    Some(lifetime.saturating_sub(age))
+}
+
+// How often the cache could be used
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    pub hits: AtomicUsize,
+    pub revalidated: AtomicUsize,
+    pub misses: AtomicUsize,
+}
+
+// Responses by url, kept in memory and optionally on disk
+#[derive(Debug)]
+pub struct HttpCache {
+    memory: Mutex<MemoryTier>,
+    disk: Option<DiskTier>,
+    // Held while a url is fetched, so that tasks asking for the same url at the
+    // same time wait for the first one rather than fetching it again
+    url_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    pub stats: CacheStats,
+}
+
+impl HttpCache {
+    pub fn new(options: HttpCacheOptions) -> Self {
+        HttpCache {
+            memory: Mutex::new(MemoryTier::new(options.memory_size)),
+            disk: options.disk_dir.map(|dir| DiskTier {
+                dir,
+                max_size: options.disk_size,
+                size: tokio::sync::Mutex::new(None),
+            }),
+            url_locks: Mutex::new(HashMap::new()),
+            stats: CacheStats::default(),
+        }
+    }
+
+    // Unlocked when the guard is dropped, also when the fetch fails or is cancelled
+    pub async fn lock_url(&self, url: &str, kind: ResourceKind) -> UrlGuard<'_> {
+        let key = get_key(url, kind);
+        let lock = self
+            .url_locks
+            .lock()
+            .unwrap()
+            .entry(key.to_owned())
+            .or_default()
+            .clone();
+
+        UrlGuard {
+            url_locks: &self.url_locks,
+            key,
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    // From memory, or else from disk
+    pub async fn get(&self, url: &str, kind: ResourceKind) -> Option<Arc<CacheEntry>> {
+        let key = get_key(url, kind);
+
+        if let Some(entry) = self.memory.lock().unwrap().get(&key) {
+            return Some(entry);
+        }
+
+        let entry = Arc::new(self.disk.as_ref()?.get(&key).await?);
+        self.memory.lock().unwrap().put(&key, entry.clone());
+
+        Some(entry)
+    }
+
+    pub async fn put(&self, url: &str, kind: ResourceKind, entry: CacheEntry) -> Arc<CacheEntry> {
+        let key = get_key(url, kind);
+
+        if let Some(disk) = &self.disk {
+            if let Err(err) = disk.put(&key, &entry).await {
+                tracing::warn!(error = ?err, "Failed to store {} on disk. Continuing...", url);
+            }
+        }
+
+        let entry = Arc::new(entry);
+        self.memory.lock().unwrap().put(&key, entry.clone());
+        entry
+    }
+}
+
+// Responses are only used for what they were checked as, so a stylesheet is never
+// handed out as a font without being sniffed, or the other way around
+fn get_key(url: &str, kind: ResourceKind) -> String {
+    let kind = match kind {
+        ResourceKind::Html => "html",
+        ResourceKind::Css => "css",
+        ResourceKind::Font => "font",
+    };
+
+    format!("{} {}", kind, url)
+}
+
+pub struct UrlGuard<'a> {
+    url_locks: &'a Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    key: String,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl Drop for UrlGuard<'_> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        let mut url_locks = self.url_locks.lock().unwrap();
+        // Nobody else is waiting for it
+        if url_locks
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            url_locks.remove(&self.key);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MemoryTier {
+    // With when they were last used
+    entries: HashMap<String, (Arc<CacheEntry>, u64)>,
+    // The keys by when they were last used, least recently first
+    last_used: BTreeMap<u64, String>,
+    size: usize,
+    max_size: usize,
+    clock: u64,
+}
+
+impl MemoryTier {
+    fn new(max_size: usize) -> Self {
+        MemoryTier {
+            entries: HashMap::new(),
+            last_used: BTreeMap::new(),
+            size: 0,
+            max_size,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<CacheEntry>> {
+        self.clock += 1;
+        let (entry, last_used) = self.entries.get_mut(key)?;
+
+        self.last_used.remove(last_used);
+        *last_used = self.clock;
+        self.last_used.insert(self.clock, key.to_owned());
+
+        Some(entry.clone())
+    }
+
+    fn put(&mut self, key: &str, entry: Arc<CacheEntry>) {
+        self.remove(key);
+
+        // It would push out everything else
+        if entry.content.len() > self.max_size {
+            return;
+        }
+
+        while self.size + entry.content.len() > self.max_size {
+            let Some((_, least_recently_used)) = self.last_used.pop_first() else {
+                break;
+            };
+            self.remove(&least_recently_used);
+        }
+
+        self.clock += 1;
+        self.size += entry.content.len();
+        self.entries.insert(key.to_owned(), (entry, self.clock));
+        self.last_used.insert(self.clock, key.to_owned());
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((removed, last_used)) = self.entries.remove(key) {
+            self.size -= removed.content.len();
+            self.last_used.remove(&last_used);
+        }
+    }
+}
+
+// Each response is a .json file with the entry and a .body file with the content,
+// named by the hash of the key. The modified time of the .json file is when the
+// response was last used.
+#[derive(Debug)]
+struct DiskTier {
+    dir: PathBuf,
+    max_size: u64,
+    // Of the files in dir, counted when the first response is stored
+    size: tokio::sync::Mutex<Option<u64>>,
+}
+
+impl DiskTier {
+    async fn get(&self, key: &str) -> Option<CacheEntry> {
+        let path = self.get_path(key);
+
+        let meta = tokio::fs::read(path.with_extension("json")).await.ok()?;
+        touch(path.with_extension("json")).await;
+
+        let mut entry: CacheEntry = match serde_json::from_slice(&meta) {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!(error = ?err, "Failed to read cached {}. Continuing...", key);
+                return None;
+            }
+        };
+        entry.content = tokio::fs::read(path.with_extension("body")).await.ok()?;
+
+        Some(entry)
+    }
+
+    async fn put(&self, key: &str, entry: &CacheEntry) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .wrap_err(format!("Unable to create cache dir {:?}", self.dir))?;
+
+        let path = self.get_path(key);
+        let meta = serde_json::to_vec(entry).wrap_err("Unable to serialize cache entry")?;
+
+        // The entry is written last, so it's never read with a body that isn't there yet
+        write_file(&path.with_extension("body"), &entry.content).await?;
+        write_file(&path.with_extension("json"), &meta).await?;
+
+        // Overwritten files are counted twice, until the dir is pruned
+        let mut size = self.size.lock().await;
+        let new_size = match *size {
+            Some(size) => size + (entry.content.len() + meta.len()) as u64,
+            None => prune_dir(self.dir.clone(), u64::MAX).await?,
+        };
+
+        *size = Some(match new_size > self.max_size {
+            // Down to three quarters, so it isn't pruned again by the next response
+            true => prune_dir(self.dir.clone(), self.max_size / 4 * 3).await?,
+            false => new_size,
+        });
+
+        Ok(())
+    }
+
+    fn get_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}", Sha256::digest(key.as_bytes())))
+    }
+}
+
+// Removes the least recently used responses until the files in dir take up at most
+// max_size bytes, and returns how many they take up
+async fn prune_dir(dir: PathBuf, max_size: u64) -> Result<u64> {
+    tokio::task::spawn_blocking(move || {
+        // The size of the files of each response, and when it was last used
+        let mut responses: HashMap<PathBuf, (u64, SystemTime)> = HashMap::new();
+
+        let files = std::fs::read_dir(&dir).wrap_err(format!("Unable to read {:?}", dir))?;
+        for file in files.filter_map(|file| file.ok()) {
+            let Some(metadata) = file.metadata().ok().filter(|metadata| metadata.is_file()) else {
+                continue;
+            };
+            let path = file.path();
+            let (size, last_used) = responses
+                .entry(path.with_extension(""))
+                .or_insert((0, SystemTime::UNIX_EPOCH));
+
+            *size += metadata.len();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                *last_used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            }
+        }
+
+        let mut size: u64 = responses.values().map(|(size, _)| size).sum();
+        let mut responses: Vec<(PathBuf, (u64, SystemTime))> = responses.into_iter().collect();
+        responses.sort_by_key(|(_, (_, last_used))| *last_used);
+
+        for (path, (response_size, _)) in responses {
+            if size <= max_size {
+                break;
+            }
+
+            // The entry goes first, so the body is never read without it
+            for extension in ["json", "body", "tmp"] {
+                let _ = std::fs::remove_file(path.with_extension(extension));
+            }
+            size -= response_size;
+        }
+
+        Ok(size)
+    })
+    .await
+    .map_err(|err| eyre!(err))?
+}
+
+// Marks a stored response as used
+async fn touch(path: PathBuf) {
+    let touched = tokio::task::spawn_blocking(move || {
+        std::fs::File::options()
+            .append(true)
+            .open(path)?
+            .set_modified(SystemTime::now())
+    })
+    .await;
+
+    if !matches!(touched, Ok(Ok(_))) {
+        tracing::warn!("Failed to mark cached response as used. Continuing...");
+    }
+}
+
+// Written to a temporary file first, so a file is never read half written
+async fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+    let temporary_path = path.with_extension("tmp");
+
+    tokio::fs::write(&temporary_path, content)
+        .await
+        .wrap_err(format!("Unable to write {:?}", temporary_path))?;
+    tokio::fs::rename(&temporary_path, path)
+        .await
+        .wrap_err(format!("Unable to write {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use eyre::Result;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+    use crate::crawler::download::ResourceKind;
+
+    use super::{get_freshness, CacheEntry, HttpCache, HttpCacheOptions};
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    fn entry(final_url: &str, content: Vec<u8>) -> CacheEntry {
+        CacheEntry {
+            final_url: final_url.to_owned(),
+            content_type: Some("font/woff2".to_owned()),
+            etag: Some("\"abc\"".to_owned()),
+            last_modified: None,
+            stored_at: SystemTime::now(),
+            fresh_for: Duration::from_secs(60),
+            content,
+        }
+    }
+
+    #[test]
+    fn get_freshness_from_headers() -> Result<()> {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT")?;
+        let freshness = |headers_list| get_freshness(&headers(headers_list), now);
+
+        assert_eq!(
+            freshness(&[("cache-control", "public, max-age=31536000")]),
+            Some(Duration::from_secs(31536000))
+        );
+        assert_eq!(
+            freshness(&[("cache-control", "max-age=600, s-maxage=60"), ("age", "20")]),
+            Some(Duration::from_secs(580))
+        );
+        assert_eq!(
+            freshness(&[("cache-control", "no-cache"), ("etag", "\"abc\"")]),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(freshness(&[("cache-control", "No-Store")]), None);
+        assert_eq!(
+            freshness(&[
+                ("date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+                ("expires", "Wed, 21 Oct 2015 08:28:00 GMT")
+            ]),
+            Some(Duration::from_secs(3600))
+        );
+        // A tenth of the time since it was last modified
+        assert_eq!(
+            freshness(&[("last-modified", "Wed, 21 Oct 2015 06:28:00 GMT")]),
+            Some(Duration::from_secs(360))
+        );
+        assert_eq!(freshness(&[]), Some(Duration::ZERO));
+
+        // Never fresh and can't be revalidated, so not worth storing
+        assert_eq!(
+            CacheEntry::from_response("https://a.com/a.css".to_owned(), &headers(&[]), vec![], now),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn tell_fresh_entries_apart() {
+        let fresh = entry("https://a.com/font.woff2", vec![]);
+        let now = fresh.stored_at;
+        assert!(fresh.is_fresh(now));
+        assert!(!fresh.is_fresh(now + Duration::from_secs(61)));
+
+        let revalidated = fresh.revalidated(
+            &headers(&[("cache-control", "max-age=120"), ("etag", "\"def\"")]),
+            now + Duration::from_secs(61),
+        );
+        assert!(revalidated.is_fresh(now + Duration::from_secs(61)));
+        assert_eq!(revalidated.etag.as_deref(), Some("\"def\""));
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let cache = HttpCache::new(HttpCacheOptions {
+            memory_size: 10,
+            disk_dir: None,
+            ..HttpCacheOptions::default()
+        });
+
+        cache
+            .put("a", ResourceKind::Font, entry("a", vec![0; 4]))
+            .await;
+        cache
+            .put("b", ResourceKind::Font, entry("b", vec![0; 4]))
+            .await;
+        assert!(cache.get("a", ResourceKind::Font).await.is_some());
+
+        // b was used least recently, so it makes room for c
+        cache
+            .put("c", ResourceKind::Font, entry("c", vec![0; 4]))
+            .await;
+        assert!(cache.get("a", ResourceKind::Font).await.is_some());
+        assert!(cache.get("b", ResourceKind::Font).await.is_none());
+        assert!(cache.get("c", ResourceKind::Font).await.is_some());
+
+        // Too large to keep in memory at all
+        cache
+            .put("d", ResourceKind::Font, entry("d", vec![0; 11]))
+            .await;
+        assert!(cache.get("d", ResourceKind::Font).await.is_none());
+        assert!(cache.get("c", ResourceKind::Font).await.is_some());
+
+        // Only used as what it was stored as
+        assert!(cache.get("c", ResourceKind::Css).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn unlock_urls_when_dropped() {
+        let cache = HttpCache::new(HttpCacheOptions::default());
+        let guard = cache.lock_url("a", ResourceKind::Font).await;
+
+        // Waits for the first, until it's cancelled
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(10),
+            cache.lock_url("a", ResourceKind::Font),
+        )
+        .await;
+        assert!(waiting.is_err());
+
+        drop(guard);
+        assert!(cache.url_locks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keep_entries_on_disk() -> Result<()> {
+        let disk_dir =
+            std::env::temp_dir().join(format!("fonts-http-cache-{}", std::process::id()));
+
+        let cache = HttpCache::new(HttpCacheOptions {
+            memory_size: 10,
+            disk_dir: Some(disk_dir.clone()),
+            ..HttpCacheOptions::default()
+        });
+        let url = "https://fonts.gstatic.com/s/inter/v12/font.woff2";
+        cache
+            .put(url, ResourceKind::Font, entry(url, b"wOF2 data".to_vec()))
+            .await;
+
+        // A new cache, like in the next crawl, reads it from disk
+        let cache = HttpCache::new(HttpCacheOptions {
+            memory_size: 100,
+            disk_dir: Some(disk_dir.clone()),
+            ..HttpCacheOptions::default()
+        });
+        let cached = cache
+            .get(url, ResourceKind::Font)
+            .await
+            .expect("is stored on disk");
+        assert_eq!(cached.content, b"wOF2 data");
+        assert_eq!(cached.etag.as_deref(), Some("\"abc\""));
+
+        std::fs::remove_dir_all(disk_dir)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prune_least_recently_used_from_disk() -> Result<()> {
+        let disk_dir =
+            std::env::temp_dir().join(format!("fonts-http-cache-prune-{}", std::process::id()));
+
+        // Room for two responses, but not three
+        let cache = HttpCache::new(HttpCacheOptions {
+            memory_size: 10,
+            disk_dir: Some(disk_dir.clone()),
+            disk_size: 28_000,
+        });
+
+        cache
+            .put("a", ResourceKind::Font, entry("a", vec![0; 10_000]))
+            .await;
+        cache
+            .put("b", ResourceKind::Font, entry("b", vec![0; 10_000]))
+            .await;
+        assert!(cache.get("a", ResourceKind::Font).await.is_some());
+
+        // b was used least recently, so it makes room for c
+        cache
+            .put("c", ResourceKind::Font, entry("c", vec![0; 10_000]))
+            .await;
+        assert!(cache.get("a", ResourceKind::Font).await.is_some());
+        assert!(cache.get("b", ResourceKind::Font).await.is_none());
+        assert!(cache.get("c", ResourceKind::Font).await.is_some());
+
+        std::fs::remove_dir_all(disk_dir)?;
+
+        Ok(())
+    }
+}
@@ -1,23 +1,94 @@
-use std::time::Duration;
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, SystemTime},
+};
 
 use eyre::{eyre, Context, Result};
 
-use reqwest::{header::ACCEPT, Client};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, ACCEPT, ACCEPT_ENCODING, IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE,
+    },
+    Client, Response, StatusCode,
+};
 use url::Url;
 
 use crate::{
     parsers::{
-        css_parser::parse_css_doc,
-        html_parser::{get_elements_from_page, Element},
-        url_parser::{parse_to_font_urls, parse_to_url, FontUrl},
+        content_type::{decode_css, decode_html, ContentType},
+        css_parser::{parse_css_doc, parse_css_imports},
+        font_face::{FontFace, SourceSelection},
+        font_provider::{detect_provider, ProviderUrl},
+        html_parser::{get_elements_from_page, Element, PageElements},
+        url_parser::{parse_to_font_urls, parse_to_url, to_font_url, FontUrl},
     },
     tasks::Page,
     CustomError,
 };
 
+use super::{
+    download::{
+        get_content_type, read_body, sniff_font, unexpected_content_type, DownloadOptions,
+        ResourceKind, FONT_SIGNATURE_SIZE,
+    },
+    fetch_error::{FetchError, RetryPolicy},
+    host_limiter::{HostLimiter, HostPermit},
+    http_cache::{get_http_cache, CacheEntry, HttpCache},
+    robots_cache::{RobotsCache, ROBOTS_CACHE},
+};
+
+// The product token robots.txt rules are matched against
+pub const USER_AGENT_TOKEN: &str = "fontsbot";
+const USER_AGENT: &str = concat!("fontsbot/", env!("CARGO_PKG_VERSION"));
+
+// A font found on a page, together with the @font-face rule that declared it.
+// Fonts linked directly from the html have no rule.
+#[derive(Debug, Clone)]
+pub struct FontReference {
+    // Urls to try in order, until one of them loads
+    pub urls: Vec<FontUrl>,
+    pub font_face: Option<FontFace>,
+}
+
+// How many levels of @import to follow from a stylesheet on the page
+const MAX_IMPORT_DEPTH: usize = 4;
+
+// What a page says about its fonts
+#[derive(Debug, Clone)]
+pub struct PageFonts {
+    pub font_references: Vec<FontReference>,
+    pub providers: Vec<ProviderUrl>,
+    // Families set in style attributes, which may not be declared anywhere the
+    // crawler can see, like in a stylesheet added by a script
+    pub style_families: Vec<String>,
+    // Stylesheets robots.txt does not let us fetch
+    pub disallowed_urls: Vec<String>,
+    // Stylesheets that could not be fetched, and why
+    pub failed_urls: Vec<(String, FetchError)>,
+}
+
 #[derive(Debug)]
 pub struct HttpCrawler {
     http_client: Client,
+    source_selection: SourceSelection,
+    robots_cache: Arc<RobotsCache>,
+    host_limiter: Arc<HostLimiter>,
+    retry_policy: RetryPolicy,
+    download_options: DownloadOptions,
+    http_cache: Arc<HttpCache>,
+}
+
+// What a request for a url gave
+enum Download {
+    Content {
+        content: Vec<u8>,
+        headers: HeaderMap,
+        final_url: Url,
+    },
+    // To a request with If-None-Match or If-Modified-Since
+    NotModified(HeaderMap),
 }
 
 impl HttpCrawler {
@@ -26,37 +97,68 @@ impl HttpCrawler {
             .timeout(Duration::from_secs(6))
             .gzip(true)
             .brotli(true)
+            .user_agent(USER_AGENT)
             .build()?;
-        Ok(HttpCrawler { http_client })
+        Ok(HttpCrawler {
+            http_client,
+            source_selection: SourceSelection::default(),
+            robots_cache: ROBOTS_CACHE.clone(),
+            host_limiter: Arc::new(HostLimiter::default()),
+            retry_policy: RetryPolicy::default(),
+            download_options: DownloadOptions::default(),
+            http_cache: get_http_cache(),
+        })
+    }
+
+    // Shared with the other crawlers in the crawl, so the limits hold across tasks
+    pub fn with_host_limiter(mut self, host_limiter: Arc<HostLimiter>) -> Self {
+        self.host_limiter = host_limiter;
+        self
+    }
+
+    pub fn with_download_options(mut self, download_options: DownloadOptions) -> Self {
+        self.download_options = download_options;
+        self
+    }
+
+    pub fn with_source_selection(mut self, source_selection: SourceSelection) -> Self {
+        self.source_selection = source_selection;
+        self
     }
 
     #[tracing::instrument(skip(self))]
     pub async fn get_page_content(&self, base_url: &str) -> Result<String> {
-        self.http_client
-            .get(base_url)
-            .header(ACCEPT, "text/html")
-            .send()
-            .await?
-            .text()
-            .await
-            .map_err(|err| eyre!(err))
+        let (content, content_type, _) = self.get_content(base_url, ResourceKind::Html).await?;
+
+        Ok(decode_html(&content, content_type.as_ref()))
     }
 
     #[tracing::instrument(skip(self, page), fields(url=page.base_url))]
-    pub async fn get_font_urls_from_page(&self, page: &Page) -> crate::Result<Vec<Url>> {
-        let elements: Vec<Element> = get_elements_from_page(&page.page_content);
+    pub async fn get_font_urls_from_page(&self, page: &Page) -> crate::Result<PageFonts> {
+        let PageElements {
+            elements,
+            base_href,
+        } = get_elements_from_page(&page.page_content);
 
         if elements.is_empty() {
             return Err(CustomError::NoElementsFound(page.base_url.to_owned()));
         }
 
+        let document_url = get_document_url(&page.base_url, base_href.as_deref())?;
+
         // want to end up with urls that are possible to visit after this map
-        let mut all_font_urls: Vec<Url> = vec![];
+        let mut all_font_references: Vec<FontReference> = vec![];
+        let mut visited_css_urls: HashSet<Url> = HashSet::new();
+        // Scripts and preconnected origins that may belong to a web font service
+        let mut provider_hint_urls: Vec<Url> = vec![];
+        let mut style_families: Vec<String> = vec![];
+        let mut disallowed_urls: Vec<String> = vec![];
+        let mut failed_urls: Vec<(String, FetchError)> = vec![];
 
         for element in elements {
             match element {
                 Element::LinkToCss(url) => {
-                    let css_url = match parse_to_url(&url, &page.base_url) {
+                    let css_url = match parse_to_url(&url, document_url.as_str()) {
                         Ok(parsed_url) => {
                             tracing::info!("Parsed url for css link.");
                             parsed_url
@@ -67,138 +169,983 @@ impl HttpCrawler {
                         }
                     };
 
-                    let css_content = match self.get_content_as_bytes(css_url.as_str()).await {
+                    // The same stylesheet can be linked more than once, or imported by another
+                    if !visited_css_urls.insert(css_url.to_owned()) {
+                        continue;
+                    }
+
+                    // Urls in the stylesheet are relative to where it ended up after redirects
+                    let (css_content, final_css_url) = match self
+                        .get_stylesheet(css_url.as_str())
+                        .await
+                    {
                         Ok(content) => {
                             tracing::info!("Got css content from url");
                             content
                         }
                         Err(err) => {
+                            if is_disallowed(&err) {
+                                disallowed_urls.push(css_url.to_string());
+                            } else if let Some(fetch_error) = get_fetch_error(&err) {
+                                failed_urls.push((css_url.to_string(), fetch_error.to_owned()));
+                            }
                             tracing::error!(error = ?err, "Failed to css content from url. Continuing in loop...");
                             continue;
                         }
                     };
 
-                    let font_urls = match parse_css_doc(css_content) {
-                        Ok(fonts_urls) => {
-                            tracing::info!("Got font urls from css urls.");
-                            fonts_urls
+                    // Redirected to a stylesheet that has been read already
+                    if final_css_url != css_url
+                        && !visited_css_urls.insert(final_css_url.to_owned())
+                    {
+                        continue;
+                    }
+
+                    all_font_references.extend(
+                        self.get_font_references_from_css(
+                            css_content,
+                            &final_css_url,
+                            &mut visited_css_urls,
+                            &mut disallowed_urls,
+                            &mut failed_urls,
+                        )
+                        .await,
+                    );
+                }
+                Element::LinkToFont(url) | Element::PreloadFont(url) => {
+                    let font_url = match parse_to_url(&url, document_url.as_str()) {
+                        Ok(parsed_url) => {
+                            tracing::info!("Parsed url for font link.");
+                            parsed_url
                         }
                         Err(err) => {
-                            tracing::error!(error = ?err, "Failed to get font urls from css url. Continuing in loop...");
+                            tracing::error!(error = ?err, "Failed to parse url. Continuing in loop.");
                             continue;
                         }
                     };
 
-                    let font_urls = match parse_to_font_urls(font_urls, &page.base_url) {
-                        Ok(font_urls) => {
-                            tracing::info!("Parsed to font urls.");
-                            font_urls
-                        }
-                        Err(err) => {
-                            tracing::error!(error = ?err, "Failed to parse to font urls. Continuing in loop.");
-                            continue;
-                        }
+                    let Some(font_url) = to_font_url(font_url) else {
+                        continue;
                     };
 
-                    // TODO: maybe rewrite entire function to output FontUrl
-                    // But for now. only include font urls that are http scheme
-                    let font_urls: Vec<Url> = font_urls
-                        .iter()
-                        .filter_map(|font_url| {
-                            if let FontUrl::Http(url) = font_url {
-                                return Some(url.to_owned());
-                            };
-                            None
-                        })
-                        .collect();
-
-                    all_font_urls.extend(font_urls)
+                    all_font_references.push(FontReference {
+                        urls: vec![font_url],
+                        font_face: None,
+                    });
                 }
-                Element::LinkToFont(url) => {
-                    let font_url = match parse_to_url(&url, &page.base_url) {
-                        Ok(parsed_url) => {
-                            tracing::info!("Parsed url for font link.");
-                            parsed_url
-                        }
+                // Neither point to font files, but they tell which font providers and
+                // families the page uses
+                Element::Preconnect(url) | Element::FontProviderScript(url) => {
+                    match parse_to_url(&url, document_url.as_str()) {
+                        Ok(hint_url) => provider_hint_urls.push(hint_url),
                         Err(err) => {
                             tracing::error!(error = ?err, "Failed to parse url. Continuing in loop.");
-                            continue;
                         }
                     };
-                    all_font_urls.push(font_url);
+                }
+                Element::InlineStyle(families) => {
+                    for family in families {
+                        if !style_families.contains(&family) {
+                            style_families.push(family);
+                        }
+                    }
                 }
                 Element::InlineCss(text_css) => {
-                    let bytes_css = text_css.as_bytes().to_vec();
+                    // Inline css has the same base url as the document
+                    all_font_references.extend(
+                        self.get_font_references_from_css(
+                            text_css,
+                            &document_url,
+                            &mut visited_css_urls,
+                            &mut disallowed_urls,
+                            &mut failed_urls,
+                        )
+                        .await,
+                    );
+                }
+            }
+        }
 
-                    let font_urls = match parse_css_doc(bytes_css) {
-                        Ok(fonts_urls) => {
-                            tracing::info!("Got font urls from css urls.");
-                            fonts_urls
-                        }
-                        Err(err) => {
-                            tracing::error!(error = ?err, "Failed to get font urls from css url. Continuing in loop...");
+        // Every stylesheet, script and font url that belongs to a web font service
+        let font_urls = all_font_references
+            .iter()
+            .flat_map(|font_reference| &font_reference.urls)
+            .filter_map(|font_url| match font_url {
+                FontUrl::Http(url) => Some(url),
+                FontUrl::Data(_) => None,
+            });
+
+        let mut providers: Vec<ProviderUrl> = visited_css_urls
+            .iter()
+            .chain(&provider_hint_urls)
+            .chain(font_urls)
+            .filter_map(detect_provider)
+            .collect();
+        providers.sort_by(|a, b| a.url.cmp(&b.url));
+        providers.dedup_by(|a, b| a.url == b.url);
+
+        if all_font_references.is_empty() && providers.is_empty() && style_families.is_empty() {
+            return Err(CustomError::NoFontUrlsFound(page.base_url.to_owned()));
+        }
+
+        Ok(PageFonts {
+            font_references: all_font_references,
+            providers,
+            style_families,
+            disallowed_urls,
+            failed_urls,
+        })
+    }
+
+    // Fonts in the stylesheet and in the stylesheets it imports, followed up to
+    // MAX_IMPORT_DEPTH levels deep. Imports and fonts are resolved against the url
+    // of the stylesheet they are in, and each stylesheet is only fetched once, so
+    // import cycles end.
+    async fn get_font_references_from_css(
+        &self,
+        css_content: String,
+        css_url: &Url,
+        visited_css_urls: &mut HashSet<Url>,
+        disallowed_urls: &mut Vec<String>,
+        failed_urls: &mut Vec<(String, FetchError)>,
+    ) -> Vec<FontReference> {
+        let mut all_font_references: Vec<FontReference> = vec![];
+        let mut stylesheets: VecDeque<(String, Url, usize)> =
+            VecDeque::from([(css_content, css_url.to_owned(), 0)]);
+
+        while let Some((css_content, css_url, depth)) = stylesheets.pop_front() {
+            let mut imports = parse_css_imports(&css_content);
+
+            if depth >= MAX_IMPORT_DEPTH && !imports.is_empty() {
+                tracing::warn!(
+                    "Reached max import depth for {}. Skipping imports.",
+                    css_url
+                );
+                imports.clear();
+            }
+
+            for import in imports {
+                let import_url = match parse_to_url(&import, css_url.as_str()) {
+                    Ok(import_url) => import_url,
+                    Err(err) => {
+                        tracing::error!(error = ?err, "Failed to parse import url. Continuing in loop...");
+                        continue;
+                    }
+                };
+
+                if !visited_css_urls.insert(import_url.to_owned()) {
+                    continue;
+                }
+
+                match self.get_stylesheet(import_url.as_str()).await {
+                    Ok((content, final_import_url)) => {
+                        tracing::info!("Got css content from import {}", final_import_url);
+
+                        // Redirected to a stylesheet that has been read already
+                        if final_import_url != import_url
+                            && !visited_css_urls.insert(final_import_url.to_owned())
+                        {
                             continue;
                         }
-                    };
 
-                    let font_urls = match parse_to_font_urls(font_urls, &page.base_url) {
-                        Ok(font_urls) => {
-                            tracing::info!("Parsed to font urls.");
-                            font_urls
-                        }
-                        Err(err) => {
-                            tracing::error!(error = ?err, "Failed to parse to font urls. Continuing in loop.");
-                            continue;
+                        stylesheets.push_back((content, final_import_url, depth + 1));
+                    }
+                    Err(err) => {
+                        if is_disallowed(&err) {
+                            disallowed_urls.push(import_url.to_string());
+                        } else if let Some(fetch_error) = get_fetch_error(&err) {
+                            failed_urls.push((import_url.to_string(), fetch_error.to_owned()));
                         }
-                    };
+                        tracing::error!(error = ?err, "Failed to get css content from import. Continuing in loop...");
+                    }
+                };
+            }
 
-                    // TODO: maybe rewrite entire function to output FontUrl
-                    // But for now. only include font urls that are http scheme
-                    let font_urls: Vec<Url> = font_urls
-                        .iter()
-                        .filter_map(|font_url| {
-                            if let FontUrl::Http(url) = font_url {
-                                return Some(url.to_owned());
-                            };
-                            None
-                        })
-                        .collect();
-
-                    all_font_urls.extend(font_urls)
+            match parse_css_doc(&css_content) {
+                Ok(font_faces) => {
+                    tracing::info!("Got font faces from css {}.", css_url);
+                    all_font_references.extend(to_font_references(
+                        font_faces,
+                        css_url.as_str(),
+                        self.source_selection,
+                    ));
+                }
+                Err(err) => {
+                    tracing::error!(error = ?err, "Failed to get font faces from css {}. Continuing in loop...", css_url);
                 }
+            };
+        }
+
+        all_font_references
+    }
+
+    // Fails with FetchError::UnexpectedContentType unless it starts like a font file
+    pub async fn get_font_content(&self, url: &str) -> eyre::Result<Vec<u8>> {
+        let (content, _, _) = self.get_content(url, ResourceKind::Font).await?;
+        Ok(content)
+    }
+
+    // Fetches only the first bytes of the font with a range request. Servers that
+    // ignore the range send all of it, but only the first bytes are read.
+    pub async fn sniff_font(&self, url: &str) -> eyre::Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static(ResourceKind::Font.accept()),
+        );
+        headers.insert(
+            RANGE,
+            HeaderValue::from_str(&format!("bytes=0-{}", FONT_SIGNATURE_SIZE - 1))?,
+        );
+        // A range of a compressed body can't be decompressed
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+
+        let (res, _permit) = self.fetch(url, headers).await?;
+
+        sniff_font(res)
+            .await
+            .wrap_err(format!("Content of {} is not a font", url))
+    }
+
+    // The decoded stylesheet and the url it was served from, after redirects
+    pub async fn get_stylesheet(&self, url: &str) -> eyre::Result<(String, Url)> {
+        let (content, content_type, final_url) = self.get_content(url, ResourceKind::Css).await?;
+
+        Ok((decode_css(&content, content_type.as_ref()), final_url))
+    }
+
+    // The content, its Content-Type and the url it was served from, after redirects.
+    // Stylesheets and fonts come from the http cache when it has them, and only one
+    // task at a time fetches the same url, so the others get it from the cache.
+    async fn get_content(
+        &self,
+        url: &str,
+        kind: ResourceKind,
+    ) -> eyre::Result<(Vec<u8>, Option<ContentType>, Url)> {
+        if !kind.is_cacheable() {
+            return match self.download(url, kind, HeaderMap::new()).await? {
+                Download::Content {
+                    content,
+                    headers,
+                    final_url,
+                } => Ok((content, get_content_type(&headers), final_url)),
+                Download::NotModified(_) => Err(eyre!("Not modified without asking for {}", url)),
+            };
+        }
+
+        let _guard = self.http_cache.lock_url(url, kind).await;
+        self.get_cached_content(url, kind).await
+    }
+
+    // A fresh response is used as it is, and a stale one is revalidated with its
+    // ETag or Last-Modified
+    async fn get_cached_content(
+        &self,
+        url: &str,
+        kind: ResourceKind,
+    ) -> eyre::Result<(Vec<u8>, Option<ContentType>, Url)> {
+        let now = SystemTime::now();
+        let cached = self.http_cache.get(url, kind).await;
+
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.is_fresh(now)) {
+            self.http_cache.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return from_cache_entry(cached);
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(cached) = &cached {
+            let to_header_value = |value: &Option<String>| {
+                value
+                    .as_deref()
+                    .and_then(|value| HeaderValue::from_str(value).ok())
+            };
+
+            if let Some(etag) = to_header_value(&cached.etag) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = to_header_value(&cached.last_modified) {
+                headers.insert(IF_MODIFIED_SINCE, last_modified);
             }
+        } else if kind == ResourceKind::Font && self.download_options.sniff_fonts {
+            self.sniff_font(url).await?;
         }
 
-        if all_font_urls.is_empty() {
-            return Err(CustomError::NoFontUrlsFound(page.base_url.to_owned()));
+        match (self.download(url, kind, headers).await?, cached) {
+            (Download::NotModified(headers), Some(cached)) => {
+                self.http_cache
+                    .stats
+                    .revalidated
+                    .fetch_add(1, Ordering::Relaxed);
+
+                let entry = self
+                    .http_cache
+                    .put(url, kind, cached.revalidated(&headers, now))
+                    .await;
+                from_cache_entry(&entry)
+            }
+            (Download::NotModified(_), None) => {
+                Err(eyre!("Not modified without asking for {}", url))
+            }
+            (
+                Download::Content {
+                    content,
+                    headers,
+                    final_url,
+                },
+                _,
+            ) => {
+                self.http_cache.stats.misses.fetch_add(1, Ordering::Relaxed);
+
+                if let Some(entry) =
+                    CacheEntry::from_response(final_url.to_string(), &headers, content.clone(), now)
+                {
+                    self.http_cache.put(url, kind, entry).await;
+                }
+
+                Ok((content, get_content_type(&headers), final_url))
+            }
+        }
+    }
+
+    // Responses with a Content-Type that is not accepted are not read, and a
+    // response without one is always accepted. The content is streamed, and fails
+    // with FetchError::TooLarge as soon as it's larger than the limit for its kind.
+    async fn download(
+        &self,
+        url: &str,
+        kind: ResourceKind,
+        mut headers: HeaderMap,
+    ) -> eyre::Result<Download> {
+        headers.insert(ACCEPT, HeaderValue::from_static(kind.accept()));
+
+        let (res, _permit) = self.fetch(url, headers).await?;
+
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Download::NotModified(res.headers().to_owned()));
+        }
+
+        let content_type = get_content_type(res.headers());
+
+        if !content_type
+            .as_ref()
+            .is_none_or(|content_type| kind.is_accepted(content_type))
+        {
+            return Err(unexpected_content_type(content_type.as_ref()))
+                .wrap_err(format!("Unexpected content type for {}", url));
         }
 
-        Ok(all_font_urls)
+        let headers = res.headers().to_owned();
+        let final_url = res.url().to_owned();
+
+        let content = read_body(res, kind, self.download_options.max_size(kind))
+            .await
+            .wrap_err(format!("Could not get content of {}", url))?;
+
+        Ok(Download::Content {
+            content,
+            headers,
+            final_url,
+        })
+    }
+
+    // A successful response, and the permit to hold while its body is read
+    async fn fetch(&self, url: &str, headers: HeaderMap) -> eyre::Result<(Response, HostPermit)> {
+        self.retry(url, || async {
+            let permit = self.wait_for_turn(url).await?;
+
+            let res = self
+                .send(url, headers.clone())
+                .await
+                .wrap_err(format!("Unable to fetch {}", url))?;
+
+            Ok((res, permit))
+        })
+        .await
     }
 
-    pub async fn get_content_as_bytes(&self, url: &str) -> eyre::Result<Vec<u8>> {
+    // Transient errors are retried with backoff, and other errors fail with the
+    // FetchError they were classified as, if any
+    async fn retry<T, F, Fut>(&self, url: &str, try_once: F) -> eyre::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = eyre::Result<T>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let err = match try_once().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            let Some(delay) = get_fetch_error(&err)
+                .and_then(|fetch_error| self.retry_policy.get_delay(fetch_error, attempt))
+            else {
+                return Err(err);
+            };
+
+            tracing::warn!(error = ?err, "Failed to fetch {}. Retrying in {:?}...", url, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send(&self, url: &str, headers: HeaderMap) -> Result<Response, FetchError> {
         let res = self
             .http_client
             .get(url)
+            .headers(headers)
             .send()
             .await
-            .wrap_err("Unable to send response")?;
+            .map_err(|err| FetchError::from_reqwest(&err))?;
 
-        if !res.status().is_success() {
-            return Err(eyre!(
-                "Not able to download content {}. Returned status: {}",
-                url,
-                res.status()
-            ));
+        match FetchError::from_status(res.status(), res.headers()) {
+            Some(err) => Err(err),
+            None => Ok(res),
         }
+    }
+
+    // Fails with CustomError::Disallowed when robots.txt does not let us fetch the
+    // url, and otherwise waits for the crawl delay of its host
+    pub async fn check_robots(&self, url: &str) -> eyre::Result<()> {
+        self.retry(url, || self.check_robots_once(url)).await
+    }
+
+    async fn check_robots_once(&self, url: &str) -> eyre::Result<()> {
+        let url = Url::parse(url).wrap_err(format!("Unable to parse url {}", url))?;
 
-        let content: Vec<u8> = res
-            .bytes()
+        // When robots.txt could not be read, every url of the origin fails with the same error
+        let robots = self
+            .robots_cache
+            .get_robots(&self.http_client, &url, USER_AGENT_TOKEN)
             .await
-            .wrap_err("Could not get content as bytes")?
+            .wrap_err(format!("Unable to get robots.txt for {}", url))?;
+
+        if !robots.is_allowed(&url) {
+            return Err(CustomError::Disallowed(url.to_string()).into());
+        }
+
+        self.robots_cache
+            .wait_for_crawl_delay(&url, robots.crawl_delay.unwrap_or_default())
+            .await;
+
+        Ok(())
+    }
+
+    // Checks robots.txt, and waits until the host may get another request.
+    // The permit is held until the response has been read.
+    async fn wait_for_turn(&self, url: &str) -> eyre::Result<HostPermit> {
+        self.check_robots_once(url).await?;
+
+        let url = Url::parse(url).wrap_err(format!("Unable to parse url {}", url))?;
+        Ok(self.host_limiter.acquire(&url).await)
+    }
+}
+
+fn from_cache_entry(entry: &CacheEntry) -> eyre::Result<(Vec<u8>, Option<ContentType>, Url)> {
+    let final_url = Url::parse(&entry.final_url)
+        .wrap_err(format!("Unable to parse cached url {}", entry.final_url))?;
+
+    Ok((
+        entry.content.to_owned(),
+        entry.content_type.as_deref().map(ContentType::parse),
+        final_url,
+    ))
+}
+
+pub fn is_disallowed(err: &eyre::Report) -> bool {
+    matches!(
+        err.downcast_ref::<CustomError>(),
+        Some(CustomError::Disallowed(_))
+    )
+}
+
+// Why the url could not be fetched, when it got that far
+pub fn get_fetch_error(err: &eyre::Report) -> Option<&FetchError> {
+    err.downcast_ref::<FetchError>()
+}
+
+// The url relative urls in the html are resolved against. It's the page url,
+// unless the page sets another one with <base href>.
+fn get_document_url(page_url: &str, base_href: Option<&str>) -> eyre::Result<Url> {
+    let page_url =
+        Url::parse(page_url).wrap_err(format!("Unable to parse page url {}", page_url))?;
+
+    let Some(base_href) = base_href else {
+        return Ok(page_url);
+    };
+
+    match page_url.join(base_href) {
+        Ok(base_url) => Ok(base_url),
+        Err(err) => {
+            tracing::error!(error = ?err, "Failed to parse base href {}. Using page url.", base_href);
+            Ok(page_url)
+        }
+    }
+}
+
+// The urls in the src descriptors that are possible to visit, each paired with its rule.
+// With SourceSelection::Browser there is one reference per rule, holding the supported
+// urls to fall back on, and with SourceSelection::All there is one per url.
+fn to_font_references(
+    font_faces: Vec<FontFace>,
+    base_url: &str,
+    source_selection: SourceSelection,
+) -> Vec<FontReference> {
+    let mut font_references: Vec<FontReference> = vec![];
+
+    for font_face in font_faces {
+        let urls = match source_selection {
+            SourceSelection::Browser => font_face.supported_urls(),
+            SourceSelection::All => font_face.urls(),
+        };
+
+        let font_urls = match parse_to_font_urls(urls, base_url) {
+            Ok(font_urls) => {
+                tracing::info!("Parsed to font urls.");
+                font_urls
+            }
+            Err(err) => {
+                tracing::error!(error = ?err, "Failed to parse to font urls. Continuing in loop.");
+                continue;
+            }
+        };
+
+        if font_urls.is_empty() {
+            tracing::info!("No supported sources for font face {:?}", font_face.family);
+            continue;
+        }
+
+        match source_selection {
+            SourceSelection::Browser => font_references.push(FontReference {
+                urls: font_urls,
+                font_face: Some(font_face),
+            }),
+            SourceSelection::All => {
+                font_references.extend(font_urls.into_iter().map(|url| FontReference {
+                    urls: vec![url],
+                    font_face: Some(font_face.clone()),
+                }))
+            }
+        }
+    }
+
+    font_references
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    use eyre::{eyre, Context, Result};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::{
+        crawler::{
+            fetch_error::FetchError,
+            host_limiter::{HostLimiter, HostLimits},
+        },
+        parsers::{
+            css_parser::parse_css_doc, font_face::SourceSelection, font_provider::FontProvider,
+            url_parser::FontUrl,
+        },
+        tasks::Page,
+        CustomError,
+    };
+
+    use super::{
+        get_document_url, get_fetch_error, is_disallowed, to_font_references, HttpCrawler,
+    };
+
+    // Serves a font that is always stale and one that is fresh for an hour, and
+    // records the requests it gets, with whether they were conditional
+    async fn start_font_server(requests: Arc<Mutex<Vec<(String, bool)>>>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let size = stream.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..size]).to_lowercase();
+
+                let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+                let is_conditional = request.contains("if-none-match: \"v1\"");
+                requests
+                    .lock()
+                    .expect("lock is not poisoned")
+                    .push((path.clone(), is_conditional));
+
+                let response = match (path.as_str(), is_conditional) {
+                    ("/stale.woff2", true) => {
+                        "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\ncache-control: no-cache\r\n\r\n"
+                    }
+                    ("/stale.woff2", false) => {
+                        "HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncache-control: no-cache\r\ncontent-length: 8\r\n\r\nwOF2font"
+                    }
+                    ("/fresh.woff2", _) => {
+                        "HTTP/1.1 200 OK\r\ncache-control: max-age=3600\r\ncontent-length: 8\r\n\r\nwOF2font"
+                    }
+                    _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n",
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        Ok(format!("http://{}", address))
+    }
+
+    // Fails with a 503 the first time, and then serves a robots.txt that disallows everything
+    async fn start_robots_server(requests: Arc<Mutex<Vec<String>>>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let size = stream.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..size]).to_lowercase();
+
+                let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+                let count = {
+                    let mut requests = requests.lock().expect("lock is not poisoned");
+                    requests.push(path);
+                    requests.len()
+                };
+
+                let response = match count {
+                    1 => "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    _ => "HTTP/1.1 200 OK\r\ncontent-length: 25\r\nconnection: close\r\n\r\nUser-agent: *\nDisallow: /",
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        Ok(format!("http://{}", address))
+    }
+
+    // Serves stylesheets that import each other: a.css and b.css import each other,
+    // c.css imports a.css and redirect.css, which redirects to a.css, and d0.css to
+    // d6.css import the next one in line. Every stylesheet declares one font, and
+    // the requests are recorded.
+    async fn start_css_server(requests: Arc<Mutex<Vec<String>>>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let size = stream.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..size]).to_lowercase();
+
+                let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+                requests
+                    .lock()
+                    .expect("lock is not poisoned")
+                    .push(path.clone());
+
+                let name = path.trim_start_matches('/').trim_end_matches(".css");
+                let imports = match name {
+                    "a" => vec!["b".to_owned()],
+                    "b" => vec!["a".to_owned()],
+                    "c" => vec!["a".to_owned(), "redirect".to_owned()],
+                    "d0" | "d1" | "d2" | "d3" | "d4" | "d5" => {
+                        let number: usize = name[1..].parse().unwrap_or_default();
+                        vec![format!("d{}", number + 1)]
+                    }
+                    _ => vec![],
+                };
+
+                let response = match name {
+                    "redirect" => "HTTP/1.1 301 Moved Permanently\r\nlocation: /a.css\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_owned(),
+                    "a" | "b" | "c" | "d0" | "d1" | "d2" | "d3" | "d4" | "d5" | "d6" => {
+                        let body = format!(
+                            "{}@font-face {{ font-family: {}; src: url({}.woff2); }}",
+                            imports
+                                .iter()
+                                .map(|import| format!("@import \"{}.css\";\n", import))
+                                .collect::<String>(),
+                            name,
+                            name
+                        );
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: text/css\r\ncache-control: no-store\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                    _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_owned(),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        Ok(format!("http://{}", address))
+    }
+
+    // Crawls a page that links to the stylesheet, and returns the font families
+    // found and the stylesheets requested
+    async fn crawl_stylesheet(stylesheet: &str) -> Result<(Vec<String>, Vec<String>)> {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let base_url = start_css_server(requests.clone()).await?;
+
+        let crawler =
+            HttpCrawler::new()?.with_host_limiter(Arc::new(HostLimiter::new(HostLimits {
+                requests_per_second: 100.0,
+                max_in_flight: 4,
+            })));
+        let page = Page::new(
+            format!("{}/", base_url),
+            format!(
+                r#"<html><head><link rel="stylesheet" href="{}"></head></html>"#,
+                stylesheet
+            ),
+        );
+
+        let families = crawler
+            .get_font_urls_from_page(&page)
+            .await?
+            .font_references
             .into_iter()
+            .filter_map(|font_reference| font_reference.font_face?.family)
             .collect();
 
-        Ok(content)
+        let requests = requests
+            .lock()
+            .expect("lock is not poisoned")
+            .iter()
+            .filter(|path| *path != "/robots.txt")
+            .cloned()
+            .collect();
+
+        Ok((families, requests))
+    }
+
+    #[tokio::test]
+    async fn fetch_import_cycles_once() -> Result<()> {
+        let (families, requests) = crawl_stylesheet("a.css").await?;
+
+        assert_eq!(families, vec!["a", "b"]);
+        assert_eq!(requests, vec!["/a.css", "/b.css"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_imports_redirected_to_visited_stylesheets() -> Result<()> {
+        let (families, requests) = crawl_stylesheet("c.css").await?;
+
+        // a.css is fetched once more through the redirect, but only read once
+        assert_eq!(families, vec!["c", "a", "b"]);
+        assert_eq!(
+            requests,
+            vec!["/c.css", "/a.css", "/redirect.css", "/a.css", "/b.css"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stop_following_imports_at_max_depth() -> Result<()> {
+        let (families, requests) = crawl_stylesheet("d0.css").await?;
+
+        assert_eq!(families, vec!["d0", "d1", "d2", "d3", "d4"]);
+        assert_eq!(
+            requests,
+            vec!["/d0.css", "/d1.css", "/d2.css", "/d3.css", "/d4.css"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_font_urls_against_stylesheet() -> Result<()> {
+        let css_file =
+            fs::read_to_string("test_files/test_mindjek.css").expect("Could not load css file");
+        let font_faces = parse_css_doc(&css_file)?;
+
+        let urls: Vec<String> = to_font_references(
+            font_faces,
+            "https://mindjek.com/assets/css/style.css?v=2",
+            SourceSelection::All,
+        )
+        .into_iter()
+        .flat_map(|font_reference| font_reference.urls)
+        .filter_map(|font_url| match font_url {
+            FontUrl::Http(url) => Some(url.to_string()),
+            FontUrl::Data(_) => None,
+        })
+        .collect();
+
+        let expected_results = vec![
+            "https://mindjek.com/assets/css/fonts/fontawesome-webfont.eot?v=4.4.0",
+            "https://mindjek.com/assets/css/fonts/fontawesome-webfont.eot?#iefix&v=4.4.0",
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.woff2?v=4.4.0",
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.woff?v=4.4.0",
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.ttf?v=4.4.0",
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.svg?v=4.4.0#fontawesomeregular",
+        ];
+
+        assert_eq!(urls, expected_results);
+
+        Ok(())
+    }
+
+    #[test]
+    fn select_sources_like_a_browser() -> Result<()> {
+        let css_file =
+            fs::read_to_string("test_files/test_mindjek.css").expect("Could not load css file");
+        let font_faces = parse_css_doc(&css_file)?;
+
+        let font_references = to_font_references(
+            font_faces,
+            "https://mindjek.com/assets/css/style.css",
+            SourceSelection::Browser,
+        );
+
+        // One font face, with the eot and svg sources left out
+        assert_eq!(font_references.len(), 1);
+
+        let urls: Vec<String> = font_references[0]
+            .urls
+            .iter()
+            .map(|font_url| match font_url {
+                FontUrl::Http(url) | FontUrl::Data(url) => url.to_string(),
+            })
+            .collect();
+
+        let expected_results = vec![
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.woff2?v=4.4.0",
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.woff?v=4.4.0",
+            "https://mindjek.com/assets/fonts/fontawesome-webfont.ttf?v=4.4.0",
+        ];
+
+        assert_eq!(urls, expected_results);
+
+        Ok(())
+    }
+
+    #[test]
+    fn use_base_href_as_document_url() -> Result<()> {
+        let page_url = "https://example.com/blog/post.html";
+
+        assert_eq!(
+            get_document_url(page_url, Some("/static/"))?.as_str(),
+            "https://example.com/static/"
+        );
+        assert_eq!(get_document_url(page_url, None)?.as_str(), page_url);
+
+        // An href that can't be resolved falls back on the page url
+        assert_eq!(
+            get_document_url(page_url, Some("http://[::1"))?.as_str(),
+            page_url
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn find_providers_and_families_without_stylesheets() -> Result<()> {
+        let page = Page::new(
+            "https://example.com/".to_owned(),
+            r#"<html><head><link rel="preconnect" href="https://fonts.gstatic.com"></head>
+            <body><p style="font: bold 16px/1.5 'Inter', sans-serif">Text</p></body></html>"#
+                .to_owned(),
+        );
+
+        let page_fonts = HttpCrawler::new()?.get_font_urls_from_page(&page).await?;
+
+        assert!(page_fonts.font_references.is_empty());
+        assert_eq!(page_fonts.providers.len(), 1);
+        assert_eq!(page_fonts.providers[0].provider, FontProvider::GoogleFonts);
+        assert_eq!(page_fonts.style_families, vec!["Inter"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tell_disallowed_errors_apart() {
+        let err: eyre::Report =
+            CustomError::Disallowed("https://example.com/private/font.woff2".to_owned()).into();
+        let err = Err::<(), _>(err)
+            .wrap_err("Failed to get font content")
+            .unwrap_err();
+
+        assert!(is_disallowed(&err));
+        assert!(!is_disallowed(&eyre!("Returned status: 404")));
+        assert_eq!(get_fetch_error(&err), None);
+
+        let err = Err::<(), _>(FetchError::Dns)
+            .wrap_err("Unable to fetch https://example.invalid/")
+            .wrap_err("Unable to get page content")
+            .unwrap_err();
+
+        assert!(!is_disallowed(&err));
+        assert_eq!(get_fetch_error(&err), Some(&FetchError::Dns));
+    }
+
+    #[tokio::test]
+    async fn retry_robots_txt() -> Result<()> {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let base_url = start_robots_server(requests.clone()).await?;
+
+        let err = HttpCrawler::new()?
+            .check_robots(&format!("{}/fonts.css", base_url))
+            .await
+            .expect_err("robots.txt disallows everything");
+
+        assert!(is_disallowed(&err));
+        assert_eq!(
+            *requests.lock().expect("lock is not poisoned"),
+            vec!["/robots.txt"; 2]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_shared_fonts_once() -> Result<()> {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let base_url = start_font_server(requests.clone()).await?;
+
+        let host_limiter = Arc::new(HostLimiter::new(HostLimits {
+            requests_per_second: 100.0,
+            max_in_flight: 4,
+        }));
+
+        // Crawlers share the cache
+        for _ in 0..2 {
+            let crawler = HttpCrawler::new()?.with_host_limiter(host_limiter.clone());
+            for path in ["/fresh.woff2", "/stale.woff2"] {
+                let content = crawler
+                    .get_font_content(&format!("{}{}", base_url, path))
+                    .await?;
+                assert_eq!(content, b"wOF2font");
+            }
+        }
+
+        let requests: Vec<(String, bool)> = requests
+            .lock()
+            .expect("lock is not poisoned")
+            .iter()
+            .filter(|(path, _)| path != "/robots.txt")
+            .cloned()
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                ("/fresh.woff2".to_owned(), false),
+                ("/stale.woff2".to_owned(), false),
+                ("/stale.woff2".to_owned(), true),
+            ]
+        );
+
+        Ok(())
     }
 }
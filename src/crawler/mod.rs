@@ -1,2 +1,8 @@
 pub mod browser_crawler;
+pub mod download;
+pub mod fetch_error;
+pub mod font_usage;
+pub mod host_limiter;
+pub mod http_cache;
 pub mod http_crawler;
+pub mod robots_cache;
@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use reqwest::{Client, Response, StatusCode};
+use tokio::time::Instant;
+use url::Url;
+
+use crate::parsers::robots_parser::RobotsTxt;
+
+use super::fetch_error::FetchError;
+
+// robots.txt files larger than this are cut off, like RFC 9309 allows
+const MAX_ROBOTS_TXT_SIZE: usize = 500 * 1024;
+
+// Shared by every crawler, so the crawl delay of a host holds across tasks
+pub static ROBOTS_CACHE: Lazy<Arc<RobotsCache>> = Lazy::new(|| Arc::new(RobotsCache::default()));
+
+// robots.txt of each origin, and when the next request to it may be sent
+#[derive(Debug, Default)]
+pub struct RobotsCache {
+    robots: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<CachedRobots>>>>>,
+    next_requests: Mutex<HashMap<String, Instant>>,
+}
+
+#[derive(Debug)]
+struct CachedRobots {
+    robots: RobotsResult,
+    fetched_at: Instant,
+}
+
+impl RobotsCache {
+    // Fetched once per origin, by the first request, while the others wait for it.
+    // A transient error is only shared with the requests that waited, and the
+    // next request tries again.
+    pub async fn get_robots(
+        &self,
+        http_client: &Client,
+        url: &Url,
+        user_agent: &str,
+    ) -> RobotsResult {
+        let requested_at = Instant::now();
+        let origin = url.origin().ascii_serialization();
+
+        let entry = self
+            .robots
+            .lock()
+            .unwrap()
+            .entry(origin)
+            .or_default()
+            .clone();
+
+        let mut cached = entry.lock().await;
+
+        if let Some(cached) = cached.as_ref().filter(|cached| {
+            cached.fetched_at >= requested_at
+                || cached
+                    .robots
+                    .as_ref()
+                    .err()
+                    .is_none_or(|err| !err.is_transient())
+        }) {
+            return cached.robots.clone();
+        }
+
+        let robots = fetch_robots(http_client, url, user_agent)
+            .await
+            .map(Arc::new);
+
+        *cached = Some(CachedRobots {
+            robots: robots.clone(),
+            fetched_at: Instant::now(),
+        });
+
+        robots
+    }
+
+    // Waits until the crawl delay since the last request to the origin has passed
+    pub async fn wait_for_crawl_delay(&self, url: &Url, crawl_delay: Duration) {
+        let origin = url.origin().ascii_serialization();
+
+        let request_at = {
+            let mut next_requests = self.next_requests.lock().unwrap();
+            let now = Instant::now();
+            let next_request = next_requests.entry(origin).or_insert(now);
+
+            let request_at = (*next_request).max(now);
+            *next_request = request_at + crawl_delay;
+            request_at
+        };
+
+        tokio::time::sleep_until(request_at).await;
+    }
+}
+
+// The robots.txt of an origin, or why it could not be read
+pub type RobotsResult = Result<Arc<RobotsTxt>, FetchError>;
+
+// A missing robots.txt allows everything. One that can't be read, because the
+// server fails, is rate limiting us or can't be reached, disallows everything, and
+// the error is kept so that the site is reported as down rather than as disallowed.
+async fn fetch_robots(
+    http_client: &Client,
+    url: &Url,
+    user_agent: &str,
+) -> Result<RobotsTxt, FetchError> {
+    let Ok(robots_url) = url.join("/robots.txt") else {
+        return Ok(RobotsTxt::disallow_all());
+    };
+
+    let res = match http_client.get(robots_url.as_str()).send().await {
+        Ok(res) => res,
+        Err(err) => {
+            tracing::error!(error = ?err, "Failed to get {}. Disallowing all.", robots_url);
+            return Err(FetchError::from_reqwest(&err));
+        }
+    };
+
+    // Says nothing about whether robots.txt exists, so it's tried again later
+    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+        tracing::error!("Too many requests for {}. Disallowing all.", robots_url);
+        return Err(FetchError::from_status(res.status(), res.headers())
+            .unwrap_or(FetchError::TooManyRequests(None)));
+    }
+
+    if res.status().is_client_error() {
+        tracing::info!("No robots.txt at {}. Allowing all.", robots_url);
+        return Ok(RobotsTxt::allow_all());
+    }
+
+    if let Some(err) = FetchError::from_status(res.status(), res.headers()) {
+        tracing::error!(
+            "Failed to get {}. Returned status: {}. Disallowing all.",
+            robots_url,
+            res.status()
+        );
+        return Err(err);
+    }
+
+    match read_robots_txt(res).await {
+        Ok(content) => Ok(RobotsTxt::parse(
+            &String::from_utf8_lossy(&content),
+            user_agent,
+        )),
+        Err(err) => {
+            tracing::error!(error = ?err, "Failed to read {}. Disallowing all.", robots_url);
+            Err(FetchError::from_reqwest(&err))
+        }
+    }
+}
+
+// Reads at most MAX_ROBOTS_TXT_SIZE bytes and drops the rest of the body
+async fn read_robots_txt(mut res: Response) -> Result<Vec<u8>, reqwest::Error> {
+    let mut content: Vec<u8> = vec![];
+
+    while let Some(chunk) = res.chunk().await? {
+        let remaining = MAX_ROBOTS_TXT_SIZE - content.len();
+        content.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+
+        if content.len() == MAX_ROBOTS_TXT_SIZE {
+            break;
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use eyre::Result;
+    use reqwest::{Client, StatusCode};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use url::Url;
+
+    use crate::crawler::fetch_error::FetchError;
+
+    use super::{read_robots_txt, RobotsCache, MAX_ROBOTS_TXT_SIZE};
+
+    // Fails with a 503 the first time, and then serves a robots.txt
+    async fn start_robots_server(requests: Arc<AtomicUsize>) -> Result<Url> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let _ = stream.read(&mut request).await;
+
+                let response = match requests.fetch_add(1, Ordering::SeqCst) {
+                    0 => "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    _ => "HTTP/1.1 200 OK\r\ncontent-length: 33\r\nconnection: close\r\n\r\nUser-agent: *\nDisallow: /private\n",
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        Ok(Url::parse(&format!(
+            "http://{}/private/page.html",
+            address
+        ))?)
+    }
+
+    #[tokio::test]
+    async fn only_keep_robots_txt_that_was_read() -> Result<()> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = start_robots_server(requests.clone()).await?;
+        let robots_cache = RobotsCache::default();
+        let http_client = Client::new();
+
+        // Requests waiting for the same fetch share its error
+        let (first, second) = tokio::join!(
+            robots_cache.get_robots(&http_client, &url, "fontsbot"),
+            robots_cache.get_robots(&http_client, &url, "fontsbot"),
+        );
+        let err = FetchError::ServerError(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(first.err(), Some(err.clone()));
+        assert_eq!(second.err(), Some(err));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // But the next request tries again
+        for _ in 0..2 {
+            let robots = robots_cache
+                .get_robots(&http_client, &url, "fontsbot")
+                .await?;
+            assert!(!robots.is_allowed(&url));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disallow_all_while_rate_limited() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/page.html", listener.local_addr()?))?;
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let _ = stream.read(&mut request).await;
+
+                let response = "HTTP/1.1 429 Too Many Requests\r\nretry-after: 2\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let err = RobotsCache::default()
+            .get_robots(&Client::new(), &url, "fontsbot")
+            .await
+            .expect_err("429 is not a missing robots.txt");
+
+        assert_eq!(
+            err,
+            FetchError::TooManyRequests(Some(Duration::from_secs(2)))
+        );
+        assert!(err.is_transient());
+
+        Ok(())
+    }
+
+    fn response(body: Vec<u8>) -> reqwest::Response {
+        http::Response::builder()
+            .header("content-type", "text/plain")
+            .body(body)
+            .expect("is a valid response")
+            .into()
+    }
+
+    #[tokio::test]
+    async fn cut_off_large_robots_txt() -> Result<()> {
+        let robots_txt = b"User-agent: *\nDisallow: /private\n".to_vec();
+
+        assert_eq!(
+            read_robots_txt(response(robots_txt.clone())).await?,
+            robots_txt
+        );
+
+        let large_robots_txt = robots_txt.repeat(MAX_ROBOTS_TXT_SIZE / robots_txt.len() + 1);
+
+        assert_eq!(
+            read_robots_txt(response(large_robots_txt.clone())).await?,
+            large_robots_txt[..MAX_ROBOTS_TXT_SIZE]
+        );
+
+        Ok(())
+    }
+}
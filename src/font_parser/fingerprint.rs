@@ -0,0 +1,155 @@
+use eyre::{eyre, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use tap::TapFallible;
+
+use super::{
+    glyf::{parse_glyphs, write_glyf_and_loca},
+    table_directory::FontTables,
+    tables::{read_u16, HeadTable, Os2Table},
+};
+
+// The fingerprint is a SHA-256 of the decompressed tables, so the same font gives
+// the same fingerprint whether it was served as woff, woff2 or ttf/otf. A few
+// things differ between encodings of the same font and are left out or normalized:
+// - DSIG, since signatures are usually dropped when converting to a web font
+// - loca, since it only holds glyph offsets and follows from glyf
+// - glyf is hashed with every glyph rewritten the same way, because woff2 stores
+//   glyphs in its own format and the reconstructed table is not byte identical
+// - head checkSumAdjustment, and the head flag woff2 encoders set (bit 11)
+
+static VERSION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d+)\.(\d+)").unwrap());
+
+// Tables left out of the fingerprint
+const SKIPPED_TABLES: [&str; 2] = ["DSIG", "loca"];
+
+pub fn get_fingerprint(tables: &FontTables) -> String {
+    let mut tags: Vec<&String> = tables
+        .keys()
+        .filter(|tag| !SKIPPED_TABLES.contains(&tag.as_str()))
+        .collect();
+    tags.sort();
+
+    let mut hasher = Sha256::new();
+
+    for tag in tags {
+        let data = match tag.as_str() {
+            // Hash the table as it is if the glyphs can't be read
+            "glyf" => normalize_glyf(tables)
+                .tap_err(|err| tracing::warn!(error = ?err, "Unable to normalize glyf table"))
+                .unwrap_or_else(|_| tables[tag].to_owned()),
+            // Or if the head table is too short to hold the flags
+            "head" => normalize_head(&tables[tag])
+                .tap_err(|err| tracing::warn!(error = ?err, "Unable to normalize head table"))
+                .unwrap_or_else(|_| tables[tag].to_owned()),
+            _ => tables[tag].to_owned(),
+        };
+
+        hasher.update(tag.as_bytes());
+        hasher.update((data.len() as u32).to_be_bytes());
+        hasher.update(&data);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+// head
+// 8-12     uint32      checkSumAdjustment
+// 16-18    uint16      flags                   Bit 11: font data has been losslessly transformed
+// 50-52    int16       indexToLocFormat
+
+fn normalize_head(head: &[u8]) -> Result<Vec<u8>> {
+    let mut head = head.to_owned();
+
+    let flags = read_u16(&head, 16)? & !0x0800;
+    head[8..12].copy_from_slice(&[0; 4]);
+    head[16..18].copy_from_slice(&flags.to_be_bytes());
+
+    Ok(head)
+}
+
+fn normalize_glyf(tables: &FontTables) -> Result<Vec<u8>> {
+    let get_table = |tag: &str| {
+        tables
+            .get(tag)
+            .ok_or_else(|| eyre!("Could not find {} table entry", tag))
+    };
+
+    let index_format = read_u16(get_table("head")?, 50)?;
+    let num_glyphs = read_u16(get_table("maxp")?, 4)?;
+
+    let glyphs = parse_glyphs(
+        get_table("glyf")?,
+        get_table("loca")?,
+        index_format,
+        num_glyphs,
+    )?;
+    let (glyf, _) = write_glyf_and_loca(&glyphs, 1)?;
+
+    Ok(glyf)
+}
+
+// A key that is the same for every copy of a font, made from the PostScript name
+// (or full name), the version and the vendor. Unlike the fingerprint it also
+// matches copies that were subset or otherwise modified.
+pub fn get_identity_key(
+    postscript_name: Option<&str>,
+    full_name: &str,
+    version: Option<&str>,
+    head: Option<&HeadTable>,
+    os2: Option<&Os2Table>,
+) -> String {
+    let name: String = postscript_name
+        .unwrap_or(full_name)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    // "Version 3.100;hotconv 1.0.109" and a head fontRevision of 3.0999 are both 3.100
+    let version = version
+        .and_then(|version| VERSION_RE.captures(version))
+        .and_then(|captures| {
+            format!("{}.{}", &captures[1], &captures[2])
+                .parse::<f64>()
+                .ok()
+        })
+        .or_else(|| head.map(|head| head.font_revision))
+        .map(|version| format!("{:.3}", version))
+        .unwrap_or_default();
+
+    let vendor = os2.map(|os2| os2.vendor_id.trim()).unwrap_or_default();
+
+    format!("{}|{}|{}", name, version, vendor).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_identity_key;
+
+    #[test]
+    fn normalize_identity_key() {
+        assert_eq!(
+            get_identity_key(
+                Some("Adieu-Regular"),
+                "Adieu Regular",
+                Some("Version 3.100;hotconv 1.0.109;makeotfexe 2.5.65596"),
+                None,
+                None
+            ),
+            "adieu-regular|3.100|"
+        );
+
+        // Falls back to the full name without whitespace
+        assert_eq!(
+            get_identity_key(
+                None,
+                "Univers Else Regular",
+                Some("Version 001.000"),
+                None,
+                None
+            ),
+            "universelseregular|1.000|"
+        );
+    }
+}
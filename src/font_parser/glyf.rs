@@ -0,0 +1,546 @@
+use eyre::{eyre, Result};
+
+use super::tables::{read_i16, read_u16, read_u32};
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/glyf
+// https://learn.microsoft.com/en-us/typography/opentype/spec/loca
+
+// Glyph header
+// 0-2      int16       numberOfContours    Negative for composite glyphs
+// 2-4      int16       xMin
+// 4-6      int16       yMin
+// 6-8      int16       xMax
+// 8-10     int16       yMax
+
+// Simple glyph (after the header)
+//          uint16      endPtsOfContours[numberOfContours]
+//          uint16      instructionLength
+//          uint8       instructions[instructionLength]
+//          uint8       flags[variable]
+//          uint8/int16 xCoordinates[variable]
+//          uint8/int16 yCoordinates[variable]
+
+// Composite glyph (after the header)
+//          Component records until one without MORE_COMPONENTS
+//          uint16      numInstr            If any component has WE_HAVE_INSTRUCTIONS
+//          uint8       instr[numInstr]
+
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const REPEAT_FLAG: u8 = 0x08;
+const X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+pub const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+pub const WE_HAVE_A_SCALE: u16 = 0x0008;
+pub const MORE_COMPONENTS: u16 = 0x0020;
+pub const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+pub const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+pub const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub x_min: i16,
+    pub y_min: i16,
+    pub x_max: i16,
+    pub y_max: i16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+    pub on_curve: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Glyph {
+    // Glyphs without outlines, like space
+    Empty,
+    Simple {
+        bbox: BoundingBox,
+        end_points: Vec<u16>,
+        instructions: Vec<u8>,
+        // Absolute coordinates
+        points: Vec<Point>,
+        overlap: bool,
+    },
+    Composite {
+        bbox: BoundingBox,
+        // The component records as they are stored
+        components: Vec<u8>,
+        instructions: Option<Vec<u8>>,
+    },
+}
+
+impl BoundingBox {
+    pub fn from_points(points: &[Point]) -> BoundingBox {
+        let x = points.iter().map(|point| point.x);
+        let y = points.iter().map(|point| point.y);
+
+        BoundingBox {
+            x_min: x.clone().min().unwrap_or(0) as i16,
+            y_min: y.clone().min().unwrap_or(0) as i16,
+            x_max: x.max().unwrap_or(0) as i16,
+            y_max: y.max().unwrap_or(0) as i16,
+        }
+    }
+}
+
+impl Glyph {
+    pub fn x_min(&self) -> i16 {
+        match self {
+            Glyph::Empty => 0,
+            Glyph::Simple { bbox, .. } | Glyph::Composite { bbox, .. } => bbox.x_min,
+        }
+    }
+
+    // Writes the glyph without padding. Flags are not run-length encoded, so the
+    // output only depends on the glyph and not on how it was stored before.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Glyph::Empty => {}
+            Glyph::Simple {
+                bbox,
+                end_points,
+                instructions,
+                points,
+                overlap,
+            } => {
+                write_header(out, end_points.len() as i16, bbox);
+                end_points
+                    .iter()
+                    .for_each(|end_point| out.extend_from_slice(&end_point.to_be_bytes()));
+                out.extend_from_slice(&(instructions.len() as u16).to_be_bytes());
+                out.extend_from_slice(instructions);
+
+                let mut flags: Vec<u8> = Vec::with_capacity(points.len());
+                let mut x_coordinates: Vec<u8> = vec![];
+                let mut y_coordinates: Vec<u8> = vec![];
+                let (mut last_x, mut last_y) = (0, 0);
+
+                for point in points {
+                    let mut flag = match point.on_curve {
+                        true => ON_CURVE_POINT,
+                        false => 0,
+                    };
+
+                    flag |= write_coordinate(
+                        &mut x_coordinates,
+                        point.x - last_x,
+                        X_SHORT_VECTOR,
+                        X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR,
+                    );
+                    flag |= write_coordinate(
+                        &mut y_coordinates,
+                        point.y - last_y,
+                        Y_SHORT_VECTOR,
+                        Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR,
+                    );
+
+                    flags.push(flag);
+                    (last_x, last_y) = (point.x, point.y);
+                }
+
+                if let (true, Some(first)) = (overlap, flags.first_mut()) {
+                    *first |= OVERLAP_SIMPLE;
+                }
+
+                out.extend(flags);
+                out.extend(x_coordinates);
+                out.extend(y_coordinates);
+            }
+            Glyph::Composite {
+                bbox,
+                components,
+                instructions,
+            } => {
+                write_header(out, -1, bbox);
+                out.extend_from_slice(components);
+
+                if let Some(instructions) = instructions {
+                    out.extend_from_slice(&(instructions.len() as u16).to_be_bytes());
+                    out.extend_from_slice(instructions);
+                }
+            }
+        }
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, number_of_contours: i16, bbox: &BoundingBox) {
+    [
+        number_of_contours,
+        bbox.x_min,
+        bbox.y_min,
+        bbox.x_max,
+        bbox.y_max,
+    ]
+    .iter()
+    .for_each(|value| out.extend_from_slice(&value.to_be_bytes()));
+}
+
+// Returns the flag bits for the coordinate
+fn write_coordinate(
+    out: &mut Vec<u8>,
+    delta: i32,
+    short_flag: u8,
+    same_or_positive_flag: u8,
+) -> u8 {
+    match delta {
+        0 => same_or_positive_flag,
+        1..=255 => {
+            out.push(delta as u8);
+            short_flag | same_or_positive_flag
+        }
+        -255..=-1 => {
+            out.push(-delta as u8);
+            short_flag
+        }
+        _ => {
+            out.extend_from_slice(&(delta as i16).to_be_bytes());
+            0
+        }
+    }
+}
+
+// Writes glyf and loca tables, padding each glyph to four bytes.
+// index_format is 0 for short (u16, offset / 2) and 1 for long (u32) loca offsets.
+pub fn write_glyf_and_loca(glyphs: &[Glyph], index_format: u16) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut glyf: Vec<u8> = vec![];
+    let mut loca: Vec<u8> = vec![];
+
+    let mut write_offset = |offset: usize| -> Result<()> {
+        match index_format {
+            0 => {
+                let offset: u16 = (offset / 2)
+                    .try_into()
+                    .map_err(|_| eyre!("glyf table is too large for short loca offsets"))?;
+                loca.extend_from_slice(&offset.to_be_bytes());
+            }
+            _ => loca.extend_from_slice(&(offset as u32).to_be_bytes()),
+        }
+        Ok(())
+    };
+
+    for glyph in glyphs {
+        write_offset(glyf.len())?;
+        glyph.write(&mut glyf);
+
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    write_offset(glyf.len())?;
+
+    Ok((glyf, loca))
+}
+
+// Reads every glyph using the loca offsets
+pub fn parse_glyphs(
+    glyf: &[u8],
+    loca: &[u8],
+    index_format: u16,
+    num_glyphs: u16,
+) -> Result<Vec<Glyph>> {
+    let offset = |index: usize| -> Result<usize> {
+        match index_format {
+            0 => Ok(read_u16(loca, index * 2)? as usize * 2),
+            _ => Ok(read_u32(loca, index * 4)? as usize),
+        }
+    };
+
+    (0..num_glyphs as usize)
+        .map(|index| {
+            let (start, end) = (offset(index)?, offset(index + 1)?);
+
+            match end > start {
+                true => parse_glyph(
+                    glyf.get(start..end)
+                        .ok_or_else(|| eyre!("Glyph {} is out of bounds", index))?,
+                ),
+                false => Ok(Glyph::Empty),
+            }
+        })
+        .collect()
+}
+
+fn parse_glyph(data: &[u8]) -> Result<Glyph> {
+    let number_of_contours = read_i16(data, 0)?;
+    let bbox = BoundingBox {
+        x_min: read_i16(data, 2)?,
+        y_min: read_i16(data, 4)?,
+        x_max: read_i16(data, 6)?,
+        y_max: read_i16(data, 8)?,
+    };
+
+    if number_of_contours < 0 {
+        let mut position = 10;
+        let mut have_instructions = false;
+
+        loop {
+            let flags = read_u16(data, position)?;
+            position += 4 + component_arguments_length(flags);
+            have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+
+        let components = data
+            .get(10..position)
+            .ok_or_else(|| eyre!("Glyph components are out of bounds"))?
+            .to_owned();
+
+        let instructions = match have_instructions {
+            true => {
+                let length: usize = read_u16(data, position)?.into();
+                let instructions = data
+                    .get(position + 2..position + 2 + length)
+                    .ok_or_else(|| eyre!("Glyph instructions are out of bounds"))?;
+                Some(instructions.to_owned())
+            }
+            false => None,
+        };
+
+        return Ok(Glyph::Composite {
+            bbox,
+            components,
+            instructions,
+        });
+    }
+
+    let end_points: Vec<u16> = (0..number_of_contours as usize)
+        .map(|i| read_u16(data, 10 + i * 2))
+        .collect::<Result<_>>()?;
+
+    let num_points = end_points.last().map_or(0, |&last| last as usize + 1);
+
+    let mut position = 10 + end_points.len() * 2;
+    let instruction_length: usize = read_u16(data, position)?.into();
+    let instructions = data
+        .get(position + 2..position + 2 + instruction_length)
+        .ok_or_else(|| eyre!("Glyph instructions are out of bounds"))?
+        .to_owned();
+    position += 2 + instruction_length;
+
+    let mut next_byte = || -> Result<u8> {
+        let byte = *data
+            .get(position)
+            .ok_or_else(|| eyre!("Glyph outline is out of bounds"))?;
+        position += 1;
+        Ok(byte)
+    };
+
+    let mut flags: Vec<u8> = Vec::with_capacity(num_points);
+    while flags.len() < num_points {
+        let flag = next_byte()?;
+        flags.push(flag);
+
+        if flag & REPEAT_FLAG != 0 {
+            let repeat = next_byte()?;
+            flags.extend(std::iter::repeat_n(flag, repeat as usize));
+        }
+    }
+    flags.truncate(num_points);
+
+    let mut read_coordinates = |short_flag: u8, same_or_positive_flag: u8| -> Result<Vec<i32>> {
+        let mut value = 0;
+
+        flags
+            .iter()
+            .map(|flag| {
+                value += match (flag & short_flag != 0, flag & same_or_positive_flag != 0) {
+                    (true, true) => next_byte()? as i32,
+                    (true, false) => -(next_byte()? as i32),
+                    (false, true) => 0,
+                    (false, false) => i16::from_be_bytes([next_byte()?, next_byte()?]) as i32,
+                };
+                Ok(value)
+            })
+            .collect()
+    };
+
+    let x_coordinates = read_coordinates(X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE_X_SHORT_VECTOR)?;
+    let y_coordinates = read_coordinates(Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE_Y_SHORT_VECTOR)?;
+
+    let points = flags
+        .iter()
+        .zip(x_coordinates.into_iter().zip(y_coordinates))
+        .map(|(flag, (x, y))| Point {
+            x,
+            y,
+            on_curve: flag & ON_CURVE_POINT != 0,
+        })
+        .collect();
+
+    Ok(Glyph::Simple {
+        bbox,
+        end_points,
+        instructions,
+        points,
+        overlap: flags.first().is_some_and(|flag| flag & OVERLAP_SIMPLE != 0),
+    })
+}
+
+// Length of the arguments and transform of a component record, after its flags
+// and glyph index
+pub fn component_arguments_length(flags: u16) -> usize {
+    let arguments = match flags & ARG_1_AND_2_ARE_WORDS {
+        0 => 2,
+        _ => 4,
+    };
+
+    let transform = if flags & WE_HAVE_A_SCALE != 0 {
+        2
+    } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+        4
+    } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+        8
+    } else {
+        0
+    };
+
+    arguments + transform
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+
+    use super::{
+        component_arguments_length, parse_glyphs, write_glyf_and_loca, BoundingBox, Glyph, Point,
+        ARG_1_AND_2_ARE_WORDS, WE_HAVE_AN_X_AND_Y_SCALE, WE_HAVE_A_SCALE, WE_HAVE_A_TWO_BY_TWO,
+        WE_HAVE_INSTRUCTIONS,
+    };
+
+    fn point(x: i32, y: i32, on_curve: bool) -> Point {
+        Point { x, y, on_curve }
+    }
+
+    fn bbox(x_min: i16, y_min: i16, x_max: i16, y_max: i16) -> BoundingBox {
+        BoundingBox {
+            x_min,
+            y_min,
+            x_max,
+            y_max,
+        }
+    }
+
+    // Coordinates that are the same, short in both directions and long
+    fn glyphs() -> Vec<Glyph> {
+        vec![
+            Glyph::Empty,
+            Glyph::Simple {
+                bbox: bbox(-300, 0, 100, 700),
+                end_points: vec![2, 4],
+                instructions: vec![0xb0, 0x01],
+                points: vec![
+                    point(0, 0, true),
+                    point(100, 0, false),
+                    point(100, 700, true),
+                    point(-300, 700, true),
+                    point(-300, 450, false),
+                ],
+                overlap: true,
+            },
+            Glyph::Composite {
+                bbox: bbox(0, 0, 100, 700),
+                components: vec![0x01, 0x01, 0x00, 0x01, 0x00, 0x0a, 0xff, 0xf6],
+                instructions: Some(vec![0x4b, 0x42]),
+            },
+        ]
+    }
+
+    #[test]
+    fn write_and_parse_glyphs() -> Result<()> {
+        for index_format in [0, 1] {
+            let (glyf, loca) = write_glyf_and_loca(&glyphs(), index_format)?;
+
+            assert_eq!(glyf.len() % 4, 0);
+            assert_eq!(loca.len(), 4 * (2 + 2 * index_format as usize));
+            assert_eq!(parse_glyphs(&glyf, &loca, index_format, 3)?, glyphs());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_repeated_flags() -> Result<()> {
+        #[rustfmt::skip]
+        let glyph: Vec<u8> = vec![
+            0x00, 0x01, 0, 0, 0, 0, 0, 0, 0, 0,
+            // endPtsOfContours and instructionLength
+            0x00, 0x03, 0x00, 0x00,
+            // An on-curve flag with positive short x and same y, repeated twice,
+            // then an off-curve flag with long x and y
+            0x3b, 2, 0x00,
+            10, 20, 30, 0xff, 0x9c,
+            0x01, 0x2c,
+        ];
+        let loca: Vec<u8> = [0u32, glyph.len() as u32]
+            .iter()
+            .flat_map(|offset| offset.to_be_bytes())
+            .collect();
+
+        let glyphs = parse_glyphs(&glyph, &loca, 1, 1)?;
+        let Glyph::Simple { points, .. } = &glyphs[0] else {
+            panic!("glyph is simple");
+        };
+
+        assert_eq!(
+            points,
+            &vec![
+                point(10, 0, true),
+                point(30, 0, true),
+                point(60, 0, true),
+                point(-40, 300, false),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_malformed_glyphs() -> Result<()> {
+        let (glyf, loca) = write_glyf_and_loca(&glyphs(), 1)?;
+
+        for length in 0..glyf.len() {
+            assert!(
+                parse_glyphs(&glyf[..length], &loca, 1, 3).is_err(),
+                "{}",
+                length
+            );
+        }
+        assert!(parse_glyphs(&glyf, &loca[..8], 1, 3).is_err());
+
+        // A contour that ends at point 65535, without any points
+        let glyph: Vec<u8> = vec![0x00, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0x00, 0x00];
+        assert!(parse_glyphs(&glyph, &[0, 0, 0, 7], 0, 1).is_err());
+
+        // Offsets past 128 KiB don't fit in short loca offsets
+        let large = Glyph::Composite {
+            bbox: bbox(0, 0, 0, 0),
+            components: vec![0; 140_000],
+            instructions: None,
+        };
+        assert!(write_glyf_and_loca(&[large], 0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_component_arguments_length() {
+        assert_eq!(component_arguments_length(0), 2);
+        assert_eq!(component_arguments_length(WE_HAVE_INSTRUCTIONS), 2);
+        assert_eq!(component_arguments_length(ARG_1_AND_2_ARE_WORDS), 4);
+        assert_eq!(component_arguments_length(WE_HAVE_A_SCALE), 4);
+        assert_eq!(component_arguments_length(WE_HAVE_AN_X_AND_Y_SCALE), 6);
+        assert_eq!(
+            component_arguments_length(ARG_1_AND_2_ARE_WORDS | WE_HAVE_A_TWO_BY_TWO),
+            12
+        );
+    }
+}
@@ -1,4 +1,13 @@
+mod fingerprint;
+mod glyf;
+mod name_table;
 mod parser;
+mod sfnt_parser;
+mod table_directory;
+mod tables;
+mod variations;
+mod woff2_parser;
+mod woff2_transforms;
 mod woff_parser;
 
-pub use parser::FontData;
+pub use parser::{has_font_signature, FontData};
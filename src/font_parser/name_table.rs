@@ -0,0 +1,413 @@
+use eyre::{eyre, Result};
+
+use super::tables::read_u16;
+
+// https://learn.microsoft.com/nb-no/typography/opentype/spec/name
+
+//      Type 	    Name 	            Description
+// 0-2  uint16 	    version 	        Table version number (=0 or 1).
+// 2-4  uint16 	    count 	            Number of name records.
+// 4-6  Offset16 	storageOffset 	    Offset to start of string storage (from start of table).
+//      NameRecord 	nameRecord[count] 	The name records where count is the number of records.
+//      uint16      langTagCount        Number of language-tag records (version 1 only).
+//      LangTagRecord langTagRecord[langTagCount] (version 1 only)
+//                  (Variable)          Storage for the actual string data.
+
+// Type 	Name 	Description
+// uint16 	platformID 	Platform ID.
+// uint16 	encodingID 	Platform-specific encoding ID.
+// uint16 	languageID 	Language ID.
+// uint16 	nameID 	Name ID.
+// uint16 	length 	String length (in bytes).
+// Offset16 	stringOffset 	String offset from start of storage area (in bytes).
+
+// LangTagRecord
+// uint16 	length 	Language-tag string length (in bytes)
+// Offset16 	langTagOffset 	Language-tag string offset from start of storage area (in bytes).
+
+pub const PLATFORM_UNICODE: u16 = 0;
+pub const PLATFORM_MACINTOSH: u16 = 1;
+pub const PLATFORM_WINDOWS: u16 = 3;
+
+// Name IDs
+pub const COPYRIGHT: u16 = 0;
+pub const FAMILY_NAME: u16 = 1;
+pub const SUBFAMILY_NAME: u16 = 2;
+pub const UNIQUE_IDENTIFIER: u16 = 3;
+pub const FULL_NAME: u16 = 4;
+pub const VERSION: u16 = 5;
+pub const POSTSCRIPT_NAME: u16 = 6;
+pub const TRADEMARK: u16 = 7;
+pub const MANUFACTURER: u16 = 8;
+pub const DESIGNER: u16 = 9;
+pub const DESCRIPTION: u16 = 10;
+pub const VENDOR_URL: u16 = 11;
+pub const DESIGNER_URL: u16 = 12;
+pub const LICENSE: u16 = 13;
+pub const LICENSE_URL: u16 = 14;
+pub const TYPOGRAPHIC_FAMILY_NAME: u16 = 16;
+pub const TYPOGRAPHIC_SUBFAMILY_NAME: u16 = 17;
+
+#[derive(Debug)]
+pub struct NameTable {
+    records: Vec<NameRecord>,
+}
+
+// A decoded name record. Records in encodings we can't decode are left out of
+// the name table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameRecord {
+    pub platform_id: u16,
+    pub encoding_id: u16,
+    pub language_id: u16,
+    pub name_id: u16,
+    // BCP 47 language tag, if the language ID is known
+    pub language: Option<String>,
+    pub value: String,
+}
+
+// A name in a language other than English
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalizedName {
+    pub name_id: u16,
+    pub language: Option<String>,
+    pub value: String,
+}
+
+impl NameRecord {
+    fn is_english(&self) -> bool {
+        match self.platform_id {
+            PLATFORM_UNICODE => true,
+            PLATFORM_MACINTOSH => self.language_id == 0,
+            PLATFORM_WINDOWS => self.language_id & 0x3ff == 0x09,
+            _ => false,
+        }
+    }
+
+    // Lower is better. Windows US English is what most tools write and read, and
+    // Mac records are mostly legacy duplicates.
+    fn preference(&self) -> u8 {
+        match (self.platform_id, self.language_id) {
+            (PLATFORM_WINDOWS, 0x0409) => 0,
+            (PLATFORM_WINDOWS, _) if self.is_english() => 1,
+            (PLATFORM_UNICODE, _) => 2,
+            (PLATFORM_MACINTOSH, 0) => 3,
+            _ => 4,
+        }
+    }
+}
+
+impl NameTable {
+    // Every string for the name ID, across platforms and languages
+    pub fn all(&self, name_id: u16) -> impl Iterator<Item = &NameRecord> {
+        self.records
+            .iter()
+            .filter(move |record| record.name_id == name_id)
+    }
+
+    // The preferred string for the name ID, English if there is one
+    pub fn get(&self, name_id: u16) -> Option<&str> {
+        self.all(name_id)
+            .filter(|record| !record.value.is_empty())
+            .min_by_key(|record| record.preference())
+            .map(|record| record.value.as_str())
+    }
+
+    // Typographic family name (ID 16) if present, else the legacy family name (ID 1)
+    pub fn family_name(&self) -> Option<&str> {
+        self.get(TYPOGRAPHIC_FAMILY_NAME)
+            .or_else(|| self.get(FAMILY_NAME))
+    }
+
+    // Typographic subfamily name (ID 17) if present, else the legacy subfamily name (ID 2)
+    pub fn sub_family_name(&self) -> Option<&str> {
+        self.get(TYPOGRAPHIC_SUBFAMILY_NAME)
+            .or_else(|| self.get(SUBFAMILY_NAME))
+    }
+
+    // Strings in languages other than English, without duplicates across platforms
+    pub fn localized_names(&self) -> Vec<LocalizedName> {
+        let mut localized: Vec<LocalizedName> = vec![];
+
+        for record in self.records.iter().filter(|record| !record.is_english()) {
+            let name = LocalizedName {
+                name_id: record.name_id,
+                language: record.language.to_owned(),
+                value: record.value.to_owned(),
+            };
+
+            if !localized.contains(&name) {
+                localized.push(name);
+            }
+        }
+
+        localized
+    }
+}
+
+// Parses an uncompressed name table. The table looks the same regardless of
+// which container format it was stored in.
+pub fn parse_name_table(name_data: Vec<u8>) -> Result<NameTable> {
+    let version: u16 = read_u16(&name_data, 0)?;
+    let count: u16 = read_u16(&name_data, 2)?;
+    let storage_offset: usize = read_u16(&name_data, 4)?.into();
+
+    let lang_tags: Vec<String> = match version {
+        1 => get_lang_tags(&name_data, count, storage_offset)?,
+        _ => vec![],
+    };
+
+    let records: Vec<NameRecord> = (0..count as usize)
+        .map(|i| get_name_record(&name_data, 6 + i * 12, storage_offset, &lang_tags))
+        .collect::<Result<Vec<Option<NameRecord>>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    Ok(NameTable { records })
+}
+
+// Returns None if the string is stored in an encoding we can't decode
+fn get_name_record(
+    data: &[u8],
+    position: usize,
+    storage_offset: usize,
+    lang_tags: &[String],
+) -> Result<Option<NameRecord>> {
+    let platform_id = read_u16(data, position)?;
+    let encoding_id = read_u16(data, position + 2)?;
+    let language_id = read_u16(data, position + 4)?;
+    let name_id = read_u16(data, position + 6)?;
+    let length: usize = read_u16(data, position + 8)?.into();
+    let offset: usize = read_u16(data, position + 10)?.into();
+
+    let start = storage_offset + offset;
+    let bytes = data
+        .get(start..start + length)
+        .ok_or_else(|| eyre!("Name record {} is out of bounds", name_id))?;
+
+    let value = match (platform_id, encoding_id) {
+        (PLATFORM_UNICODE, _) | (PLATFORM_WINDOWS, 0 | 1 | 10) => decode_utf16_be(bytes),
+        (PLATFORM_MACINTOSH, 0) => decode_mac_roman(bytes),
+        _ => return Ok(None),
+    };
+
+    let language = match (platform_id, language_id) {
+        (_, id) if id >= 0x8000 => lang_tags.get((id - 0x8000) as usize).cloned(),
+        (PLATFORM_WINDOWS, id) => windows_language_tag(id).map(|tag| tag.to_owned()),
+        (PLATFORM_MACINTOSH, id) => mac_language_tag(id).map(|tag| tag.to_owned()),
+        _ => None,
+    };
+
+    Ok(Some(NameRecord {
+        platform_id,
+        encoding_id,
+        language_id,
+        name_id,
+        language,
+        value: value.trim().replace('\0', ""),
+    }))
+}
+
+// Language tags are always UTF-16BE
+fn get_lang_tags(data: &[u8], count: u16, storage_offset: usize) -> Result<Vec<String>> {
+    let position = 6 + count as usize * 12;
+    let lang_tag_count = read_u16(data, position)?;
+
+    (0..lang_tag_count as usize)
+        .map(|i| {
+            let length: usize = read_u16(data, position + 2 + i * 4)?.into();
+            let offset: usize = read_u16(data, position + 4 + i * 4)?.into();
+            let start = storage_offset + offset;
+
+            data.get(start..start + length)
+                .map(decode_utf16_be)
+                .ok_or_else(|| eyre!("Language tag is out of bounds"))
+        })
+        .collect()
+}
+
+fn decode_utf16_be(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+// Characters for the bytes 0x80-0xFF. The lower half is the same as ASCII.
+const MAC_ROMAN_HIGH: &str = "ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü†°¢£§•¶ß®©™´¨≠ÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø¿¡¬√ƒ≈∆«»…\u{A0}ÀÃÕŒœ–—“”‘’÷◊ÿŸ⁄€‹›\u{FB01}\u{FB02}‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ\u{F8FF}ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ";
+
+fn decode_mac_roman(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x00..=0x7f => byte as char,
+            _ => MAC_ROMAN_HIGH
+                .chars()
+                .nth((byte - 0x80) as usize)
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        })
+        .collect()
+}
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/name#windows-language-ids
+// Only the most common ones. Unknown IDs get no language tag.
+fn windows_language_tag(language_id: u16) -> Option<&'static str> {
+    let tag = match language_id {
+        0x0401 => "ar-SA",
+        0x0402 => "bg-BG",
+        0x0403 => "ca-ES",
+        0x0404 => "zh-TW",
+        0x0405 => "cs-CZ",
+        0x0406 => "da-DK",
+        0x0407 => "de-DE",
+        0x0408 => "el-GR",
+        0x0409 => "en-US",
+        0x040B => "fi-FI",
+        0x040C => "fr-FR",
+        0x040D => "he-IL",
+        0x040E => "hu-HU",
+        0x040F => "is-IS",
+        0x0410 => "it-IT",
+        0x0411 => "ja-JP",
+        0x0412 => "ko-KR",
+        0x0413 => "nl-NL",
+        0x0414 => "nb-NO",
+        0x0415 => "pl-PL",
+        0x0416 => "pt-BR",
+        0x0418 => "ro-RO",
+        0x0419 => "ru-RU",
+        0x041A => "hr-HR",
+        0x041B => "sk-SK",
+        0x041D => "sv-SE",
+        0x041E => "th-TH",
+        0x041F => "tr-TR",
+        0x0421 => "id-ID",
+        0x0422 => "uk-UA",
+        0x0424 => "sl-SI",
+        0x0425 => "et-EE",
+        0x0426 => "lv-LV",
+        0x0427 => "lt-LT",
+        0x042A => "vi-VN",
+        0x0439 => "hi-IN",
+        0x0804 => "zh-CN",
+        0x0809 => "en-GB",
+        0x080A => "es-MX",
+        0x0814 => "nn-NO",
+        0x0816 => "pt-PT",
+        0x0C04 => "zh-HK",
+        0x0C09 => "en-AU",
+        0x0C0A => "es-ES",
+        0x0C0C => "fr-CA",
+        0x1004 => "zh-SG",
+        0x1009 => "en-CA",
+        _ => return None,
+    };
+
+    Some(tag)
+}
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/name#macintosh-language-ids
+fn mac_language_tag(language_id: u16) -> Option<&'static str> {
+    let tag = match language_id {
+        0 => "en",
+        1 => "fr",
+        2 => "de",
+        3 => "it",
+        4 => "nl",
+        5 => "sv",
+        6 => "es",
+        7 => "da",
+        8 => "pt",
+        9 => "nb",
+        10 => "he",
+        11 => "ja",
+        12 => "ar",
+        13 => "fi",
+        14 => "el",
+        15 => "is",
+        17 => "tr",
+        18 => "hr",
+        19 => "zh-Hant",
+        22 => "hu",
+        23 => "ko",
+        25 => "pl",
+        32 => "ru",
+        33 => "zh-Hans",
+        _ => return None,
+    };
+
+    Some(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_name_table, LocalizedName};
+
+    // Builds a version 0 name table from (platform, encoding, language, name id, bytes)
+    fn build_name_table(records: &[(u16, u16, u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let storage_offset = 6 + records.len() * 12;
+        let mut header: Vec<u8> = vec![];
+        let mut storage: Vec<u8> = vec![];
+
+        header.extend(0u16.to_be_bytes());
+        header.extend((records.len() as u16).to_be_bytes());
+        header.extend((storage_offset as u16).to_be_bytes());
+
+        for (platform_id, encoding_id, language_id, name_id, bytes) in records {
+            for value in [*platform_id, *encoding_id, *language_id, *name_id] {
+                header.extend(value.to_be_bytes());
+            }
+            header.extend((bytes.len() as u16).to_be_bytes());
+            header.extend((storage.len() as u16).to_be_bytes());
+            storage.extend(bytes);
+        }
+
+        header.extend(storage);
+        header
+    }
+
+    fn utf16(value: &str) -> Vec<u8> {
+        value
+            .encode_utf16()
+            .flat_map(|unit| unit.to_be_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn decode_name_records() -> eyre::Result<()> {
+        let data = build_name_table(&[
+            // Mac Roman, 0xA9 is the copyright sign
+            (1, 0, 0, 0, b"\xa9 2017 Foundry".to_vec()),
+            (1, 0, 0, 1, b"Mac Family".to_vec()),
+            (3, 1, 0x0407, 1, utf16("Familie")),
+            (3, 1, 0x0409, 1, utf16("Family")),
+            (3, 1, 0x0409, 2, utf16("Bold")),
+            (3, 1, 0x0409, 16, utf16("Typographic Family")),
+            // Big5 can't be decoded and is left out
+            (3, 4, 0x0404, 1, vec![0xa4, 0xa4]),
+        ]);
+
+        let name_table = parse_name_table(data)?;
+
+        assert_eq!(name_table.all(1).count(), 3);
+        assert_eq!(name_table.get(0), Some("© 2017 Foundry"));
+        assert_eq!(name_table.get(1), Some("Family"));
+        assert_eq!(name_table.family_name(), Some("Typographic Family"));
+        assert_eq!(name_table.sub_family_name(), Some("Bold"));
+        assert_eq!(name_table.get(14), None);
+
+        assert_eq!(
+            name_table.localized_names(),
+            vec![LocalizedName {
+                name_id: 1,
+                language: Some("de-DE".to_owned()),
+                value: "Familie".to_owned(),
+            }]
+        );
+
+        Ok(())
+    }
+}
@@ -1,41 +1,83 @@
-use std::str::FromStr;
-
 use eyre::{eyre, Result};
+use tap::TapFallible;
 
-use super::woff_parser::parse_woff;
+use super::{
+    fingerprint::{get_fingerprint, get_identity_key},
+    name_table::{self, parse_name_table, LocalizedName, NameTable},
+    sfnt_parser::{parse_sfnt, parse_sfnt_collection},
+    table_directory::FontTables,
+    tables::{HeadTable, HheaTable, Os2Table, PostTable},
+    variations::{FvarTable, StatTable},
+    woff2_parser::{parse_woff2, parse_woff2_collection},
+    woff_parser::parse_woff,
+};
 
 #[derive(Debug)]
 enum FontSignature {
     Woff,
     Woff2,
+    // 0x00010000 or 'true' for TrueType outlines, 'OTTO' for CFF outlines
+    Sfnt,
+    // 'ttcf', several sfnt fonts in one file
+    Collection,
 }
 
 impl TryInto<FontSignature> for &[u8] {
     type Error = eyre::ErrReport;
 
     fn try_into(self) -> Result<FontSignature> {
-        std::str::from_utf8(&self[0..4])?.parse()
-    }
-}
-
-impl FromStr for FontSignature {
-    type Err = eyre::ErrReport;
+        let signature = self
+            .get(0..4)
+            .ok_or_else(|| eyre!("Content is too short to contain a font signature"))?;
 
-    fn from_str(s: &str) -> Result<Self> {
-        match s {
-            "wOFF" => Ok(FontSignature::Woff),
-            "wOF2" => Ok(FontSignature::Woff2),
-            _ => Err(eyre!("Signature variant for {} not found!", s)),
+        match signature {
+            b"wOFF" => Ok(FontSignature::Woff),
+            b"wOF2" => Ok(FontSignature::Woff2),
+            [0x00, 0x01, 0x00, 0x00] | b"OTTO" | b"true" => Ok(FontSignature::Sfnt),
+            b"ttcf" => Ok(FontSignature::Collection),
+            _ => Err(eyre!("Signature variant for {:?} not found!", signature)),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+// Whether the content starts like a font file
+pub fn has_font_signature(content: &[u8]) -> bool {
+    TryInto::<FontSignature>::try_into(content).is_ok()
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FontData {
     pub family_name: String,
     pub sub_family_name: String,
     pub identifier: String,
     pub full_name: String,
+    pub copyright: Option<String>,
+    pub version: Option<String>,
+    pub postscript_name: Option<String>,
+    pub trademark: Option<String>,
+    pub manufacturer: Option<String>,
+    pub designer: Option<String>,
+    pub description: Option<String>,
+    pub vendor_url: Option<String>,
+    pub designer_url: Option<String>,
+    pub license: Option<String>,
+    pub license_url: Option<String>,
+    // Names in languages other than English
+    pub localized_names: Vec<LocalizedName>,
+    pub os2: Option<Os2Table>,
+    pub head: Option<HeadTable>,
+    pub hhea: Option<HheaTable>,
+    pub post: Option<PostTable>,
+    // Variation axes and named instances, only present in variable fonts
+    pub fvar: Option<FvarTable>,
+    // Style attributes of the font, or of every position in the design space
+    pub stat: Option<StatTable>,
+    // Variable fonts with an avar table remap their axis values
+    pub has_avar: bool,
+    // SHA-256 of the tables, the same for every encoding (woff, woff2, ttf) of a font
+    pub fingerprint: String,
+    // PostScript name, version and vendor, e.g. "adieu-regular|3.100|ukwn"
+    pub identity_key: String,
 }
 
 impl FontData {
@@ -46,50 +88,501 @@ impl FontData {
         use std::fs;
         let content = fs::read(filepath)?;
 
-        return FontData::from_bytes(&content);
+        FontData::from_bytes(&content)
     }
 
+    #[cfg(test)]
+    fn all_from_filepath(filepath: &str) -> Result<Vec<FontData>> {
+        let content = std::fs::read(filepath)?;
+
+        FontData::all_from_bytes(&content)
+    }
+
+    // For collections this is the first font in the collection.
+    // Use FontData::all_from_bytes to get all of them.
     pub fn from_bytes(content: &Vec<u8>) -> Result<FontData> {
         let signature: FontSignature = content.as_slice().try_into()?;
 
         match signature {
-            FontSignature::Woff => return parse_woff(&content),
-            FontSignature::Woff2 => return Err(eyre!("woff2 parsing not implemented!")),
+            FontSignature::Woff => parse_woff(content),
+            FontSignature::Woff2 => parse_woff2(content),
+            FontSignature::Sfnt => parse_sfnt(content),
+            FontSignature::Collection => parse_sfnt_collection(content)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| eyre!("Font collection contains no fonts")),
+        }
+    }
+
+    // Builds the font data from the decompressed tables of one font. Only the name
+    // table is required, the other tables are left out if missing or malformed.
+    pub(super) fn from_tables(tables: &FontTables) -> Result<FontData> {
+        let name_data = tables
+            .get("name")
+            .ok_or_else(|| eyre!("Could not find name table entry"))?;
+
+        let name_table: NameTable = parse_name_table(name_data.to_owned())?;
+
+        let required_name = |name: Option<&str>, description: &str| {
+            name.map(|name| name.to_owned())
+                .ok_or_else(|| eyre!("Unable to find {} record", description))
         };
+        let optional_name = |name_id: u16| name_table.get(name_id).map(|name| name.to_owned());
+
+        let full_name = required_name(name_table.get(name_table::FULL_NAME), "full name")?;
+        let version = optional_name(name_table::VERSION);
+        let postscript_name = optional_name(name_table::POSTSCRIPT_NAME);
+        let os2: Option<Os2Table> = parse_optional_table(tables, "OS/2");
+        let head: Option<HeadTable> = parse_optional_table(tables, "head");
+
+        let identity_key = get_identity_key(
+            postscript_name.as_deref(),
+            &full_name,
+            version.as_deref(),
+            head.as_ref(),
+            os2.as_ref(),
+        );
+
+        Ok(FontData {
+            family_name: required_name(name_table.family_name(), "font family")?,
+            sub_family_name: required_name(name_table.sub_family_name(), "font subfamily")?,
+            identifier: required_name(
+                name_table.get(name_table::UNIQUE_IDENTIFIER),
+                "unique identifier",
+            )?,
+            full_name,
+            copyright: optional_name(name_table::COPYRIGHT),
+            version,
+            postscript_name,
+            trademark: optional_name(name_table::TRADEMARK),
+            manufacturer: optional_name(name_table::MANUFACTURER),
+            designer: optional_name(name_table::DESIGNER),
+            description: optional_name(name_table::DESCRIPTION),
+            vendor_url: optional_name(name_table::VENDOR_URL),
+            designer_url: optional_name(name_table::DESIGNER_URL),
+            license: optional_name(name_table::LICENSE),
+            license_url: optional_name(name_table::LICENSE_URL),
+            localized_names: name_table.localized_names(),
+            os2,
+            head,
+            hhea: parse_optional_table(tables, "hhea"),
+            post: parse_optional_table(tables, "post"),
+            fvar: parse_optional_named_table(tables, "fvar", &name_table, FvarTable::parse),
+            stat: parse_optional_named_table(tables, "STAT", &name_table, StatTable::parse),
+            has_avar: tables.contains_key("avar"),
+            fingerprint: get_fingerprint(tables),
+            identity_key,
+        })
+    }
+
+    // The style the OS/2 table marks the font as, e.g. "Bold Italic". None for
+    // fonts without an OS/2 table, and for weights like Light that set no bits.
+    pub fn style(&self) -> Option<String> {
+        let os2 = self.os2.as_ref()?;
+
+        let slope = match (os2.is_oblique(), os2.is_italic()) {
+            (true, _) => Some("Oblique"),
+            (false, true) => Some("Italic"),
+            (false, false) => None,
+        };
+
+        match (os2.is_bold(), slope) {
+            (true, Some(slope)) => Some(format!("Bold {}", slope)),
+            (true, None) => Some("Bold".to_owned()),
+            (false, Some(slope)) => Some(slope.to_owned()),
+            (false, None) if os2.is_regular() => Some("Regular".to_owned()),
+            (false, None) => None,
+        }
+    }
+
+    pub fn is_variable(&self) -> bool {
+        self.fvar.is_some()
+    }
+
+    // Returns one FontData per font in the file. Only collections (ttc, or woff2
+    // with a collection directory) contain more than one.
+    pub fn all_from_bytes(content: &Vec<u8>) -> Result<Vec<FontData>> {
+        let signature: FontSignature = content.as_slice().try_into()?;
+
+        match signature {
+            FontSignature::Woff2 => parse_woff2_collection(content),
+            FontSignature::Collection => parse_sfnt_collection(content),
+            FontSignature::Woff | FontSignature::Sfnt => Ok(vec![FontData::from_bytes(content)?]),
+        }
     }
 }
 
+fn parse_optional_table<'a, T>(tables: &'a FontTables, tag: &str) -> Option<T>
+where
+    T: TryFrom<&'a [u8], Error = eyre::Report>,
+{
+    let data = tables.get(tag)?;
+
+    T::try_from(data.as_slice())
+        .tap_err(|err| tracing::warn!(error = ?err, "Unable to parse {} table", tag))
+        .ok()
+}
+
+// Like parse_optional_table, for tables that refer to strings in the name table
+fn parse_optional_named_table<T>(
+    tables: &FontTables,
+    tag: &str,
+    name_table: &NameTable,
+    parse: fn(&[u8], &NameTable) -> Result<T>,
+) -> Option<T> {
+    let data = tables.get(tag)?;
+
+    parse(data, name_table)
+        .tap_err(|err| tracing::warn!(error = ?err, "Unable to parse {} table", tag))
+        .ok()
+}
+
 #[cfg(test)]
 mod tests {
 
     use eyre::Result;
 
-    use super::FontData;
+    use super::{has_font_signature, FontData};
+    use crate::font_parser::{
+        sfnt_parser::get_sfnt_table_directory,
+        table_directory::get_font_tables,
+        tables::{HeadTable, HheaTable, Os2Table, PostTable},
+        variations::{AxisValueKind, VariationAxis},
+    };
 
-    #[test]
-    fn get_font_data_from_woff() -> Result<()> {
-        let font_data = FontData::from_filepath("test_files/test_font_1.woff")?;
-
-        let expected_results = FontData {
+    // test_font_1 in every format
+    fn univers_else() -> FontData {
+        FontData {
             family_name: "Univers Else".to_owned(),
             sub_family_name: "Regular".to_owned(),
             identifier: "webfont".to_owned(),
             full_name: "Univers Else Regular".to_owned(),
-        };
-
-        assert_eq!(font_data, expected_results);
-
-        let font_data = FontData::from_filepath("test_files/test_font_2.woff")?;
+            copyright: Some("Pierre Huyghebaert (Typography, initiative, testing)\nPierre Marchand (Development and typography, Fonzie software)\nDelphine Platteeuw (Design and testing)\nGregoire Vigneron (Scanning and assembling)\n\nOSP: December 2010 - Version 1.0 (http://ospublish.constantvzw.org/foundry/univers-else)\n\nUnivers Else is released under the OFL 1.1 -- http://scripts.sil.org/OFL\n\nFor information on what you're allowed to change or modify, \nconsult the OFL-1.1.txt and OFL-FAQ.txt files.  The OFL-FAQ also gives a very general rationale and various recommendations regarding why you would want to contribute to the project or make your own version of the font.".to_owned()),
+            version: Some("Version 001.000".to_owned()),
+            postscript_name: Some("UniversElse-Regular".to_owned()),
+            trademark: None,
+            manufacturer: None,
+            designer: None,
+            description: None,
+            vendor_url: None,
+            designer_url: None,
+            license: None,
+            license_url: None,
+            localized_names: vec![],
+            os2: Some(Os2Table {
+                version: 3,
+                weight_class: 400,
+                width_class: 5,
+                fs_selection: 0x40,
+                vendor_id: "PfEd".to_owned(),
+                panose: [2, 0, 5, 3, 0, 0, 0, 0, 0, 0],
+                typo_ascender: 800,
+                typo_descender: -200,
+                typo_line_gap: 0,
+                x_height: Some(527),
+                cap_height: Some(748),
+            }),
+            head: Some(HeadTable {
+                font_revision: 1.0,
+                units_per_em: 1000,
+                created: 1314015786,
+                modified: 1314015786,
+                mac_style: 0,
+            }),
+            hhea: Some(HheaTable {
+                ascender: 1117,
+                descender: -252,
+                line_gap: 0,
+                advance_width_max: 1564,
+            }),
+            post: Some(PostTable {
+                italic_angle: 0.0,
+                underline_position: -325,
+                underline_thickness: 50,
+                is_fixed_pitch: false,
+            }),
+            fvar: None,
+            stat: None,
+            has_avar: false,
+            fingerprint: "d3413fa6510f073fda895054fba02c85f51900faca3c38742ed01914c4a92b3b".to_owned(),
+            identity_key: "universelse-regular|1.000|pfed".to_owned(),
+        }
+    }
 
-        let expected_results = FontData {
+    // test_font_2 in every format
+    fn adieu() -> FontData {
+        FontData {
             family_name: "Adieu".to_owned(),
             sub_family_name: "Regular".to_owned(),
             identifier: "3.100;UKWN;Adieu-Regular".to_owned(),
             full_name: "Adieu Regular".to_owned(),
-        };
+            copyright: Some(
+                "Copyright © 2017 by Good Type Foundry Kenneth Knutsen. All rights reserved."
+                    .to_owned(),
+            ),
+            version: Some("Version 3.100;hotconv 1.0.109;makeotfexe 2.5.65596".to_owned()),
+            postscript_name: Some("Adieu-Regular".to_owned()),
+            trademark: None,
+            manufacturer: Some("Good Type Foundry".to_owned()),
+            designer: Some("Good Type Foundry Kenneth Knutsen".to_owned()),
+            description: None,
+            vendor_url: Some("goodtypefoundry.com".to_owned()),
+            designer_url: Some("goodtypefoundry.com".to_owned()),
+            license: None,
+            license_url: None,
+            localized_names: vec![],
+            os2: Some(Os2Table {
+                version: 3,
+                weight_class: 400,
+                width_class: 5,
+                fs_selection: 0x40,
+                vendor_id: "UKWN".to_owned(),
+                panose: [0, 0, 5, 0, 0, 0, 0, 0, 0, 0],
+                typo_ascender: 700,
+                typo_descender: -200,
+                typo_line_gap: 300,
+                x_height: Some(500),
+                cap_height: Some(700),
+            }),
+            head: Some(HeadTable {
+                font_revision: 3.0999908447265625,
+                units_per_em: 1000,
+                created: 1638368640,
+                modified: 1638368640,
+                mac_style: 0,
+            }),
+            hhea: Some(HheaTable {
+                ascender: 1000,
+                descender: -200,
+                line_gap: 0,
+                advance_width_max: 1751,
+            }),
+            post: Some(PostTable {
+                italic_angle: 0.0,
+                underline_position: -75,
+                underline_thickness: 50,
+                is_fixed_pitch: false,
+            }),
+            fvar: None,
+            stat: None,
+            has_avar: false,
+            fingerprint: "91e2434dadadeb60430532b61db321ee639295d46d00c36bdd4615a9009e7273"
+                .to_owned(),
+            identity_key: "adieu-regular|3.100|ukwn".to_owned(),
+        }
+    }
+
+    #[test]
+    fn get_font_data_from_woff() -> Result<()> {
+        let font_data = FontData::from_filepath("test_files/test_font_1.woff")?;
+
+        assert_eq!(font_data, univers_else());
+
+        let font_data = FontData::from_filepath("test_files/test_font_2.woff")?;
+
+        assert_eq!(font_data, adieu());
 
+        Ok(())
+    }
+
+    #[test]
+    fn get_font_data_from_sfnt() -> Result<()> {
+        let font_data = FontData::from_filepath("test_files/test_font_1.ttf")?;
+
+        assert_eq!(font_data, univers_else());
+
+        let font_data = FontData::from_filepath("test_files/test_font_2.otf")?;
+
+        assert_eq!(font_data, adieu());
+
+        Ok(())
+    }
+
+    #[test]
+    fn leave_out_truncated_head() -> Result<()> {
+        let content = std::fs::read("test_files/test_font_1.ttf")?;
+        let mut tables = get_font_tables(&content, &get_sfnt_table_directory(&content, 0)?)?;
+        tables.insert("head".to_owned(), vec![0; 12]);
+
+        let font_data = FontData::from_tables(&tables)?;
+
+        assert_eq!(font_data.head, None);
+        assert_eq!(font_data.full_name, univers_else().full_name);
+        assert_ne!(font_data.fingerprint, univers_else().fingerprint);
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_font_data_from_woff2() -> Result<()> {
+        // Same fonts as the woff tests. test_font_1 has transformed glyf and loca tables
+        let font_data = FontData::from_filepath("test_files/test_font_1.woff2")?;
+
+        assert_eq!(font_data, univers_else());
+
+        // And with a transformed hmtx table as well
+        let font_data = FontData::from_filepath("test_files/test_font_1_hmtx.woff2")?;
+
+        assert_eq!(font_data, univers_else());
+
+        let font_data = FontData::from_filepath("test_files/test_font_2.woff2")?;
+
+        assert_eq!(font_data, adieu());
+
+        Ok(())
+    }
+
+    #[test]
+    fn recognise_font_signatures() -> Result<()> {
+        for filepath in [
+            "test_files/test_font_1.ttf",
+            "test_files/test_font_1.woff",
+            "test_files/test_font_1.woff2",
+            "test_files/test_font_2.otf",
+            "test_files/test_collection.ttc",
+        ] {
+            assert!(
+                has_font_signature(&std::fs::read(filepath)?),
+                "{}",
+                filepath
+            );
+        }
+
+        assert!(!has_font_signature(b"<!DOCTYPE html>"));
+        assert!(!has_font_signature(b"wOF"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_font_data_from_collection() -> Result<()> {
+        let expected_results = vec![univers_else(), adieu()];
+
+        let font_data = FontData::all_from_filepath("test_files/test_collection.ttc")?;
         assert_eq!(font_data, expected_results);
 
+        let font_data = FontData::all_from_filepath("test_files/test_collection.woff2")?;
+        assert_eq!(font_data, expected_results);
+
+        // Single font files are a collection of one
+        let font_data = FontData::all_from_filepath("test_files/test_font_2.woff")?;
+        assert_eq!(font_data, expected_results[1..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_style_from_os2() -> Result<()> {
+        let mut font_data = FontData::from_filepath("test_files/test_font_1.woff")?;
+        assert_eq!(font_data.style().as_deref(), Some("Regular"));
+
+        let os2 = font_data.os2.as_mut().expect("font has an OS/2 table");
+        assert!(os2.is_regular());
+        assert!(!os2.is_bold());
+        assert!(!os2.is_italic());
+        assert!(!os2.is_oblique());
+
+        // Bold and italic
+        os2.fs_selection = 0x0021;
+        assert_eq!(font_data.style().as_deref(), Some("Bold Italic"));
+
+        // A Light weight sets none of the bits
+        font_data
+            .os2
+            .as_mut()
+            .expect("font has an OS/2 table")
+            .fs_selection = 0;
+        assert_eq!(font_data.style(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_variations_from_variable_font() -> Result<()> {
+        // test_font_1 with wght and wdth axes added. NRKSans_Variable.woff2, which
+        // test_nrk.css links to, is not among the test files.
+        let font_data = FontData::from_filepath("test_files/test_variable_font.woff2")?;
+
+        assert!(font_data.is_variable());
+        assert!(font_data.has_avar);
+
+        let fvar = font_data.fvar.expect("font has an fvar table");
+        assert_eq!(
+            fvar.axes,
+            vec![
+                VariationAxis {
+                    tag: "wght".to_owned(),
+                    name: Some("Weight".to_owned()),
+                    min_value: 100.0,
+                    default_value: 400.0,
+                    max_value: 900.0,
+                    hidden: false,
+                },
+                VariationAxis {
+                    tag: "wdth".to_owned(),
+                    name: Some("Width".to_owned()),
+                    min_value: 75.0,
+                    default_value: 100.0,
+                    max_value: 100.0,
+                    hidden: false,
+                },
+            ]
+        );
+
+        let instance_names: Vec<&str> = fvar
+            .instances
+            .iter()
+            .filter_map(|instance| instance.sub_family_name.as_deref())
+            .collect();
+        assert_eq!(
+            instance_names,
+            vec!["Thin", "Regular", "Bold", "Black", "Condensed Bold"]
+        );
+
+        let condensed_bold = &fvar.instances[4];
+        assert_eq!(
+            condensed_bold.coordinates,
+            vec![("wght".to_owned(), 700.0), ("wdth".to_owned(), 75.0)]
+        );
+        assert_eq!(
+            condensed_bold.postscript_name.as_deref(),
+            Some("UniversElse-CondensedBold")
+        );
+        assert_eq!(fvar.instances[0].postscript_name, None);
+
+        let stat = font_data.stat.expect("font has a STAT table");
+        assert_eq!(stat.design_axes.len(), 2);
+        assert_eq!(stat.elided_fallback_name.as_deref(), Some("Regular"));
+        assert_eq!(stat.axis_values.len(), 7);
+
+        let regular = &stat.axis_values[1];
+        assert_eq!(regular.name.as_deref(), Some("Regular"));
+        assert!(regular.elidable);
+        assert_eq!(
+            regular.kind,
+            AxisValueKind::Linked {
+                axis_tag: "wght".to_owned(),
+                value: 400.0,
+                linked_value: 700.0
+            }
+        );
+
+        assert_eq!(
+            stat.axis_values[4].kind,
+            AxisValueKind::Range {
+                axis_tag: "wdth".to_owned(),
+                nominal_value: 75.0,
+                min_value: 75.0,
+                max_value: 87.5
+            }
+        );
+        assert_eq!(
+            stat.axis_values[6].kind,
+            AxisValueKind::Multiple {
+                values: vec![("wght".to_owned(), 700.0), ("wdth".to_owned(), 75.0)]
+            }
+        );
+
         Ok(())
     }
 }
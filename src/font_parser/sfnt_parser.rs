@@ -0,0 +1,88 @@
+use eyre::{eyre, Result};
+
+use super::{
+    table_directory::{
+        get_font_tables, get_table_directory, FontTables, TableDirectoryEntry,
+        SFNT_TABLE_RECORD_LENGTH,
+    },
+    FontData,
+};
+
+// Parses a bare OpenType/TrueType font. This is the same layout WOFF wraps, only
+// without compression, so the tables can be read straight from the file.
+pub fn parse_sfnt(content: &[u8]) -> Result<FontData> {
+    parse_sfnt_face(content, 0)
+}
+
+// Parses every face in a TrueType/OpenType collection
+pub fn parse_sfnt_collection(content: &[u8]) -> Result<Vec<FontData>> {
+    get_collection_offsets(content)?
+        .into_iter()
+        .map(|offset| parse_sfnt_face(content, offset))
+        .collect()
+}
+
+fn parse_sfnt_face(content: &[u8], start: usize) -> Result<FontData> {
+    let entries: Vec<TableDirectoryEntry> = get_sfnt_table_directory(content, start)?;
+
+    let tables: FontTables = get_font_tables(content, &entries)?;
+
+    FontData::from_tables(&tables)
+}
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/otff#organization-of-an-opentype-font
+
+// TableDirectory
+// 0-4      uint32      sfntVersion	    0x00010000, 'OTTO' or 'true'
+// 4-6      uint16      numTables	    Number of tables.
+// 6-8      uint16      searchRange	    Maximum power of 2 less than or equal to numTables, times 16.
+// 8-10     uint16      entrySelector	Log2 of the maximum power of 2 less than or equal to numTables.
+// 10-12    uint16      rangeShift	    numTables times 16, minus searchRange.
+// 12-      TableRecord tableRecords[numTables]
+
+const SFNT_HEADER_LENGTH: usize = 12;
+
+// Reads the table directory starting at `start`. Table offsets in a sfnt are
+// always relative to the beginning of the file.
+pub fn get_sfnt_table_directory(content: &[u8], start: usize) -> Result<Vec<TableDirectoryEntry>> {
+    let num_tables: u16 = content
+        .get(start + 4..start + 6)
+        .ok_or_else(|| eyre!("sfnt header is too short"))
+        .map(|value| u16::from_be_bytes([value[0], value[1]]))?;
+
+    get_table_directory(
+        content,
+        start + SFNT_HEADER_LENGTH,
+        num_tables,
+        SFNT_TABLE_RECORD_LENGTH,
+        TableDirectoryEntry::from_sfnt_record,
+    )
+}
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/otff#font-collections
+
+// TTCHeader
+// 0-4      TAG         ttcTag	                    'ttcf'
+// 4-6      uint16      majorVersion	            1 or 2
+// 6-8      uint16      minorVersion	            0
+// 8-12     uint32      numFonts	                Number of fonts in the collection.
+// 12-      Offset32    tableDirectoryOffsets[numFonts]	Offset to each font's table directory from the beginning of the file.
+//
+// Version 2 adds DSIG fields after the offsets, which we don't need.
+
+fn get_collection_offsets(content: &[u8]) -> Result<Vec<usize>> {
+    let num_fonts: u32 = content
+        .get(8..12)
+        .ok_or_else(|| eyre!("ttc header is too short"))
+        .map(|value| u32::from_be_bytes([value[0], value[1], value[2], value[3]]))?;
+
+    (0..num_fonts as usize)
+        .map(|i| {
+            let index = 12 + i * 4;
+            content
+                .get(index..index + 4)
+                .ok_or_else(|| eyre!("ttc table directory offsets are out of bounds"))
+                .map(|value| u32::from_be_bytes([value[0], value[1], value[2], value[3]]) as usize)
+        })
+        .collect()
+}
@@ -0,0 +1,121 @@
+use eyre::{eyre, Result};
+use flate2::read::ZlibDecoder;
+use std::{collections::HashMap, io::Read};
+
+// Uncompressed table data by tag. This is what every container format is
+// decoded into before the tables themselves are parsed.
+pub type FontTables = HashMap<String, Vec<u8>>;
+
+// https://www.w3.org/TR/WOFF/#TableDirectory
+// https://learn.microsoft.com/en-us/typography/opentype/spec/otff#table-directory
+
+// WOFF TableDirectoryEntry (20 bytes)
+// 0-4      UInt32	tag	            4-byte sfnt table identifier.
+// 4-8      UInt32	offset	        Offset to the data, from beginning of WOFF file.
+// 8-12     UInt32	compLength	    Length of the compressed data, excluding padding.
+// 12-16    UInt32	origLength	    Length of the uncompressed table, excluding padding.
+// 16-20    UInt32	origChecksum	Checksum of the uncompressed table.
+
+// sfnt TableRecord (16 bytes)
+// 0-4      Tag	        tableTag	Table identifier.
+// 4-8      uint32	    checksum	Checksum for this table.
+// 8-12     Offset32	offset	    Offset from beginning of font file.
+// 12-16    uint32	    length	    Length of this table.
+
+pub const WOFF_TABLE_DIRECTORY_ENTRY_LENGTH: usize = 20;
+pub const SFNT_TABLE_RECORD_LENGTH: usize = 16;
+
+// A table in a sfnt based font file. Tables stored uncompressed, which is always
+// the case for sfnt files, have comp_length equal to orig_length.
+#[derive(Debug)]
+pub struct TableDirectoryEntry {
+    pub tag: String,
+    pub offset: usize,
+    pub comp_length: usize,
+    pub orig_length: usize,
+}
+
+impl TableDirectoryEntry {
+    pub fn from_woff_entry(value: &[u8]) -> Result<Self> {
+        let value = value
+            .get(0..WOFF_TABLE_DIRECTORY_ENTRY_LENGTH)
+            .ok_or_else(|| eyre!("Table directory entry is out of bounds"))?;
+
+        let tag = std::str::from_utf8(&value[0..4])?;
+        let offset: u32 = u32::from_be_bytes(value[4..8].try_into()?);
+        let comp_length: u32 = u32::from_be_bytes(value[8..12].try_into()?);
+        let orig_length: u32 = u32::from_be_bytes(value[12..16].try_into()?);
+
+        Ok(TableDirectoryEntry {
+            tag: tag.to_owned(),
+            offset: offset as usize,
+            comp_length: comp_length as usize,
+            orig_length: orig_length as usize,
+        })
+    }
+
+    pub fn from_sfnt_record(value: &[u8]) -> Result<Self> {
+        let value = value
+            .get(0..SFNT_TABLE_RECORD_LENGTH)
+            .ok_or_else(|| eyre!("Table record is out of bounds"))?;
+
+        let tag = std::str::from_utf8(&value[0..4])?;
+        let offset: u32 = u32::from_be_bytes(value[8..12].try_into()?);
+        let length: u32 = u32::from_be_bytes(value[12..16].try_into()?);
+
+        Ok(TableDirectoryEntry {
+            tag: tag.to_owned(),
+            offset: offset as usize,
+            comp_length: length as usize,
+            orig_length: length as usize,
+        })
+    }
+}
+
+// Reads `num_tables` entries of `entry_length` bytes starting at `start`
+pub fn get_table_directory(
+    content: &[u8],
+    start: usize,
+    num_tables: u16,
+    entry_length: usize,
+    parse_entry: fn(&[u8]) -> Result<TableDirectoryEntry>,
+) -> Result<Vec<TableDirectoryEntry>> {
+    (0..num_tables as usize)
+        .map(|i| {
+            let index = start + i * entry_length;
+            content
+                .get(index..index + entry_length)
+                .ok_or_else(|| eyre!("Table directory is out of bounds"))
+                .and_then(parse_entry)
+        })
+        .collect()
+}
+
+pub fn get_font_tables(data: &[u8], entries: &[TableDirectoryEntry]) -> Result<FontTables> {
+    entries
+        .iter()
+        .map(|entry| Ok((entry.tag.to_owned(), get_table_data(data, entry)?)))
+        .collect()
+}
+
+// Returns the uncompressed table data for the entry
+pub fn get_table_data(data: &[u8], entry: &TableDirectoryEntry) -> Result<Vec<u8>> {
+    let table = data
+        .get(entry.offset..entry.offset + entry.comp_length)
+        .ok_or_else(|| eyre!("Table {} is out of bounds", entry.tag))?;
+
+    // decompress data with zlib decoder if comp_length != orig_length
+    if entry.comp_length == entry.orig_length {
+        return Ok(table.to_owned());
+    }
+
+    // Not preallocated, since origLength is whatever the file claims
+    let mut table_data = vec![];
+
+    ZlibDecoder::new(table)
+        .take(entry.orig_length as u64)
+        .read_to_end(&mut table_data)
+        .map_err(|err| eyre!(err))?;
+
+    Ok(table_data)
+}
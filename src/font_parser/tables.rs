@@ -0,0 +1,219 @@
+use eyre::{eyre, Result};
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/os2
+// https://learn.microsoft.com/en-us/typography/opentype/spec/head
+// https://learn.microsoft.com/en-us/typography/opentype/spec/hhea
+// https://learn.microsoft.com/en-us/typography/opentype/spec/post
+
+// Data types
+// Fixed            32-bit signed fixed-point number (16.16)
+// FWORD            int16 that describes a quantity in font design units
+// UFWORD           uint16 that describes a quantity in font design units
+// LONGDATETIME     Date and time represented in number of seconds since 12:00 midnight, January 1, 1904, UTC.
+
+// Seconds between 1904-01-01 and 1970-01-01
+const LONGDATETIME_UNIX_OFFSET: i64 = 2_082_844_800;
+
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .ok_or_else(|| eyre!("Reading u16 at {} is out of bounds", offset))
+        .map(|value| u16::from_be_bytes([value[0], value[1]]))
+}
+
+pub fn read_i16(data: &[u8], offset: usize) -> Result<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .ok_or_else(|| eyre!("Reading u32 at {} is out of bounds", offset))
+        .map(|value| u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+pub fn read_fixed(data: &[u8], offset: usize) -> Result<f64> {
+    read_u32(data, offset).map(|value| value as i32 as f64 / 65536.0)
+}
+
+pub fn read_tag(data: &[u8], offset: usize) -> Result<String> {
+    let tag = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| eyre!("Reading tag at {} is out of bounds", offset))?;
+
+    Ok(String::from_utf8_lossy(tag).to_string())
+}
+
+// Returns the date as seconds since the unix epoch
+fn read_long_date_time(data: &[u8], offset: usize) -> Result<i64> {
+    let value = data
+        .get(offset..offset + 8)
+        .ok_or_else(|| eyre!("Reading LONGDATETIME at {} is out of bounds", offset))?;
+
+    Ok(i64::from_be_bytes(value.try_into()?) - LONGDATETIME_UNIX_OFFSET)
+}
+
+// OS/2 (only the fields we use)
+// 0-2      uint16      version
+// 4-6      uint16      usWeightClass
+// 6-8      uint16      usWidthClass
+// 32-42    uint8       panose[10]
+// 58-62    Tag         achVendID
+// 62-64    uint16      fsSelection
+// 68-70    FWORD       sTypoAscender
+// 70-72    FWORD       sTypoDescender
+// 72-74    FWORD       sTypoLineGap
+// 86-88    FWORD       sxHeight            version 2 and up
+// 88-90    FWORD       sCapHeight          version 2 and up
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Os2Table {
+    pub version: u16,
+    // 100 (Thin) to 900 (Black), 400 is Regular
+    pub weight_class: u16,
+    // 1 (Ultra-condensed) to 9 (Ultra-expanded), 5 is Normal
+    pub width_class: u16,
+    pub fs_selection: u16,
+    pub vendor_id: String,
+    pub panose: [u8; 10],
+    pub typo_ascender: i16,
+    pub typo_descender: i16,
+    pub typo_line_gap: i16,
+    pub x_height: Option<i16>,
+    pub cap_height: Option<i16>,
+}
+
+// fsSelection bits
+impl Os2Table {
+    pub fn is_italic(&self) -> bool {
+        self.fs_selection & 0x0001 != 0
+    }
+
+    pub fn is_bold(&self) -> bool {
+        self.fs_selection & 0x0020 != 0
+    }
+
+    pub fn is_regular(&self) -> bool {
+        self.fs_selection & 0x0040 != 0
+    }
+
+    pub fn is_oblique(&self) -> bool {
+        self.fs_selection & 0x0200 != 0
+    }
+}
+
+impl TryFrom<&[u8]> for Os2Table {
+    type Error = eyre::Report;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let version = read_u16(data, 0)?;
+
+        let panose: [u8; 10] = data
+            .get(32..42)
+            .ok_or_else(|| eyre!("OS/2 panose is out of bounds"))?
+            .try_into()?;
+
+        let (x_height, cap_height) = match version {
+            0 | 1 => (None, None),
+            _ => (Some(read_i16(data, 86)?), Some(read_i16(data, 88)?)),
+        };
+
+        Ok(Os2Table {
+            version,
+            weight_class: read_u16(data, 4)?,
+            width_class: read_u16(data, 6)?,
+            fs_selection: read_u16(data, 62)?,
+            vendor_id: read_tag(data, 58)?.trim_end().replace('\0', ""),
+            panose,
+            typo_ascender: read_i16(data, 68)?,
+            typo_descender: read_i16(data, 70)?,
+            typo_line_gap: read_i16(data, 72)?,
+            x_height,
+            cap_height,
+        })
+    }
+}
+
+// head (only the fields we use)
+// 4-8      Fixed           fontRevision
+// 18-20    uint16          unitsPerEm
+// 20-28    LONGDATETIME    created
+// 28-36    LONGDATETIME    modified
+// 44-46    uint16          macStyle
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadTable {
+    pub font_revision: f64,
+    pub units_per_em: u16,
+    // Seconds since the unix epoch
+    pub created: i64,
+    // Seconds since the unix epoch
+    pub modified: i64,
+    pub mac_style: u16,
+}
+
+impl TryFrom<&[u8]> for HeadTable {
+    type Error = eyre::Report;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Ok(HeadTable {
+            font_revision: read_fixed(data, 4)?,
+            units_per_em: read_u16(data, 18)?,
+            created: read_long_date_time(data, 20)?,
+            modified: read_long_date_time(data, 28)?,
+            mac_style: read_u16(data, 44)?,
+        })
+    }
+}
+
+// hhea (only the fields we use)
+// 4-6      FWORD       ascender
+// 6-8      FWORD       descender
+// 8-10     FWORD       lineGap
+// 10-12    UFWORD      advanceWidthMax
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HheaTable {
+    pub ascender: i16,
+    pub descender: i16,
+    pub line_gap: i16,
+    pub advance_width_max: u16,
+}
+
+impl TryFrom<&[u8]> for HheaTable {
+    type Error = eyre::Report;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Ok(HheaTable {
+            ascender: read_i16(data, 4)?,
+            descender: read_i16(data, 6)?,
+            line_gap: read_i16(data, 8)?,
+            advance_width_max: read_u16(data, 10)?,
+        })
+    }
+}
+
+// post (only the fields we use)
+// 4-8      Fixed       italicAngle         Counter-clockwise degrees from the vertical.
+// 8-10     FWORD       underlinePosition
+// 10-12    FWORD       underlineThickness
+// 12-16    uint32      isFixedPitch        0 if proportionally spaced, non-zero if monospaced.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostTable {
+    pub italic_angle: f64,
+    pub underline_position: i16,
+    pub underline_thickness: i16,
+    pub is_fixed_pitch: bool,
+}
+
+impl TryFrom<&[u8]> for PostTable {
+    type Error = eyre::Report;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Ok(PostTable {
+            italic_angle: read_fixed(data, 4)?,
+            underline_position: read_i16(data, 8)?,
+            underline_thickness: read_i16(data, 10)?,
+            is_fixed_pitch: read_u32(data, 12)? != 0,
+        })
+    }
+}
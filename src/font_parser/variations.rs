@@ -0,0 +1,298 @@
+use eyre::{eyre, Result};
+
+use super::{
+    name_table::NameTable,
+    tables::{read_fixed, read_tag, read_u16, read_u32},
+};
+
+// https://learn.microsoft.com/en-us/typography/opentype/spec/fvar
+// https://learn.microsoft.com/en-us/typography/opentype/spec/stat
+// https://learn.microsoft.com/en-us/typography/opentype/spec/avar
+
+// Axis, instance and axis value names live in the name table, so these tables are
+// parsed with it at hand and the names resolved up front.
+
+// fvar header
+// 0-2      uint16      majorVersion
+// 2-4      uint16      minorVersion
+// 4-6      Offset16    axesArrayOffset     Offset from the start of the table to the axes
+// 6-8      uint16      reserved
+// 8-10     uint16      axisCount
+// 10-12    uint16      axisSize            Size of each VariationAxisRecord (20)
+// 12-14    uint16      instanceCount
+// 14-16    uint16      instanceSize        Size of each InstanceRecord
+
+// VariationAxisRecord
+// 0-4      Tag         axisTag
+// 4-8      Fixed       minValue
+// 8-12     Fixed       defaultValue
+// 12-16    Fixed       maxValue
+// 16-18    uint16      flags               0x0001 HIDDEN_AXIS
+// 18-20    uint16      axisNameID
+
+// InstanceRecord (follows directly after the axes)
+// 0-2      uint16      subfamilyNameID
+// 2-4      uint16      flags               Reserved
+// 4-       Fixed       coordinates[axisCount]
+//          uint16      postScriptNameID    Only if instanceSize is axisCount * 4 + 6
+
+const HIDDEN_AXIS: u16 = 0x0001;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FvarTable {
+    pub axes: Vec<VariationAxis>,
+    pub instances: Vec<NamedInstance>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    // e.g. wght, wdth, ital, slnt, opsz
+    pub tag: String,
+    pub name: Option<String>,
+    pub min_value: f64,
+    pub default_value: f64,
+    pub max_value: f64,
+    // Hidden axes are not meant to be shown in user interfaces
+    pub hidden: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedInstance {
+    pub sub_family_name: Option<String>,
+    pub postscript_name: Option<String>,
+    // Axis tag and value, in the same order as the axes
+    pub coordinates: Vec<(String, f64)>,
+}
+
+impl FvarTable {
+    pub fn parse(data: &[u8], name_table: &NameTable) -> Result<Self> {
+        let axes_offset: usize = read_u16(data, 4)?.into();
+        let axis_count: usize = read_u16(data, 8)?.into();
+        let axis_size: usize = read_u16(data, 10)?.into();
+        let instance_count: usize = read_u16(data, 12)?.into();
+        let instance_size: usize = read_u16(data, 14)?.into();
+
+        if axis_size < 20 || instance_size < axis_count * 4 + 4 {
+            return Err(eyre!("fvar record sizes are invalid"));
+        }
+
+        let axes: Vec<VariationAxis> = (0..axis_count)
+            .map(|i| {
+                let position = axes_offset + i * axis_size;
+
+                Ok(VariationAxis {
+                    tag: read_tag(data, position)?,
+                    name: get_name(name_table, read_u16(data, position + 18)?),
+                    min_value: read_fixed(data, position + 4)?,
+                    default_value: read_fixed(data, position + 8)?,
+                    max_value: read_fixed(data, position + 12)?,
+                    hidden: read_u16(data, position + 16)? & HIDDEN_AXIS != 0,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let instances_offset = axes_offset + axis_count * axis_size;
+        let has_postscript_name = instance_size >= axis_count * 4 + 6;
+
+        let instances: Vec<NamedInstance> = (0..instance_count)
+            .map(|i| {
+                let position = instances_offset + i * instance_size;
+
+                let coordinates = axes
+                    .iter()
+                    .enumerate()
+                    .map(|(j, axis)| {
+                        Ok((axis.tag.to_owned(), read_fixed(data, position + 4 + j * 4)?))
+                    })
+                    .collect::<Result<_>>()?;
+
+                let postscript_name = match has_postscript_name {
+                    true => get_name(name_table, read_u16(data, position + 4 + axis_count * 4)?),
+                    false => None,
+                };
+
+                Ok(NamedInstance {
+                    sub_family_name: get_name(name_table, read_u16(data, position)?),
+                    postscript_name,
+                    coordinates,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(FvarTable { axes, instances })
+    }
+}
+
+// STAT header
+// 0-2      uint16      majorVersion
+// 2-4      uint16      minorVersion
+// 4-6      uint16      designAxisSize              Size of each AxisRecord (8)
+// 6-8      uint16      designAxisCount
+// 8-12     Offset32    designAxesOffset            Offset from the start of the table
+// 12-14    uint16      axisValueCount
+// 14-18    Offset32    offsetToAxisValueOffsets    Offset from the start of the table to an Offset16 array
+// 18-20    uint16      elidedFallbackNameID        Version 1.1 and up
+
+// AxisRecord
+// 0-4      Tag         axisTag
+// 4-6      uint16      axisNameID
+// 6-8      uint16      axisOrdering
+
+// AxisValue tables start with format, then (for format 1-3) axisIndex, flags and
+// valueNameID. Offsets are from the start of the Offset16 array.
+// Format 1     Fixed value
+// Format 2     Fixed nominalValue, Fixed rangeMinValue, Fixed rangeMaxValue
+// Format 3     Fixed value, Fixed linkedValue
+// Format 4     uint16 axisCount, uint16 flags, uint16 valueNameID, AxisValueRecord[axisCount]
+//              where AxisValueRecord is uint16 axisIndex, Fixed value
+
+const ELIDABLE_AXIS_VALUE_NAME: u16 = 0x0002;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatTable {
+    pub design_axes: Vec<DesignAxis>,
+    pub axis_values: Vec<AxisValue>,
+    // Name to use when every axis value name is elided, usually "Regular"
+    pub elided_fallback_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesignAxis {
+    pub tag: String,
+    pub name: Option<String>,
+    // Order the axis value names are combined in when naming a style
+    pub ordering: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisValue {
+    pub name: Option<String>,
+    // Elidable names, like "Regular" or "Normal", are left out of style names
+    pub elidable: bool,
+    pub kind: AxisValueKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AxisValueKind {
+    // Format 1
+    Single {
+        axis_tag: String,
+        value: f64,
+    },
+    // Format 2
+    Range {
+        axis_tag: String,
+        nominal_value: f64,
+        min_value: f64,
+        max_value: f64,
+    },
+    // Format 3, e.g. Regular (400) linked to Bold (700)
+    Linked {
+        axis_tag: String,
+        value: f64,
+        linked_value: f64,
+    },
+    // Format 4
+    Multiple {
+        values: Vec<(String, f64)>,
+    },
+}
+
+impl StatTable {
+    pub fn parse(data: &[u8], name_table: &NameTable) -> Result<Self> {
+        let minor_version = read_u16(data, 2)?;
+        let design_axis_size: usize = read_u16(data, 4)?.into();
+        let design_axis_count: usize = read_u16(data, 6)?.into();
+        let design_axes_offset = read_u32(data, 8)? as usize;
+        let axis_value_count: usize = read_u16(data, 12)?.into();
+        let axis_value_offsets = read_u32(data, 14)? as usize;
+
+        let design_axes: Vec<DesignAxis> = (0..design_axis_count)
+            .map(|i| {
+                let position = design_axes_offset + i * design_axis_size;
+
+                Ok(DesignAxis {
+                    tag: read_tag(data, position)?,
+                    name: get_name(name_table, read_u16(data, position + 4)?),
+                    ordering: read_u16(data, position + 6)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let axis_tag = |axis_index: u16| -> Result<String> {
+            design_axes
+                .get(axis_index as usize)
+                .map(|axis| axis.tag.to_owned())
+                .ok_or_else(|| eyre!("STAT axis index {} is out of bounds", axis_index))
+        };
+
+        let axis_values: Vec<AxisValue> = (0..axis_value_count)
+            .map(|i| {
+                let offset: usize = read_u16(data, axis_value_offsets + i * 2)?.into();
+                let position = axis_value_offsets + offset;
+
+                let format = read_u16(data, position)?;
+                let kind = match format {
+                    1 => AxisValueKind::Single {
+                        axis_tag: axis_tag(read_u16(data, position + 2)?)?,
+                        value: read_fixed(data, position + 8)?,
+                    },
+                    2 => AxisValueKind::Range {
+                        axis_tag: axis_tag(read_u16(data, position + 2)?)?,
+                        nominal_value: read_fixed(data, position + 8)?,
+                        min_value: read_fixed(data, position + 12)?,
+                        max_value: read_fixed(data, position + 16)?,
+                    },
+                    3 => AxisValueKind::Linked {
+                        axis_tag: axis_tag(read_u16(data, position + 2)?)?,
+                        value: read_fixed(data, position + 8)?,
+                        linked_value: read_fixed(data, position + 12)?,
+                    },
+                    4 => {
+                        let axis_count: usize = read_u16(data, position + 2)?.into();
+                        let values = (0..axis_count)
+                            .map(|j| {
+                                let record = position + 8 + j * 6;
+                                Ok((
+                                    axis_tag(read_u16(data, record)?)?,
+                                    read_fixed(data, record + 2)?,
+                                ))
+                            })
+                            .collect::<Result<_>>()?;
+
+                        AxisValueKind::Multiple { values }
+                    }
+                    _ => return Err(eyre!("Unknown STAT axis value format {}", format)),
+                };
+
+                // flags and valueNameID are at the same position in every format
+                let flags = read_u16(data, position + 4)?;
+
+                Ok(AxisValue {
+                    name: get_name(name_table, read_u16(data, position + 6)?),
+                    elidable: flags & ELIDABLE_AXIS_VALUE_NAME != 0,
+                    kind,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let elided_fallback_name = match minor_version {
+            0 => None,
+            _ => get_name(name_table, read_u16(data, 18)?),
+        };
+
+        Ok(StatTable {
+            design_axes,
+            axis_values,
+            elided_fallback_name,
+        })
+    }
+}
+
+// 0xFFFF means no name in fvar
+fn get_name(name_table: &NameTable, name_id: u16) -> Option<String> {
+    match name_id {
+        0xFFFF => None,
+        _ => name_table.get(name_id).map(|name| name.to_owned()),
+    }
+}
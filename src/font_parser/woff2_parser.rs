@@ -0,0 +1,412 @@
+use brotli::Decompressor;
+use eyre::{eyre, Result};
+use std::io::Read;
+
+use super::{table_directory::FontTables, woff2_transforms::reverse_transforms, FontData};
+
+pub fn parse_woff2(content: &[u8]) -> Result<FontData> {
+    parse_woff2_collection(content)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre!("woff2 file contains no fonts"))
+}
+
+// Parses every font in the file. A woff2 file without a collection directory is
+// treated as a collection of one font that uses every table.
+pub fn parse_woff2_collection(content: &[u8]) -> Result<Vec<FontData>> {
+    let header: Woff2Header = content.try_into()?;
+
+    let (entries, mut position) = get_table_directory(content, header.num_tables)?;
+
+    let fonts: Vec<Vec<usize>> = match header.flavor {
+        TTCF_FLAVOR => get_collection_directory(content, &mut position, entries.len())?,
+        _ => vec![(0..entries.len()).collect()],
+    };
+
+    let compressed_end = position + header.total_compressed_size;
+    let compressed = content
+        .get(position..compressed_end)
+        .ok_or_else(|| eyre!("Compressed data block is out of bounds"))?;
+
+    let table_data = decompress_table_data(compressed, &entries)?;
+
+    fonts
+        .iter()
+        .map(|table_indices| {
+            let mut tables: FontTables = table_indices
+                .iter()
+                .map(|&index| get_table_data(&table_data, &entries[index]))
+                .collect::<Result<FontTables>>()?;
+
+            let transformed: Vec<String> = table_indices
+                .iter()
+                .map(|&index| &entries[index])
+                .filter(|entry| entry.transform_length.is_some())
+                .map(|entry| entry.tag.to_owned())
+                .collect();
+
+            reverse_transforms(&mut tables, &transformed)?;
+
+            FontData::from_tables(&tables)
+        })
+        .collect()
+}
+
+// https://www.w3.org/TR/WOFF2/#FileStructure
+// https://github.com/google/woff2/blob/master/src/woff2_dec.cc
+
+// Data types
+// UInt8            8-bit unsigned integer
+// UInt32           32-bit (4-byte) unsigned integer in big-endian format
+// UIntBase128      Variable-length encoding of 32-bit unsigned integers (1-5 bytes)
+
+// WOFF2Header
+// 0-4      UInt32  signature	            0x774F4632 'wOF2'
+// 4-8      UInt32  flavor	                The "sfnt version" of the input font.
+// 8-12     UInt32  length	                Total size of the WOFF file.
+// 12-14    UInt16  numTables	            Number of entries in directory of font tables.
+// 14-16    UInt16  reserved	            Reserved; set to 0.
+// 16-20    UInt32  totalSfntSize	        Total size needed for the uncompressed font data, including the sfnt header, directory, and font tables (including padding).
+// 20-24    UInt32  totalCompressedSize     Total length of the compressed data block.
+// 24-26    UInt16  majorVersion	        Major version of the WOFF file.
+// 26-28    UInt16  minorVersion	        Minor version of the WOFF file.
+// 28-32    UInt32  metaOffset	            Offset to metadata block, from beginning of WOFF file.
+// 32-36    UInt32  metaLength	            Length of compressed metadata block.
+// 36-40    UInt32  metaOrigLength	        Uncompressed size of metadata block.
+// 40-44    UInt32  privOffset	            Offset to private data block, from beginning of WOFF file.
+// 44-48    UInt32  privLength	            Length of private data block.
+
+const WOFF2_HEADER_LENGTH: usize = 48;
+
+// 'ttcf', the flavor used when the file holds a font collection
+const TTCF_FLAVOR: u32 = 0x74746366;
+
+struct Woff2Header {
+    flavor: u32,
+    num_tables: u16,
+    total_compressed_size: usize,
+}
+
+impl TryFrom<&[u8]> for Woff2Header {
+    type Error = eyre::Report;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < WOFF2_HEADER_LENGTH {
+            return Err(eyre!("woff2 header is too short"));
+        }
+
+        let flavor: u32 = u32::from_be_bytes(value[4..8].try_into()?);
+        let num_tables: u16 = u16::from_be_bytes(value[12..14].try_into()?);
+        let total_compressed_size: u32 = u32::from_be_bytes(value[20..24].try_into()?);
+
+        Ok(Woff2Header {
+            flavor,
+            num_tables,
+            total_compressed_size: total_compressed_size as usize,
+        })
+    }
+}
+
+// WOFF2 TableDirectoryEntry
+//      UInt8           flags	            Table type and flags
+//      UInt32	        tag	                4-byte tag (optional)
+//      UIntBase128     origLength	        Length of original table
+//      UIntBase128     transformLength     Transformed length (if applicable)
+//
+// Bits 0-5 of flags index into KNOWN_TAGS, and the value 63 means the tag follows
+// as 4 bytes. Bits 6-7 hold the transformation version. For glyf and loca version 0
+// means the table is transformed, while for every other table version 0 is the null
+// transform. transformLength is only present for transformed tables.
+
+const KNOWN_TAGS: [&str; 63] = [
+    "cmap", "head", "hhea", "hmtx", "maxp", "name", "OS/2", "post", "cvt ", "fpgm", "glyf", "loca",
+    "prep", "CFF ", "VORG", "EBDT", "EBLC", "gasp", "hdmx", "kern", "LTSH", "PCLT", "VDMX", "vhea",
+    "vmtx", "BASE", "GDEF", "GPOS", "GSUB", "EBSC", "JSTF", "MATH", "CBDT", "CBLC", "COLR", "CPAL",
+    "SVG ", "sbix", "acnt", "avar", "bdat", "bloc", "bsln", "cvar", "fdsc", "feat", "fmtx", "fvar",
+    "gvar", "hsty", "just", "lcar", "mort", "morx", "opbd", "prop", "trak", "Zapf", "Silf", "Glat",
+    "Gloc", "Feat", "Sill",
+];
+
+struct Woff2TableDirectoryEntry {
+    tag: String,
+    // Offset into the decompressed table data
+    offset: usize,
+    orig_length: usize,
+    transform_length: Option<usize>,
+}
+
+impl Woff2TableDirectoryEntry {
+    // Number of bytes the table takes up in the decompressed data
+    fn length(&self) -> usize {
+        self.transform_length.unwrap_or(self.orig_length)
+    }
+}
+
+// Returns the table directory entries and the position where the directory ends
+fn get_table_directory(
+    content: &[u8],
+    num_tables: u16,
+) -> Result<(Vec<Woff2TableDirectoryEntry>, usize)> {
+    let mut entries: Vec<Woff2TableDirectoryEntry> = Vec::with_capacity(num_tables.into());
+    let mut position: usize = WOFF2_HEADER_LENGTH;
+    let mut offset: usize = 0;
+
+    for _ in 0..num_tables {
+        let flags: u8 = *content
+            .get(position)
+            .ok_or_else(|| eyre!("Table directory is out of bounds"))?;
+        position += 1;
+
+        let tag: String = match flags & 0x3f {
+            0x3f => {
+                let tag = content
+                    .get(position..position + 4)
+                    .ok_or_else(|| eyre!("Table directory is out of bounds"))?;
+                position += 4;
+                std::str::from_utf8(tag)?.to_owned()
+            }
+            index => KNOWN_TAGS[index as usize].to_owned(),
+        };
+
+        let orig_length = read_uint_base_128(content, &mut position)? as usize;
+
+        let transform_version = flags >> 6;
+        let is_transformed = match tag.as_str() {
+            "glyf" | "loca" => transform_version == 0,
+            _ => transform_version != 0,
+        };
+
+        let transform_length = match is_transformed {
+            true => Some(read_uint_base_128(content, &mut position)? as usize),
+            false => None,
+        };
+
+        let entry = Woff2TableDirectoryEntry {
+            tag,
+            offset,
+            orig_length,
+            transform_length,
+        };
+
+        offset += entry.length();
+        entries.push(entry);
+    }
+
+    Ok((entries, position))
+}
+
+// CollectionHeader, follows directly after the table directory
+//      UInt32          version         0x00010000 or 0x00020000
+//      255UInt16       numFonts        Number of fonts in the collection
+//      CollectionFontEntry fonts[numFonts]
+//
+// CollectionFontEntry
+//      255UInt16       numTables       Number of tables used by this font
+//      UInt32          flavor          The "sfnt version" of the font
+//      255UInt16       index[numTables] Indices into the table directory
+
+// Returns the table directory indices used by each font in the collection
+fn get_collection_directory(
+    content: &[u8],
+    position: &mut usize,
+    num_entries: usize,
+) -> Result<Vec<Vec<usize>>> {
+    // skip version
+    *position += 4;
+
+    let num_fonts = read_255_uint_16(content, position)?;
+
+    (0..num_fonts)
+        .map(|_| {
+            let num_tables = read_255_uint_16(content, position)?;
+
+            // skip flavor
+            *position += 4;
+
+            (0..num_tables)
+                .map(|_| {
+                    let index = read_255_uint_16(content, position)? as usize;
+                    if index >= num_entries {
+                        return Err(eyre!("Collection table index {} is out of bounds", index));
+                    }
+                    Ok(index)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// 255UInt16 uses one byte for small values. The byte values 253, 254 and 255
+// mean a u16 follows, that 506 should be added to the next byte, or that 253
+// should be added to the next byte.
+pub(super) fn read_255_uint_16(content: &[u8], position: &mut usize) -> Result<u16> {
+    let mut next = || -> Result<u16> {
+        let byte = *content
+            .get(*position)
+            .ok_or_else(|| eyre!("255UInt16 is out of bounds"))?;
+        *position += 1;
+        Ok(byte as u16)
+    };
+
+    match next()? {
+        253 => Ok((next()? << 8) | next()?),
+        254 => Ok(next()? + 506),
+        255 => Ok(next()? + 253),
+        code => Ok(code),
+    }
+}
+
+// Transformed tables are returned as they are stored. See woff2_transforms.rs for
+// turning them back into regular tables.
+fn get_table_data(
+    table_data: &[u8],
+    entry: &Woff2TableDirectoryEntry,
+) -> Result<(String, Vec<u8>)> {
+    let data = table_data
+        .get(entry.offset..entry.offset + entry.length())
+        .ok_or_else(|| eyre!("Table {} is out of bounds", entry.tag))?;
+
+    Ok((entry.tag.to_owned(), data.to_owned()))
+}
+
+// UIntBase128 stores 7 bits per byte, most significant group first. The high bit
+// of each byte tells whether another byte follows.
+fn read_uint_base_128(content: &[u8], position: &mut usize) -> Result<u32> {
+    let mut accum: u32 = 0;
+
+    for i in 0..5 {
+        let byte: u8 = *content
+            .get(*position)
+            .ok_or_else(|| eyre!("UIntBase128 is out of bounds"))?;
+        *position += 1;
+
+        // Leading zeros are not allowed
+        if i == 0 && byte == 0x80 {
+            return Err(eyre!("UIntBase128 has leading zeros"));
+        }
+
+        // Would overflow when shifting in 7 more bits
+        if accum & 0xfe00_0000 != 0 {
+            return Err(eyre!("UIntBase128 overflows u32"));
+        }
+
+        accum = (accum << 7) | (byte & 0x7f) as u32;
+
+        if byte & 0x80 == 0 {
+            return Ok(accum);
+        }
+    }
+
+    Err(eyre!("UIntBase128 is longer than 5 bytes"))
+}
+
+// All tables are compressed together as one brotli stream, stored back to back
+// without padding.
+fn decompress_table_data(
+    compressed: &[u8],
+    entries: &[Woff2TableDirectoryEntry],
+) -> Result<Vec<u8>> {
+    let expected_length: usize = entries.iter().map(|entry| entry.length()).sum();
+
+    // Not preallocated, since the lengths are whatever the file claims
+    let mut table_data = vec![];
+
+    Decompressor::new(compressed, 4096)
+        .take(expected_length as u64)
+        .read_to_end(&mut table_data)
+        .map_err(|err| eyre!(err))?;
+
+    if table_data.len() != expected_length {
+        return Err(eyre!(
+            "Decompressed table data has length {}, expected {}",
+            table_data.len(),
+            expected_length
+        ));
+    }
+
+    Ok(table_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decompress_table_data, read_255_uint_16, read_uint_base_128, Woff2TableDirectoryEntry,
+    };
+
+    #[test]
+    fn read_uint_base_128_values() {
+        let mut position = 0;
+        assert_eq!(read_uint_base_128(&[0x3f], &mut position).unwrap(), 63);
+        assert_eq!(position, 1);
+
+        let mut position = 0;
+        assert_eq!(
+            read_uint_base_128(&[0x81, 0x80, 0x00], &mut position).unwrap(),
+            16384
+        );
+        assert_eq!(position, 3);
+
+        let mut position = 0;
+        assert!(read_uint_base_128(&[0x80, 0x01], &mut position).is_err());
+
+        let mut position = 0;
+        assert!(read_uint_base_128(&[0xff, 0xff, 0xff, 0xff, 0x7f], &mut position).is_err());
+
+        let mut position = 0;
+        assert_eq!(
+            read_uint_base_128(&[0x8f, 0xff, 0xff, 0xff, 0x7f], &mut position).unwrap(),
+            u32::MAX
+        );
+
+        // Out of bounds, and more than 5 bytes
+        for data in [&[][..], &[0x81], &[0x81, 0x80, 0x80, 0x80, 0x80, 0x00]] {
+            let mut position = 0;
+            assert!(
+                read_uint_base_128(data, &mut position).is_err(),
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn read_255_uint_16_values() {
+        let data = [0x0f, 0xff, 0x00, 0xfe, 0x00, 0xfd, 0x04, 0x00];
+
+        let mut position = 0;
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 15);
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 253);
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 506);
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 1024);
+        assert_eq!(position, data.len());
+
+        let data = [0xfc, 0xff, 0xff, 0xfe, 0xff, 0xfd, 0xff, 0xff];
+
+        let mut position = 0;
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 252);
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 508);
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), 761);
+        assert_eq!(read_255_uint_16(&data, &mut position).unwrap(), u16::MAX);
+
+        // The codes need the bytes after them
+        for data in [&[][..], &[0xfd, 0x01], &[0xfe], &[0xff]] {
+            let mut position = 0;
+            assert!(read_255_uint_16(data, &mut position).is_err(), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn fail_on_table_lengths_larger_than_the_data() {
+        let entries: Vec<Woff2TableDirectoryEntry> = (0..u16::MAX)
+            .map(|_| Woff2TableDirectoryEntry {
+                tag: "glyf".to_owned(),
+                offset: 0,
+                orig_length: u32::MAX as usize,
+                transform_length: None,
+            })
+            .collect();
+
+        // An empty brotli stream
+        assert!(decompress_table_data(&[0x06], &entries).is_err());
+    }
+}
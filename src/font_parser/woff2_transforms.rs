@@ -0,0 +1,581 @@
+use eyre::{eyre, Result};
+
+use super::{
+    glyf::{
+        component_arguments_length, write_glyf_and_loca, BoundingBox, Glyph, Point,
+        MORE_COMPONENTS, WE_HAVE_INSTRUCTIONS,
+    },
+    table_directory::FontTables,
+    tables::{read_i16, read_u16, read_u32},
+    woff2_parser::read_255_uint_16,
+};
+
+// https://www.w3.org/TR/WOFF2/#glyf_table_format
+// https://www.w3.org/TR/WOFF2/#hmtx_table_format
+// https://github.com/google/woff2/blob/master/src/woff2_dec.cc
+
+// Transformed glyf table
+// 0-2      UInt16      reserved
+// 2-4      UInt16      optionFlags             Bit 0: overlapSimpleBitmap[] is present
+// 4-6      UInt16      numGlyphs
+// 6-8      UInt16      indexFormat             loca offset format of the original font
+// 8-12     UInt32      nContourStreamSize      Int16 number of contours per glyph
+// 12-16    UInt32      nPointsStreamSize       255UInt16 number of points per contour
+// 16-20    UInt32      flagStreamSize          UInt8 flag per point
+// 20-24    UInt32      glyphStreamSize         Point triplets and instruction lengths
+// 24-28    UInt32      compositeStreamSize     Component records of composite glyphs
+// 28-32    UInt32      bboxStreamSize          Bitmap of explicit bounding boxes, then the boxes
+// 32-36    UInt32      instructionStreamSize   Instructions of every glyph
+// The streams follow in the same order, then the optional overlapSimpleBitmap.
+
+const TRANSFORMED_GLYF_HEADER_LENGTH: usize = 36;
+
+// Turns transformed glyf, loca and hmtx tables back into regular sfnt tables
+pub fn reverse_transforms(tables: &mut FontTables, transformed: &[String]) -> Result<()> {
+    let is_transformed = |tag: &str| transformed.iter().any(|t| t == tag);
+
+    let mut glyphs: Option<Vec<Glyph>> = None;
+
+    if is_transformed("glyf") {
+        let data = tables
+            .get("glyf")
+            .ok_or_else(|| eyre!("Could not find glyf table entry"))?;
+
+        let index_format = read_u16(data, 6)?;
+        let decoded = decode_glyphs(data)?;
+        let (glyf, loca) = write_glyf_and_loca(&decoded, index_format)?;
+
+        tables.insert("glyf".to_owned(), glyf);
+        tables.insert("loca".to_owned(), loca);
+        glyphs = Some(decoded);
+    }
+
+    if is_transformed("hmtx") {
+        let glyphs = glyphs
+            .as_ref()
+            .ok_or_else(|| eyre!("hmtx can only be transformed together with glyf"))?;
+        let hhea = tables
+            .get("hhea")
+            .ok_or_else(|| eyre!("Could not find hhea table entry"))?;
+        let number_of_h_metrics: usize = read_u16(hhea, 34)?.into();
+        let data = tables
+            .get("hmtx")
+            .ok_or_else(|| eyre!("Could not find hmtx table entry"))?;
+
+        let hmtx = decode_hmtx(data, glyphs, number_of_h_metrics)?;
+        tables.insert("hmtx".to_owned(), hmtx);
+    }
+
+    Ok(())
+}
+
+// Reads from one of the streams in the transformed glyf table
+struct Stream<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Stream<'a> {
+    fn read_u8(&mut self) -> Result<u8> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| eyre!("glyf stream is out of bounds"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let value = read_u16(self.data, self.position)?;
+        self.position += 2;
+        Ok(value)
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        self.read_u16().map(|value| value as i16)
+    }
+
+    fn read_255_uint_16(&mut self) -> Result<u16> {
+        read_255_uint_16(self.data, &mut self.position)
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or_else(|| eyre!("glyf stream is out of bounds"))?;
+        self.position += length;
+        Ok(bytes)
+    }
+}
+
+fn decode_glyphs(data: &[u8]) -> Result<Vec<Glyph>> {
+    let option_flags = read_u16(data, 2)?;
+    let num_glyphs: usize = read_u16(data, 4)?.into();
+
+    let mut position = TRANSFORMED_GLYF_HEADER_LENGTH;
+    let mut streams: Vec<Stream> = (0..7)
+        .map(|i| {
+            let size = read_u32(data, 8 + i * 4)? as usize;
+            let stream = data
+                .get(position..position + size)
+                .ok_or_else(|| eyre!("glyf stream {} is out of bounds", i))?;
+            position += size;
+            Ok(Stream {
+                data: stream,
+                position: 0,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    let overlap_bitmap: Option<&[u8]> = match option_flags & 0x0001 {
+        0 => None,
+        _ => Some(
+            data.get(position..position + num_glyphs.div_ceil(8))
+                .ok_or_else(|| eyre!("Overlap bitmap is out of bounds"))?,
+        ),
+    };
+
+    let mut instruction_stream = streams.pop().unwrap();
+    let mut bbox_stream = streams.pop().unwrap();
+    let mut composite_stream = streams.pop().unwrap();
+    let mut glyph_stream = streams.pop().unwrap();
+    let mut flag_stream = streams.pop().unwrap();
+    let mut n_points_stream = streams.pop().unwrap();
+    let mut n_contour_stream = streams.pop().unwrap();
+
+    // One bit per glyph, most significant bit first, padded to four bytes
+    let bbox_bitmap = bbox_stream.read_bytes(num_glyphs.div_ceil(32) * 4)?;
+    let is_bit_set = |bitmap: &[u8], index: usize| bitmap[index / 8] & (0x80 >> (index % 8)) != 0;
+
+    (0..num_glyphs)
+        .map(|index| {
+            let number_of_contours = n_contour_stream.read_i16()?;
+            let has_bbox = is_bit_set(bbox_bitmap, index);
+
+            let mut explicit_bbox = || -> Result<Option<BoundingBox>> {
+                match has_bbox {
+                    true => Ok(Some(BoundingBox {
+                        x_min: bbox_stream.read_i16()?,
+                        y_min: bbox_stream.read_i16()?,
+                        x_max: bbox_stream.read_i16()?,
+                        y_max: bbox_stream.read_i16()?,
+                    })),
+                    false => Ok(None),
+                }
+            };
+
+            match number_of_contours {
+                0 if has_bbox => Err(eyre!("Empty glyph {} has a bounding box", index)),
+                0 => Ok(Glyph::Empty),
+                -1 => {
+                    let start = composite_stream.position;
+                    let mut have_instructions = false;
+
+                    loop {
+                        let flags = composite_stream.read_u16()?;
+                        composite_stream.read_bytes(2 + component_arguments_length(flags))?;
+                        have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+
+                        if flags & MORE_COMPONENTS == 0 {
+                            break;
+                        }
+                    }
+
+                    let components =
+                        composite_stream.data[start..composite_stream.position].to_owned();
+
+                    let instructions = match have_instructions {
+                        true => {
+                            let length = glyph_stream.read_255_uint_16()?;
+                            Some(instruction_stream.read_bytes(length.into())?.to_owned())
+                        }
+                        false => None,
+                    };
+
+                    let bbox = explicit_bbox()?
+                        .ok_or_else(|| eyre!("Composite glyph {} has no bounding box", index))?;
+
+                    Ok(Glyph::Composite {
+                        bbox,
+                        components,
+                        instructions,
+                    })
+                }
+                contours if contours > 0 => {
+                    let mut end_points: Vec<u16> = Vec::with_capacity(contours as usize);
+                    let mut num_points: usize = 0;
+
+                    for _ in 0..contours {
+                        num_points += n_points_stream.read_255_uint_16()? as usize;
+                        let end_point: u16 = num_points
+                            .checked_sub(1)
+                            .and_then(|end_point| end_point.try_into().ok())
+                            .ok_or_else(|| {
+                                eyre!("Glyph {} has an invalid number of points", index)
+                            })?;
+                        end_points.push(end_point);
+                    }
+
+                    let (mut x, mut y) = (0, 0);
+                    let points: Vec<Point> = (0..num_points)
+                        .map(|_| {
+                            let flag = flag_stream.read_u8()?;
+                            let (dx, dy) = read_triplet(flag, &mut glyph_stream)?;
+                            (x, y) = (x + dx, y + dy);
+
+                            // Coordinates are int16 in the glyf table
+                            if i16::try_from(x).is_err() || i16::try_from(y).is_err() {
+                                return Err(eyre!("Glyph {} has coordinates out of range", index));
+                            }
+
+                            Ok(Point {
+                                x,
+                                y,
+                                on_curve: flag & 0x80 == 0,
+                            })
+                        })
+                        .collect::<Result<_>>()?;
+
+                    let instruction_length = glyph_stream.read_255_uint_16()?;
+                    let instructions = instruction_stream
+                        .read_bytes(instruction_length.into())?
+                        .to_owned();
+
+                    let bbox =
+                        explicit_bbox()?.unwrap_or_else(|| BoundingBox::from_points(&points));
+
+                    Ok(Glyph::Simple {
+                        bbox,
+                        end_points,
+                        instructions,
+                        points,
+                        overlap: overlap_bitmap.is_some_and(|bitmap| is_bit_set(bitmap, index)),
+                    })
+                }
+                _ => Err(eyre!("Glyph {} has an invalid number of contours", index)),
+            }
+        })
+        .collect()
+}
+
+// Point coordinates are stored as a flag (in the flag stream) and 1-4 bytes (in
+// the glyph stream). The high bit of the flag is set for off-curve points, the
+// rest selects how many bits each delta uses and their signs.
+fn read_triplet(flag: u8, stream: &mut Stream) -> Result<(i32, i32)> {
+    let flag = (flag & 0x7f) as i32;
+
+    let with_sign = |flag: i32, value: i32| match flag & 1 {
+        0 => -value,
+        _ => value,
+    };
+
+    let triplet = match flag {
+        0..=9 => {
+            let b0 = stream.read_u8()? as i32;
+            (0, with_sign(flag, ((flag & 14) << 7) + b0))
+        }
+        10..=19 => {
+            let b0 = stream.read_u8()? as i32;
+            (with_sign(flag, (((flag - 10) & 14) << 7) + b0), 0)
+        }
+        20..=83 => {
+            let b0 = flag - 20;
+            let b1 = stream.read_u8()? as i32;
+            (
+                with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+                with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+            )
+        }
+        84..=119 => {
+            let b0 = flag - 84;
+            let b1 = stream.read_u8()? as i32;
+            let b2 = stream.read_u8()? as i32;
+            (
+                with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+                with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+            )
+        }
+        120..=123 => {
+            let b1 = stream.read_u8()? as i32;
+            let b2 = stream.read_u8()? as i32;
+            let b3 = stream.read_u8()? as i32;
+            (
+                with_sign(flag, (b1 << 4) + (b2 >> 4)),
+                with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+            )
+        }
+        _ => {
+            let x = stream.read_u16()? as i32;
+            let y = stream.read_u16()? as i32;
+            (with_sign(flag, x), with_sign(flag >> 1, y))
+        }
+    };
+
+    Ok(triplet)
+}
+
+// Transformed hmtx table
+// UInt8    flags                   Bit 0: lsb[] is left out, bit 1: leftSideBearing[] is left out
+// UInt16   advanceWidth[numberOfHMetrics]
+// Int16    lsb[numberOfHMetrics]
+// Int16    leftSideBearing[numGlyphs - numberOfHMetrics]
+// Left out side bearings are equal to the xMin of the glyph.
+fn decode_hmtx(data: &[u8], glyphs: &[Glyph], number_of_h_metrics: usize) -> Result<Vec<u8>> {
+    let flags = *data
+        .first()
+        .ok_or_else(|| eyre!("Transformed hmtx table is empty"))?;
+
+    if number_of_h_metrics == 0 || number_of_h_metrics > glyphs.len() {
+        return Err(eyre!("hhea numberOfHMetrics is invalid"));
+    }
+
+    let mut position = 1;
+    let advance_widths: Vec<u16> = (0..number_of_h_metrics)
+        .map(|i| read_u16(data, position + i * 2))
+        .collect::<Result<_>>()?;
+    position += number_of_h_metrics * 2;
+
+    let mut read_side_bearings =
+        |range: std::ops::Range<usize>, left_out: bool| -> Result<Vec<i16>> {
+            match left_out {
+                true => Ok(glyphs[range].iter().map(|glyph| glyph.x_min()).collect()),
+                false => {
+                    let values = range
+                        .enumerate()
+                        .map(|(i, _)| read_i16(data, position + i * 2))
+                        .collect::<Result<Vec<i16>>>()?;
+                    position += values.len() * 2;
+                    Ok(values)
+                }
+            }
+        };
+
+    let side_bearings = read_side_bearings(0..number_of_h_metrics, flags & 0x01 != 0)?;
+    let monospace_side_bearings =
+        read_side_bearings(number_of_h_metrics..glyphs.len(), flags & 0x02 != 0)?;
+
+    let mut hmtx: Vec<u8> = Vec::with_capacity(number_of_h_metrics * 2 + glyphs.len() * 2);
+
+    for (advance_width, side_bearing) in advance_widths.iter().zip(side_bearings) {
+        hmtx.extend_from_slice(&advance_width.to_be_bytes());
+        hmtx.extend_from_slice(&side_bearing.to_be_bytes());
+    }
+    monospace_side_bearings
+        .iter()
+        .for_each(|side_bearing| hmtx.extend_from_slice(&side_bearing.to_be_bytes()));
+
+    Ok(hmtx)
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+
+    use super::{decode_glyphs, decode_hmtx, read_triplet, reverse_transforms, Stream};
+    use crate::font_parser::{
+        glyf::{BoundingBox, Glyph, Point},
+        table_directory::FontTables,
+    };
+
+    // Builds a transformed glyf table from its seven streams
+    fn transformed_glyf(num_glyphs: u16, streams: [&[u8]; 7]) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![];
+        for value in [0, 0, num_glyphs, 0] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        for stream in &streams {
+            data.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+        }
+        for stream in streams {
+            data.extend_from_slice(stream);
+        }
+        data
+    }
+
+    // An empty glyph, a triangle and a composite glyph with instructions
+    fn example_glyf() -> Vec<u8> {
+        transformed_glyf(
+            3,
+            [
+                &[0x00, 0x00, 0x00, 0x01, 0xff, 0xff],
+                &[3],
+                &[1, 0x8b, 84],
+                &[10, 20, 5, 7, 2, 1],
+                &[0x01, 0x00, 0x00, 0x01, 3, 4],
+                &[0x20, 0, 0, 0, 0xff, 0xfb, 0, 0, 0, 30, 0, 40],
+                &[0xb0, 0x01, 0x4b],
+            ],
+        )
+    }
+
+    fn example_glyphs() -> Vec<Glyph> {
+        vec![
+            Glyph::Empty,
+            Glyph::Simple {
+                bbox: BoundingBox {
+                    x_min: 0,
+                    y_min: 2,
+                    x_max: 20,
+                    y_max: 10,
+                },
+                end_points: vec![2],
+                instructions: vec![0xb0, 0x01],
+                points: vec![
+                    Point {
+                        x: 0,
+                        y: 10,
+                        on_curve: true,
+                    },
+                    Point {
+                        x: 20,
+                        y: 10,
+                        on_curve: false,
+                    },
+                    Point {
+                        x: 14,
+                        y: 2,
+                        on_curve: true,
+                    },
+                ],
+                overlap: false,
+            },
+            Glyph::Composite {
+                bbox: BoundingBox {
+                    x_min: -5,
+                    y_min: 0,
+                    x_max: 30,
+                    y_max: 40,
+                },
+                components: vec![0x01, 0x00, 0x00, 0x01, 3, 4],
+                instructions: Some(vec![0x4b]),
+            },
+        ]
+    }
+
+    #[test]
+    fn read_point_triplets() -> Result<()> {
+        let triplets: [(u8, &[u8], (i32, i32)); 12] = [
+            (0, &[5], (0, -5)),
+            (1, &[5], (0, 5)),
+            (9, &[0x10], (0, 1040)),
+            (10, &[3], (-3, 0)),
+            (19, &[1], (1025, 0)),
+            (20, &[0x12], (-2, -3)),
+            (23, &[0x12], (2, 3)),
+            (84, &[5, 7], (-6, -8)),
+            (120, &[0x12, 0x34, 0x56], (-291, -1110)),
+            (127, &[0x01, 0x00, 0x02, 0x00], (256, 512)),
+            // The on-curve bit is not part of the triplet
+            (0x81, &[5], (0, 5)),
+            (0xff, &[0xff, 0xff, 0xff, 0xff], (65535, 65535)),
+        ];
+
+        for (flag, data, expected) in triplets {
+            let mut stream = Stream { data, position: 0 };
+            assert_eq!(read_triplet(flag, &mut stream)?, expected, "{}", flag);
+            assert_eq!(stream.position, data.len());
+        }
+
+        let mut stream = Stream {
+            data: &[5],
+            position: 0,
+        };
+        assert!(read_triplet(84, &mut stream).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_transformed_glyphs() -> Result<()> {
+        assert_eq!(decode_glyphs(&example_glyf())?, example_glyphs());
+
+        let mut tables = FontTables::new();
+        tables.insert("glyf".to_owned(), example_glyf());
+        reverse_transforms(&mut tables, &["glyf".to_owned()])?;
+        // Short offsets, the original index format
+        assert_eq!(tables["loca"].len(), 4 * 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_malformed_glyf_streams() {
+        let data = example_glyf();
+        for length in 0..data.len() {
+            assert!(decode_glyphs(&data[..length]).is_err(), "{}", length);
+        }
+
+        // The nContour, nPoints, flag, glyph, composite, bbox and instruction streams
+        let malformed: [[&[u8]; 7]; 5] = [
+            // Invalid number of contours
+            [&[0xff, 0xfe], &[], &[], &[], &[], &[0, 0, 0, 0], &[]],
+            // A contour without points
+            [&[0x00, 0x01], &[0], &[], &[0], &[], &[0, 0, 0, 0], &[]],
+            // An empty glyph with a bounding box
+            [
+                &[0x00, 0x00],
+                &[],
+                &[],
+                &[],
+                &[],
+                &[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                &[],
+            ],
+            // A composite glyph without one
+            [
+                &[0xff, 0xff],
+                &[],
+                &[],
+                &[],
+                &[0, 0, 0, 0, 0, 0],
+                &[0, 0, 0, 0],
+                &[],
+            ],
+            // Points outside the int16 range
+            [
+                &[0x00, 0x01],
+                &[1],
+                &[127],
+                &[0xff, 0xff, 0xff, 0xff, 0],
+                &[],
+                &[0, 0, 0, 0],
+                &[],
+            ],
+        ];
+
+        for streams in malformed {
+            assert!(
+                decode_glyphs(&transformed_glyf(1, streams)).is_err(),
+                "{:?}",
+                streams
+            );
+        }
+
+        // hmtx is decoded from the glyphs of the transformed glyf table
+        let mut tables = FontTables::new();
+        tables.insert("hhea".to_owned(), vec![0; 36]);
+        tables.insert("hmtx".to_owned(), vec![0x03]);
+        assert!(reverse_transforms(&mut tables, &["hmtx".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn decode_transformed_hmtx() -> Result<()> {
+        let glyphs = example_glyphs();
+
+        // Left side bearings of the metrics are left out, the ones after are stored
+        let hmtx = decode_hmtx(&[0x01, 0x01, 0xf4, 0x02, 0x58, 0xff, 0xf6], &glyphs, 2)?;
+        assert_eq!(hmtx, [0x01, 0xf4, 0, 0, 0x02, 0x58, 0, 0, 0xff, 0xf6]);
+
+        // Both are left out, and equal to xMin
+        let hmtx = decode_hmtx(&[0x03, 0x01, 0xf4, 0x02, 0x58], &glyphs, 2)?;
+        assert_eq!(hmtx, [0x01, 0xf4, 0, 0, 0x02, 0x58, 0, 0, 0xff, 0xfb]);
+
+        assert!(decode_hmtx(&[], &glyphs, 2).is_err());
+        assert!(decode_hmtx(&[0x03, 0x01, 0xf4], &glyphs, 2).is_err());
+        assert!(decode_hmtx(&[0x03, 0x01, 0xf4, 0x02, 0x58], &glyphs, 0).is_err());
+        assert!(decode_hmtx(&[0x03, 0x01, 0xf4, 0x02, 0x58], &glyphs, 4).is_err());
+
+        Ok(())
+    }
+}
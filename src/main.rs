@@ -1,16 +1,31 @@
-use std::{fs, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+    vec,
+};
 
 use crate::{
-    crawler::{browser_crawler::BrowserCrawler, http_crawler::HttpCrawler},
+    crawler::{
+        browser_crawler::{BrowserCrawler, BrowserMode, BrowserOptions, WaitStrategy},
+        download::{parse_size, DownloadOptions},
+        fetch_error::FetchError,
+        host_limiter::{parse_host_limits, HostLimiter, HostLimits},
+        http_cache::{get_http_cache, init_http_cache, HttpCacheOptions},
+        http_crawler::{is_disallowed, HttpCrawler},
+    },
+    font_parser::FontData,
+    parsers::{font_face::SourceSelection, font_provider::FontProvider},
     tasks::{
         channel_message::ChannelMessage, html_browser::start_html_browser_tasks,
         html_http::start_html_http_tasks, page::start_page_tasks, verifier::start_verifier_tasks,
-        Page, SiteData,
+        FontLocation, Page, SiteData,
     },
 };
 use eyre::{eyre, Context};
 use opentelemetry::global;
-use tracing::{error, Instrument};
+use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use url::Url;
 
@@ -33,6 +48,8 @@ pub enum CustomError {
     NoElementsFound(String),
     #[error("No font urls found: {0}")]
     NoFontUrlsFound(String),
+    #[error("Disallowed by robots.txt: {0}")]
+    Disallowed(String),
     #[error("Reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
     #[error("Eyre report: {0}")]
@@ -45,7 +62,100 @@ async fn main() -> Result<()> {
 
     let args: Vec<String> = std::env::args().collect();
 
-    let url = args.get(1);
+    // Fetch every src of an @font-face rule, not only the one a browser would use
+    let source_selection = match args.iter().any(|arg| arg == "--all-sources") {
+        true => SourceSelection::All,
+        false => SourceSelection::Browser,
+    };
+
+    // Render every page in the browser, and report which fonts its text uses
+    let browser_mode = match args.iter().any(|arg| arg == "--font-usage") {
+        true => BrowserMode::FontUsage,
+        false => BrowserMode::Content,
+    };
+
+    // When a page rendered in the browser is done, e.g. --wait=network-idle:500,
+    // and how long to wait for it at most, e.g. --timeout=30000
+    let mut browser_options = BrowserOptions {
+        mode: browser_mode,
+        ..BrowserOptions::default()
+    };
+    if let Some(wait_strategy) = args.iter().find_map(|arg| arg.strip_prefix("--wait=")) {
+        browser_options.wait_strategy = wait_strategy.parse::<WaitStrategy>()?;
+    }
+    if let Some(timeout) = args.iter().find_map(|arg| arg.strip_prefix("--timeout=")) {
+        browser_options.timeout = timeout
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .wrap_err(format!("Invalid timeout {}", timeout))?;
+    }
+
+    // Requests per second and max in flight for every host, e.g. --host-limits=2:4,
+    // and for a host and its subdomains, e.g. --host-limit=fonts.gstatic.com=10:8
+    let mut default_limits = HostLimits::default();
+    if let Some(limits) = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--host-limits="))
+    {
+        default_limits = limits.parse::<HostLimits>()?;
+    }
+    let mut host_limiter = HostLimiter::new(default_limits);
+    for host_limits in args
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("--host-limit="))
+    {
+        let (host, limits) = parse_host_limits(host_limits)?;
+        host_limiter = host_limiter.with_host_limits(&host, limits);
+    }
+    let host_limiter = Arc::new(host_limiter);
+
+    // Max sizes of pages, stylesheets and fonts, in bytes or with k or m, e.g.
+    // --max-font-size=20m, and whether to check that a font starts like one before
+    // downloading all of it, with --sniff-fonts
+    let mut download_options = DownloadOptions {
+        sniff_fonts: args.iter().any(|arg| arg == "--sniff-fonts"),
+        ..DownloadOptions::default()
+    };
+    if let Some(size) = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--max-html-size="))
+    {
+        download_options.max_html_size = parse_size(size)?;
+    }
+    if let Some(size) = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--max-css-size="))
+    {
+        download_options.max_css_size = parse_size(size)?;
+    }
+    if let Some(size) = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--max-font-size="))
+    {
+        download_options.max_font_size = parse_size(size)?;
+    }
+
+    // Stylesheets and fonts are cached in memory, --memory-cache-size=512m, and
+    // only on disk when given a --cache-dir, up to --disk-cache-size=2048m
+    let mut http_cache_options = HttpCacheOptions::default();
+    if let Some(size) = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--memory-cache-size="))
+    {
+        http_cache_options.memory_size = parse_size(size)? as usize;
+    }
+    if let Some(size) = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--disk-cache-size="))
+    {
+        http_cache_options.disk_size = parse_size(size)?;
+    }
+    if let Some(dir) = args.iter().find_map(|arg| arg.strip_prefix("--cache-dir=")) {
+        http_cache_options.disk_dir = Some(dir.into());
+    }
+    init_http_cache(http_cache_options)?;
+
+    let url = args.iter().skip(1).find(|arg| !arg.starts_with("--"));
 
     if let Some(url) = url {
         let base_url: String = Url::parse(url)
@@ -53,11 +163,21 @@ async fn main() -> Result<()> {
             .as_str()
             .to_owned();
 
-        let crawler: HttpCrawler = HttpCrawler::new()?;
-
-        let content = get_content_from_url(&url).await?;
+        let crawler: HttpCrawler = HttpCrawler::new()?
+            .with_source_selection(source_selection)
+            .with_host_limiter(host_limiter.clone())
+            .with_download_options(download_options);
 
-        let page = Page::new(url.to_owned(), content.clone());
+        let page =
+            match get_page_from_url(url, &browser_options, &host_limiter, download_options).await {
+                Ok(page) => page,
+                Err(err) if is_disallowed(&err) => {
+                    tracing::error!("{}", err);
+                    println!("Disallowed by robots.txt: {}", base_url);
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
 
         let all_font_data = match SiteData::from_page(&crawler, &page).await {
             Ok(data) => {
@@ -92,15 +212,40 @@ async fn main() -> Result<()> {
         let (html_browser_node_tx, html_browser_node_rx) =
             async_channel::bounded::<ChannelMessage<String>>(3);
 
-        let html_http_handles = start_html_http_tasks(&html_http_node_rx, &verifier_node_tx, 3);
-
-        let verifier_handles =
-            start_verifier_tasks(&verifier_node_rx, &html_browser_node_tx, &page_node_tx, 3);
-
-        let html_browser_handles =
-            start_html_browser_tasks(&html_browser_node_rx, &page_node_tx, 3);
-
-        let page_handles = start_page_tasks(&page_node_rx, 5);
+        let html_http_handles = start_html_http_tasks(
+            &html_http_node_rx,
+            &verifier_node_tx,
+            3,
+            &host_limiter,
+            download_options,
+        );
+
+        let verifier_handles = start_verifier_tasks(
+            &verifier_node_rx,
+            &html_browser_node_tx,
+            &page_node_tx,
+            3,
+            browser_mode,
+            &host_limiter,
+            download_options,
+        );
+
+        let html_browser_handles = start_html_browser_tasks(
+            &html_browser_node_rx,
+            &page_node_tx,
+            3,
+            browser_options,
+            &host_limiter,
+            download_options,
+        );
+
+        let page_handles = start_page_tasks(
+            &page_node_rx,
+            5,
+            source_selection,
+            &host_limiter,
+            download_options,
+        );
 
         start_jobs(urls, &html_http_node_tx).await;
 
@@ -110,8 +255,12 @@ async fn main() -> Result<()> {
         drop(html_browser_node_tx);
         drop(page_node_tx);
 
+        let mut disallowed_pages: Vec<String> = vec![];
+        let mut failed_pages: Vec<(String, FetchError)> = vec![];
         for h in html_http_handles {
-            h.await.map_err(|err| eyre!(err))?;
+            let r = h.await.map_err(|err| eyre!(err))?;
+            disallowed_pages.extend(r.disallowed_urls);
+            failed_pages.extend(r.fetch_errors);
             println!("HTTP HTML FERDIG");
         }
 
@@ -136,12 +285,161 @@ async fn main() -> Result<()> {
         println!("Font data for everything");
         println!("Length: {}", all_site_data.len());
         println!("{:#?}", all_site_data);
+
+        // Count sites per unique font rather than per font url. A site can declare
+        // the same font more than once, and be crawled by both http and the browser.
+        // Also where the font was first found, to tell fonts with the same name apart
+        let mut font_usage: HashMap<&str, (&FontData, &FontLocation, HashSet<&str>)> =
+            HashMap::new();
+        for site_data in &all_site_data {
+            for font in &site_data.fonts {
+                font_usage
+                    .entry(&font.font_data.fingerprint)
+                    .or_insert((&font.font_data, &font.location, HashSet::new()))
+                    .2
+                    .insert(&site_data.url);
+            }
+        }
+
+        println!("Unique fonts: {}", font_usage.len());
+        for (font_data, location, sites) in font_usage.values() {
+            let mut style = font_data.style().unwrap_or_else(|| "-".to_owned());
+            if font_data.is_variable() {
+                style.push_str(", variable");
+            }
+            println!(
+                "{} [{}]: {} ({})",
+                font_data.full_name,
+                style,
+                sites.len(),
+                location
+            );
+        }
+
+        // Sites per web font service
+        let mut provider_usage: HashMap<FontProvider, usize> = HashMap::new();
+        for site_data in &all_site_data {
+            let site_providers: HashSet<FontProvider> = site_data
+                .providers
+                .iter()
+                .map(|provider_url| provider_url.provider)
+                .collect();
+
+            for provider in site_providers {
+                *provider_usage.entry(provider).or_insert(0) += 1;
+            }
+        }
+
+        println!("Font providers: {}", provider_usage.len());
+        for (provider, count) in &provider_usage {
+            println!("{:?}: {}", provider, count);
+        }
+
+        // Sites per family set in style attributes
+        let mut style_family_usage: HashMap<&str, usize> = HashMap::new();
+        for site_data in &all_site_data {
+            for family in &site_data.style_families {
+                *style_family_usage.entry(family).or_insert(0) += 1;
+            }
+        }
+
+        println!("Families in style attributes: {}", style_family_usage.len());
+        for (family, count) in &style_family_usage {
+            println!("{}: {}", family, count);
+        }
+
+        // Sites and characters per family the text is rendered with, and how many
+        // sites fell back from the family they asked for
+        let mut family_usage: HashMap<&str, (usize, usize)> = HashMap::new();
+        let mut fallback_sites: usize = 0;
+        for font_usage in all_site_data
+            .iter()
+            .filter_map(|site_data| site_data.font_usage.as_ref())
+        {
+            for (family, characters) in font_usage.used_families() {
+                let (sites, total_characters) = family_usage.entry(family).or_insert((0, 0));
+                *sites += 1;
+                *total_characters += characters;
+            }
+
+            if font_usage
+                .families
+                .iter()
+                .any(|family_usage| family_usage.fell_back)
+            {
+                fallback_sites += 1;
+            }
+        }
+
+        if !family_usage.is_empty() {
+            println!("Fonts in use: {}", family_usage.len());
+            for (family, (sites, characters)) in &family_usage {
+                println!("{}: {} sites, {} characters", family, sites, characters);
+            }
+            println!("Sites with fallback fonts: {}", fallback_sites);
+        }
+
+        // Pages, stylesheets and fonts that were left out because of robots.txt
+        let disallowed_urls: Vec<&String> = all_site_data
+            .iter()
+            .flat_map(|site_data| &site_data.disallowed_urls)
+            .collect();
+
+        println!("Pages disallowed by robots.txt: {}", disallowed_pages.len());
+        for url in &disallowed_pages {
+            println!("{}", url);
+        }
+        println!(
+            "Stylesheets and fonts disallowed by robots.txt: {}",
+            disallowed_urls.len()
+        );
+        for url in disallowed_urls {
+            println!("{}", url);
+        }
+
+        // Sites that were down, as opposed to sites without fonts, and the
+        // stylesheets and fonts that failed
+        let failed_urls: Vec<&(String, FetchError)> = all_site_data
+            .iter()
+            .flat_map(|site_data| &site_data.failed_urls)
+            .collect();
+
+        println!("Pages that could not be fetched: {}", failed_pages.len());
+        print_fetch_errors(failed_pages.iter());
+        println!(
+            "Stylesheets and fonts that could not be fetched: {}",
+            failed_urls.len()
+        );
+        print_fetch_errors(failed_urls.into_iter());
+
+        let stats = &get_http_cache().stats;
+        println!(
+            "HTTP cache: {} hits, {} revalidated, {} fetched",
+            stats.hits.load(Ordering::Relaxed),
+            stats.revalidated.load(Ordering::Relaxed),
+            stats.misses.load(Ordering::Relaxed)
+        );
     }
 
     global::shutdown_tracer_provider();
     Ok(())
 }
 
+// Counts per kind of error, and then every url with its error
+fn print_fetch_errors<'a>(fetch_errors: impl Iterator<Item = &'a (String, FetchError)> + Clone) {
+    let mut kinds: HashMap<&str, usize> = HashMap::new();
+    for (_, fetch_error) in fetch_errors.clone() {
+        *kinds.entry(fetch_error.kind()).or_insert(0) += 1;
+    }
+
+    for (kind, count) in &kinds {
+        println!("{}: {}", kind, count);
+    }
+    for (url, fetch_error) in fetch_errors {
+        println!("{}: {}", url, fetch_error);
+    }
+}
+
 async fn start_jobs(
     urls: Vec<String>,
     html_http_node_tx: &async_channel::Sender<ChannelMessage<String>>,
@@ -160,31 +458,48 @@ async fn start_job(url: String, html_http_node_tx: &async_channel::Sender<Channe
     let mut message = ChannelMessage::new(span.to_owned(), url);
     message.inject(&span.context());
 
-    if let Err(_) = html_http_node_tx.send(message).instrument(span).await {
+    if html_http_node_tx
+        .send(message)
+        .instrument(span)
+        .await
+        .is_err()
+    {
         tracing::error!("Could not send to html_http channel");
     }
 }
 
 // Fetches with http, verifies, and fetches with browser if necessary
-async fn get_content_from_url(url: &str) -> eyre::Result<String> {
-    let crawler: HttpCrawler = HttpCrawler::new()?;
+async fn get_page_from_url(
+    url: &str,
+    browser_options: &BrowserOptions,
+    host_limiter: &Arc<HostLimiter>,
+    download_options: DownloadOptions,
+) -> eyre::Result<Page> {
+    let crawler: HttpCrawler = HttpCrawler::new()?
+        .with_host_limiter(host_limiter.clone())
+        .with_download_options(download_options);
+
+    if browser_options.mode == BrowserMode::FontUsage {
+        crawler.check_robots(url).await?;
+        return get_page_with_browser(url, browser_options, host_limiter, download_options).await;
+    }
 
-    let content = match crawler.get_page_content(&url).await {
+    let content = match crawler.get_page_content(url).await {
         Ok(content) => {
             tracing::info!("Got content with http!");
             content
         }
+        // The browser must not fetch it either
+        Err(err) if is_disallowed(&err) => return Err(err),
         Err(err) => {
             tracing::error!("{}", err);
             tracing::info!("Could not get fetch with http. Trying with browser");
-            let browser_crawler: BrowserCrawler = BrowserCrawler::new()?;
-            browser_crawler
-                .get_page_content(&url)
-                .wrap_err(format!("Unable to get page content for {}.", &url))?
+            return get_page_with_browser(url, browser_options, host_limiter, download_options)
+                .await;
         }
     };
 
-    let page = Page::new(url.to_owned(), content.clone());
+    let page = Page::new(url.to_owned(), content);
 
     if let Err(err) = crawler.get_font_urls_from_page(&page).await {
         match err {
@@ -194,10 +509,8 @@ async fn get_content_from_url(url: &str) -> eyre::Result<String> {
                     page.base_url
                 );
 
-                let browser_crawler: BrowserCrawler = BrowserCrawler::new()?;
-                return browser_crawler
-                    .get_page_content(&url)
-                    .wrap_err(format!("Unable to get page content for {}.", &url));
+                return get_page_with_browser(url, browser_options, host_limiter, download_options)
+                    .await;
             }
             err => {
                 tracing::error!(
@@ -212,5 +525,24 @@ async fn get_content_from_url(url: &str) -> eyre::Result<String> {
         }
     }
 
-    return Ok(content);
+    Ok(page)
+}
+
+// Renders the page, and keeps the fonts the browser loaded
+async fn get_page_with_browser(
+    url: &str,
+    browser_options: &BrowserOptions,
+    host_limiter: &Arc<HostLimiter>,
+    download_options: DownloadOptions,
+) -> eyre::Result<Page> {
+    let browser_crawler: BrowserCrawler = BrowserCrawler::new(1)
+        .with_options(browser_options.to_owned())
+        .with_host_limiter(host_limiter.clone())
+        .with_download_options(download_options);
+    let browser_page = browser_crawler
+        .get_page(url)
+        .await
+        .wrap_err(format!("Unable to get page content for {}.", &url))?;
+
+    Ok(Page::from_browser_page(url.to_owned(), browser_page))
 }
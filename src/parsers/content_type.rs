@@ -0,0 +1,182 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+// Which encoding a page or stylesheet is in, decided like a browser does.
+// A byte order mark always wins, and is handled by Encoding::decode.
+// https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding
+// https://www.w3.org/TR/css-syntax-3/#input-byte-stream
+
+// How far into a page <meta charset> is looked for
+const META_PRESCAN_SIZE: usize = 1024;
+
+// Both <meta charset="..."> and <meta http-equiv="Content-Type" content="text/html; charset=...">
+static META_CHARSET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)<meta[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#)
+        .expect("meta charset regex is valid")
+});
+
+// The Content-Type header of a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentType {
+    // Lowercased and without parameters, e.g. text/html
+    pub mime_type: String,
+    // None when there is no charset parameter, or its label is unknown
+    pub charset: Option<&'static Encoding>,
+}
+
+impl ContentType {
+    pub fn parse(value: &str) -> Self {
+        let mut parts = value.split(';');
+        let mime_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+
+        let charset = parts
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .and_then(|(_, label)| {
+                Encoding::for_label(label.trim().trim_matches(['"', '\'']).as_bytes())
+            });
+
+        ContentType { mime_type, charset }
+    }
+
+    pub fn is_html(&self) -> bool {
+        matches!(
+            self.mime_type.as_str(),
+            "text/html" | "application/xhtml+xml"
+        )
+    }
+
+    // Stylesheets are often served as plain text or without a proper type, so
+    // those are accepted too
+    pub fn is_css(&self) -> bool {
+        matches!(
+            self.mime_type.as_str(),
+            "text/css" | "text/plain" | "application/x-css" | "application/octet-stream" | ""
+        )
+    }
+}
+
+// The charset of the Content-Type header wins over <meta charset>. Without either,
+// the page is UTF-8 when it is valid UTF-8, and otherwise windows-1252.
+pub fn decode_html(content: &[u8], content_type: Option<&ContentType>) -> String {
+    let encoding = content_type
+        .and_then(|content_type| content_type.charset)
+        .or_else(|| get_meta_charset(content))
+        .unwrap_or_else(|| match std::str::from_utf8(content) {
+            Ok(_) => UTF_8,
+            Err(_) => WINDOWS_1252,
+        });
+
+    let (text, _, _) = encoding.decode(content);
+    text.into_owned()
+}
+
+// The charset of the Content-Type header wins over @charset, and without
+// either the stylesheet is UTF-8
+pub fn decode_css(content: &[u8], content_type: Option<&ContentType>) -> String {
+    let encoding = content_type
+        .and_then(|content_type| content_type.charset)
+        .or_else(|| get_charset_rule(content))
+        .unwrap_or(UTF_8);
+
+    let (text, _, _) = encoding.decode(content);
+    text.into_owned()
+}
+
+fn get_meta_charset(content: &[u8]) -> Option<&'static Encoding> {
+    let head = &content[..content.len().min(META_PRESCAN_SIZE)];
+    let label = META_CHARSET.captures(head)?.get(1)?.as_bytes();
+
+    Encoding::for_label(label).map(ascii_compatible)
+}
+
+// Only counts when the stylesheet starts with exactly @charset "...";
+fn get_charset_rule(content: &[u8]) -> Option<&'static Encoding> {
+    let rest = content.strip_prefix(b"@charset \"")?;
+    let end = rest.iter().position(|byte| *byte == b'"')?;
+
+    if rest.get(end + 1) != Some(&b';') {
+        return None;
+    }
+
+    Encoding::for_label(&rest[..end]).map(ascii_compatible)
+}
+
+// A declaration that could be read as ASCII can't be UTF-16, and then it's UTF-8
+fn ascii_compatible(encoding: &'static Encoding) -> &'static Encoding {
+    match encoding == UTF_16BE || encoding == UTF_16LE {
+        true => UTF_8,
+        false => encoding,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    use super::{decode_css, decode_html, ContentType};
+
+    #[test]
+    fn parse_content_type() {
+        let content_type = ContentType::parse("Text/HTML; Charset=\"Shift_JIS\"");
+        assert_eq!(content_type.mime_type, "text/html");
+        assert_eq!(content_type.charset, Some(SHIFT_JIS));
+        assert!(content_type.is_html());
+        assert!(!content_type.is_css());
+
+        let content_type = ContentType::parse("text/css;charset=unknown");
+        assert_eq!(content_type.charset, None);
+        assert!(content_type.is_css());
+
+        assert!(!ContentType::parse("image/png").is_css());
+    }
+
+    #[test]
+    fn decode_pages_in_legacy_encodings() {
+        let (page, _, _) =
+            SHIFT_JIS.encode(r#"<html><head><meta charset="shift_jis"></head>日本語のフォント"#);
+        assert!(decode_html(&page, None).ends_with("日本語のフォント"));
+
+        let (page, _, _) = SHIFT_JIS.encode(
+            r#"<meta http-equiv="Content-Type" content="text/html; charset=Shift_JIS">フォント"#,
+        );
+        assert!(decode_html(&page, None).ends_with("フォント"));
+
+        // The header wins over the meta tag
+        let (page, _, _) = WINDOWS_1252.encode(r#"<meta charset="utf-8">Café"#);
+        let content_type = ContentType::parse("text/html; charset=windows-1252");
+        assert!(decode_html(&page, Some(&content_type)).ends_with("Café"));
+
+        // Not valid UTF-8, and nothing declared
+        let (page, _, _) = WINDOWS_1252.encode("<p>Skrifttyper på nett</p>");
+        assert_eq!(decode_html(&page, None), "<p>Skrifttyper på nett</p>");
+
+        // The byte order mark wins over everything
+        let page = [b"\xEF\xBB\xBF".as_slice(), "<p>på</p>".as_bytes()].concat();
+        let content_type = ContentType::parse("text/html; charset=windows-1252");
+        assert_eq!(decode_html(&page, Some(&content_type)), "<p>på</p>");
+    }
+
+    #[test]
+    fn decode_stylesheets_in_legacy_encodings() {
+        let css = r#"@charset "Shift_JIS";
+@font-face { font-family: "游ゴシック"; src: url(yugothic.woff2); }"#;
+        let (content, _, _) = SHIFT_JIS.encode(css);
+        assert_eq!(decode_css(&content, None), css);
+
+        // Must be at the very start
+        let css = format!(" {}", css);
+        let (content, _, _) = SHIFT_JIS.encode(&css);
+        assert_ne!(decode_css(&content, None), css);
+
+        let content_type = ContentType::parse("text/css; charset=shift_jis");
+        let (content, _, _) = SHIFT_JIS.encode("a::before { content: \"フォント\" }");
+        assert_eq!(
+            decode_css(&content, Some(&content_type)),
+            "a::before { content: \"フォント\" }"
+        );
+
+        assert_eq!(decode_css(b"\xEF\xBB\xBFa { }", None), "a { }");
+    }
+}
@@ -1,49 +1,511 @@
-use eyre::{eyre, Context, Result};
+use eyre::{eyre, Result};
 
-use once_cell::sync::Lazy;
-use regex::Regex;
+use super::{
+    css_tokenizer::{tokenize, Token},
+    font_face::FontFace,
+};
 
-// r"(@font-face \{\w*\})"
-// static FONT_FACE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"@font-face \{.*\}").unwrap());
-static FONT_FACE_RE: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"@font-face\{(?P<data>[\s\S]*?)\}").unwrap());
-static URL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"url\((?P<data>[\S]*?)\)").unwrap());
+// Parser following CSS Syntax Module Level 3, section 5. Only what is needed to
+// find @font-face rules is kept: rules, their blocks and declarations.
+// https://www.w3.org/TR/css-syntax-3/#parsing
 
-pub fn parse_css_doc(css_as_bytes: Vec<u8>) -> Result<Vec<String>> {
-    let mut text = std::str::from_utf8(&css_as_bytes)
-        .wrap_err("Not able to parse bytes to utf-8 string. Might be encoding issue.")?
-        .to_owned();
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentValue {
+    Token(Token),
+    Function {
+        name: String,
+        arguments: Vec<ComponentValue>,
+    },
+    // A {}, [] or () block, identified by its opening token
+    Block {
+        opening: Token,
+        contents: Vec<ComponentValue>,
+    },
+}
 
-    // can use retain since variable s is mutable. just want to remove whitespace
-    text.retain(|c| !c.is_whitespace());
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    At {
+        name: String,
+        prelude: Vec<ComponentValue>,
+        // The contents of the {} block, None for statements like @import
+        block: Option<Vec<ComponentValue>>,
+    },
+    Qualified {
+        prelude: Vec<ComponentValue>,
+        block: Vec<ComponentValue>,
+    },
+}
 
-    let matches: Vec<&str> = FONT_FACE_RE
-        .captures_iter(&text.trim())
-        .filter_map(|c| c.name("data"))
-        .map(|c| -> &str { c.as_str() })
-        .collect();
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    // Lowercased, since property and descriptor names are case-insensitive
+    pub name: String,
+    // Without leading and trailing whitespace and !important
+    pub value: Vec<ComponentValue>,
+    pub important: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFaceRule {
+    pub declarations: Vec<Declaration>,
+}
+
+// Blocks and functions nested deeper than this are left out. They are parsed
+// recursively, so a stylesheet of nothing but ( would otherwise overflow the stack.
+const MAX_NESTING_DEPTH: usize = 128;
+
+// Conditional and grouping at-rules whose block holds more rules
+const GROUPING_AT_RULES: [&str; 7] = [
+    "media",
+    "supports",
+    "layer",
+    "container",
+    "document",
+    "-moz-document",
+    "scope",
+];
+
+pub fn parse_css_doc(css: &str) -> Result<Vec<FontFace>> {
+    let font_faces: Vec<FontFace> = parse_font_faces(css).iter().map(FontFace::from).collect();
 
-    if matches.is_empty() {
+    if font_faces.is_empty() {
         return Err(eyre!("Could not find font-face attribute"));
     }
 
-    // At this point, content has been extracted from @font-face{}
-    // Maybe this should be extracted into its own method?
-    let urls: Vec<String> = matches
+    if font_faces
+        .iter()
+        .all(|font_face| font_face.urls().is_empty())
+    {
+        return Err(eyre!("Could not find url in font-face attribute"));
+    }
+
+    Ok(font_faces)
+}
+
+// Urls of the stylesheets imported with @import url(...) or @import "...".
+// Only top level rules count, since @import is ignored anywhere else.
+pub fn parse_css_imports(css: &str) -> Vec<String> {
+    parse_stylesheet(css)
+        .iter()
+        .filter_map(|rule| match rule {
+            Rule::At { name, prelude, .. } if name.eq_ignore_ascii_case("import") => {
+                prelude.iter().find_map(|value| match value {
+                    ComponentValue::Token(Token::String(url)) => Some(url.to_owned()),
+                    _ => get_urls(std::slice::from_ref(value)).into_iter().next(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Every @font-face rule in the stylesheet, including those nested in @media,
+// @supports and other grouping rules
+pub fn parse_font_faces(css: &str) -> Vec<FontFaceRule> {
+    let rules = parse_stylesheet(css);
+
+    let mut font_faces: Vec<FontFaceRule> = vec![];
+    collect_font_faces(&rules, &mut font_faces);
+
+    font_faces
+}
+
+fn collect_font_faces(rules: &[Rule], font_faces: &mut Vec<FontFaceRule>) {
+    for rule in rules {
+        let Rule::At {
+            name,
+            block: Some(block),
+            ..
+        } = rule
+        else {
+            continue;
+        };
+
+        let name = name.to_ascii_lowercase();
+
+        if name == "font-face" {
+            font_faces.push(FontFaceRule {
+                declarations: parse_declarations(block),
+            });
+        } else if GROUPING_AT_RULES.contains(&name.as_str()) {
+            collect_font_faces(&parse_rules(block.to_owned(), false), font_faces);
+        }
+    }
+}
+
+impl FontFaceRule {
+    // The last declaration wins, as in the cascade
+    pub fn get(&self, name: &str) -> Option<&Declaration> {
+        self.declarations
+            .iter()
+            .rev()
+            .find(|declaration| declaration.name == name)
+    }
+}
+
+impl Declaration {
+    pub fn value_to_string(&self) -> String {
+        serialize(&self.value)
+    }
+}
+
+// Both url(x) and url("x")
+pub fn get_urls(values: &[ComponentValue]) -> Vec<String> {
+    values
         .iter()
-        .flat_map(|url| {
-            URL_RE
-                .captures_iter(url.trim())
-                .filter_map(|cap| cap.name("data"))
-                .map(|cap| cap.as_str().replace(&['\"', '\''], ""))
+        .filter_map(|value| match value {
+            ComponentValue::Token(Token::Url(url)) => Some(url.to_owned()),
+            ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("url") => {
+                arguments.iter().find_map(|argument| match argument {
+                    ComponentValue::Token(Token::String(url)) => Some(url.to_owned()),
+                    _ => None,
+                })
+            }
+            _ => None,
         })
+        .collect()
+}
+
+pub fn parse_stylesheet(css: &str) -> Vec<Rule> {
+    parse_rules(to_component_values(css), true)
+}
+
+fn to_component_values(css: &str) -> Vec<ComponentValue> {
+    skip_deep_blocks(tokenize(css))
+        .into_iter()
+        .map(ComponentValue::Token)
+        .collect()
+}
+
+// Leaves out the blocks nested deeper than MAX_NESTING_DEPTH, with everything in
+// them, and keeps the rest. Follows the blocks like the parser does, where only the
+// matching token closes a block, so that e.g. ( ] ( ] counts as nested too.
+fn skip_deep_blocks(tokens: Vec<Token>) -> Vec<Token> {
+    let mut closing_tokens: Vec<Token> = vec![];
+    let mut kept: Vec<Token> = vec![];
+
+    for token in tokens {
+        let closing = match token {
+            Token::Function(_) | Token::OpenParen => Some(Token::CloseParen),
+            Token::OpenSquare => Some(Token::CloseSquare),
+            Token::OpenCurly => Some(Token::CloseCurly),
+            _ => None,
+        };
+
+        // The depth of the block the token opens, closes or is in
+        let depth = match closing {
+            Some(closing) => {
+                closing_tokens.push(closing);
+                closing_tokens.len()
+            }
+            None if closing_tokens.last() == Some(&token) => {
+                closing_tokens.pop();
+                closing_tokens.len() + 1
+            }
+            None => closing_tokens.len(),
+        };
+
+        if depth <= MAX_NESTING_DEPTH {
+            kept.push(token);
+        }
+    }
+
+    kept
+}
+
+// The tokens are wrapped as component values before they are parsed, so blocks
+// can be parsed again later without going back to the tokens.
+struct Parser {
+    values: Vec<ComponentValue>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(values: Vec<ComponentValue>) -> Parser {
+        Parser {
+            values,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&ComponentValue> {
+        self.values.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<ComponentValue> {
+        let value = self.values.get(self.position).cloned();
+        self.position += 1;
+        value
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-component-value
+    fn consume_component_value(&mut self) -> Option<ComponentValue> {
+        let value = self.next()?;
+
+        let value = match value {
+            ComponentValue::Token(Token::Function(name)) => ComponentValue::Function {
+                name,
+                arguments: self.consume_until(&Token::CloseParen),
+            },
+            ComponentValue::Token(
+                opening @ (Token::OpenCurly | Token::OpenSquare | Token::OpenParen),
+            ) => {
+                let closing = match opening {
+                    Token::OpenCurly => Token::CloseCurly,
+                    Token::OpenSquare => Token::CloseSquare,
+                    _ => Token::CloseParen,
+                };
+
+                ComponentValue::Block {
+                    contents: self.consume_until(&closing),
+                    opening,
+                }
+            }
+            value => value,
+        };
+
+        Some(value)
+    }
+
+    // Consumes component values until the closing token (which is consumed) or the end
+    fn consume_until(&mut self, closing: &Token) -> Vec<ComponentValue> {
+        let mut values: Vec<ComponentValue> = vec![];
+
+        while let Some(value) = self.peek() {
+            if matches!(value, ComponentValue::Token(token) if token == closing) {
+                self.position += 1;
+                break;
+            }
+
+            values.extend(self.consume_component_value());
+        }
+
+        values
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-at-rule
+    fn consume_at_rule(&mut self, name: String) -> Rule {
+        let mut prelude: Vec<ComponentValue> = vec![];
+
+        loop {
+            match self.peek() {
+                None => break,
+                Some(ComponentValue::Token(Token::Semicolon)) => {
+                    self.position += 1;
+                    break;
+                }
+                Some(ComponentValue::Token(Token::OpenCurly)) => {
+                    self.position += 1;
+                    return Rule::At {
+                        name,
+                        prelude,
+                        block: Some(self.consume_until(&Token::CloseCurly)),
+                    };
+                }
+                // Already parsed blocks, when a block is parsed as rules again
+                Some(ComponentValue::Block {
+                    opening: Token::OpenCurly,
+                    contents,
+                }) => {
+                    let block = contents.to_owned();
+                    self.position += 1;
+                    return Rule::At {
+                        name,
+                        prelude,
+                        block: Some(block),
+                    };
+                }
+                Some(_) => prelude.extend(self.consume_component_value()),
+            }
+        }
+
+        Rule::At {
+            name,
+            prelude,
+            block: None,
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-qualified-rule
+    // Returns None if the input ends before the block
+    fn consume_qualified_rule(&mut self) -> Option<Rule> {
+        let mut prelude: Vec<ComponentValue> = vec![];
+
+        loop {
+            match self.peek()? {
+                ComponentValue::Token(Token::OpenCurly) => {
+                    self.position += 1;
+                    return Some(Rule::Qualified {
+                        prelude,
+                        block: self.consume_until(&Token::CloseCurly),
+                    });
+                }
+                ComponentValue::Block {
+                    opening: Token::OpenCurly,
+                    contents,
+                } => {
+                    let block = contents.to_owned();
+                    self.position += 1;
+                    return Some(Rule::Qualified { prelude, block });
+                }
+                _ => prelude.extend(self.consume_component_value()),
+            }
+        }
+    }
+}
+
+// https://www.w3.org/TR/css-syntax-3/#consume-list-of-rules
+fn parse_rules(values: Vec<ComponentValue>, top_level: bool) -> Vec<Rule> {
+    let mut parser = Parser::new(values);
+    let mut rules: Vec<Rule> = vec![];
+
+    while let Some(value) = parser.peek() {
+        match value {
+            ComponentValue::Token(Token::Whitespace) => parser.position += 1,
+            ComponentValue::Token(Token::Cdo | Token::Cdc) if top_level => parser.position += 1,
+            ComponentValue::Token(Token::AtKeyword(name)) => {
+                let name = name.to_owned();
+                parser.position += 1;
+                rules.push(parser.consume_at_rule(name));
+            }
+            _ => rules.extend(parser.consume_qualified_rule()),
+        }
+    }
+
+    rules
+}
+
+// https://www.w3.org/TR/css-syntax-3/#consume-list-of-declarations
+// Nested at-rules and invalid declarations are skipped.
+// The declarations in a style="..." attribute
+pub fn parse_style_attribute(style: &str) -> Vec<Declaration> {
+    parse_declarations(&to_component_values(style))
+}
+
+pub fn parse_declarations(block: &[ComponentValue]) -> Vec<Declaration> {
+    let mut parser = Parser::new(block.to_owned());
+    let mut declarations: Vec<Declaration> = vec![];
+
+    while let Some(value) = parser.peek() {
+        match value {
+            ComponentValue::Token(Token::Whitespace | Token::Semicolon) => parser.position += 1,
+            ComponentValue::Token(Token::AtKeyword(name)) => {
+                let name = name.to_owned();
+                parser.position += 1;
+                parser.consume_at_rule(name);
+            }
+            _ => {
+                let mut values: Vec<ComponentValue> = vec![];
+                while !matches!(
+                    parser.peek(),
+                    None | Some(ComponentValue::Token(Token::Semicolon))
+                ) {
+                    values.extend(parser.consume_component_value());
+                }
+
+                declarations.extend(consume_declaration(values));
+            }
+        }
+    }
+
+    declarations
+}
+
+// https://www.w3.org/TR/css-syntax-3/#consume-declaration
+fn consume_declaration(values: Vec<ComponentValue>) -> Option<Declaration> {
+    let mut values = values.into_iter();
+
+    let name = match values.next()? {
+        ComponentValue::Token(Token::Ident(name)) => name.to_ascii_lowercase(),
+        _ => return None,
+    };
+
+    let mut values = values.skip_while(|value| *value == ComponentValue::Token(Token::Whitespace));
+
+    if values.next()? != ComponentValue::Token(Token::Colon) {
+        return None;
+    }
+
+    let mut value: Vec<ComponentValue> = values
+        .skip_while(|value| *value == ComponentValue::Token(Token::Whitespace))
         .collect();
 
-    if urls.is_empty() {
-        return Err(eyre!("Could not find url in font-face attribute"));
+    let trim_end = |value: &mut Vec<ComponentValue>| {
+        while value.last() == Some(&ComponentValue::Token(Token::Whitespace)) {
+            value.pop();
+        }
+    };
+
+    trim_end(&mut value);
+
+    let important = match value.as_slice() {
+        [.., ComponentValue::Token(Token::Delim('!')), ComponentValue::Token(Token::Ident(ident))]
+            if ident.eq_ignore_ascii_case("important") =>
+        {
+            value.truncate(value.len() - 2);
+            trim_end(&mut value);
+            true
+        }
+        _ => false,
+    };
+
+    Some(Declaration {
+        name,
+        value,
+        important,
+    })
+}
+
+// Turns component values back into css text. Strings are always double quoted and
+// whitespace is collapsed to a single space.
+pub fn serialize(values: &[ComponentValue]) -> String {
+    values.iter().map(serialize_value).collect()
+}
+
+fn serialize_value(value: &ComponentValue) -> String {
+    match value {
+        ComponentValue::Token(token) => serialize_token(token),
+        ComponentValue::Function { name, arguments } => {
+            format!("{}({})", name, serialize(arguments))
+        }
+        ComponentValue::Block { opening, contents } => {
+            let (open, close) = match opening {
+                Token::OpenCurly => ('{', '}'),
+                Token::OpenSquare => ('[', ']'),
+                _ => ('(', ')'),
+            };
+            format!("{}{}{}", open, serialize(contents), close)
+        }
     }
+}
 
-    Ok(urls)
+fn serialize_token(token: &Token) -> String {
+    match token {
+        Token::Ident(value) => value.to_owned(),
+        Token::Function(name) => format!("{}(", name),
+        Token::AtKeyword(name) => format!("@{}", name),
+        Token::Hash { value, .. } => format!("#{}", value),
+        Token::String(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        Token::Url(url) => format!("url({})", url),
+        Token::BadString | Token::BadUrl => String::new(),
+        Token::Delim(c) => c.to_string(),
+        Token::Number { repr, .. } => repr.to_owned(),
+        Token::Percentage { repr, .. } => format!("{}%", repr),
+        Token::Dimension { repr, unit, .. } => format!("{}{}", repr, unit),
+        Token::Whitespace => " ".to_owned(),
+        Token::Cdo => "<!--".to_owned(),
+        Token::Cdc => "-->".to_owned(),
+        Token::Colon => ":".to_owned(),
+        Token::Semicolon => ";".to_owned(),
+        Token::Comma => ",".to_owned(),
+        Token::OpenSquare => "[".to_owned(),
+        Token::CloseSquare => "]".to_owned(),
+        Token::OpenParen => "(".to_owned(),
+        Token::CloseParen => ")".to_owned(),
+        Token::OpenCurly => "{".to_owned(),
+        Token::CloseCurly => "}".to_owned(),
+    }
 }
 
 #[cfg(test)]
@@ -52,13 +514,23 @@ mod tests {
 
     use eyre::Result;
 
-    use crate::parsers::css_parser::parse_css_doc;
+    use crate::parsers::{
+        css_parser::{
+            parse_css_doc, parse_css_imports, parse_font_faces, parse_style_attribute,
+            MAX_NESTING_DEPTH,
+        },
+        font_face::FontFace,
+    };
 
     #[test]
     fn get_urls_from_css_file() -> Result<()> {
-        let css_file = fs::read("test_files/test_mindjek.css").expect("Could not load css file");
+        let css_file =
+            fs::read_to_string("test_files/test_mindjek.css").expect("Could not load css file");
 
-        let urls = parse_css_doc(css_file)?;
+        let urls: Vec<String> = parse_css_doc(&css_file)?
+            .iter()
+            .flat_map(FontFace::urls)
+            .collect();
 
         let expected_results = vec![
             "fonts/fontawesome-webfont.eot?v=4.4.0",
@@ -71,9 +543,13 @@ mod tests {
 
         assert_eq!(urls, expected_results);
 
-        let css_file = fs::read("test_files/test_nrk.css").expect("Could not load css file");
+        let css_file =
+            fs::read_to_string("test_files/test_nrk.css").expect("Could not load css file");
 
-        let urls = parse_css_doc(css_file)?;
+        let urls: Vec<String> = parse_css_doc(&css_file)?
+            .iter()
+            .flat_map(FontFace::urls)
+            .collect();
 
         let expected_results = vec![
             "https://static.nrk.no/nrk-sans/1.2.1/NRKSans_Variable.woff2",
@@ -91,19 +567,18 @@ mod tests {
 
     #[test]
     fn get_urls_from_inline_css() -> Result<()> {
-        let inline_css_strings = vec![
-                                    "\n      .tk-franklin-gothic-urw {\n        font-family: \"franklin-gothic-urw\", sans-serif;\n      }\n    ".to_owned(), 
+        let inline_css_strings = ["\n      .tk-franklin-gothic-urw {\n        font-family: \"franklin-gothic-urw\", sans-serif;\n      }\n    ".to_owned(), 
                                     "\n      @font-face {\n        font-family: tk-franklin-gothic-urw-n4;\n        src: url(https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/l?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&amp;fvd=n4&amp;v=3)\n            format(\"woff2\"),\n          url(https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/d?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&amp;fvd=n4&amp;v=3)\n            format(\"woff\"),\n          url(https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/a?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&amp;fvd=n4&amp;v=3)\n            format(\"opentype\");\n        font-weight: 400;\n        font-style: normal;\n        font-stretch: normal;\n        font-display: auto;\n      }\n    ".to_owned(), 
                                     "\n      body,\n      html {\n        height: 100%;\n        font-family: franklin-gothic-urw, sans-serif;\n        font-weight: 400;\n        font-size: 20px;\n        color: #333e48;\n        margin: 0;\n        box-sizing: border-box;\n      }\n      * {\n        box-sizing: inherit;\n        color: currentColor;\n      }\n      .title-wrapper p:first-of-type {\n        margin-top: 40px;\n        margin-bottom: 13px;\n      }\n      hr {\n        display: none;\n      }\n      p {\n        margin: 0 0 18px;\n      }\n    ".to_owned(), 
                                     "\n      [_nghost-xbj-3] {\n        flex-flow: column nowrap;\n        height: 100vh;\n        padding: 0 39px;\n        width: 100vw;\n      }\n      .top[_ngcontent-xbj-3],\n      [_nghost-xbj-3] {\n        display: flex;\n      }\n      .top[_ngcontent-xbj-3] {\n        height: 10vh;\n        min-height: 100px;\n        justify-content: space-between;\n        padding-top: 29px;\n        z-index: 2;\n      }\n      .top[_ngcontent-xbj-3] a[_ngcontent-xbj-3] {\n        text-decoration: none;\n      }\n      .top[_ngcontent-xbj-3] a[_ngcontent-xbj-3]:hover {\n        text-decoration: underline;\n      }\n      .middle[_ngcontent-xbj-3] {\n        height: 69vh;\n        display: flex;\n        align-items: center;\n      }\n      .bottom[_ngcontent-xbj-3] {\n        display: flex;\n        height: 21vh;\n        justify-content: flex-end;\n      }\n      .bottom[_ngcontent-xbj-3],\n      .middle[_ngcontent-xbj-3],\n      .top[_ngcontent-xbj-3] {\n        width: 100%;\n      }\n      .middle[_ngcontent-xbj-3] {\n        position: relative;\n      }\n      .left-arrow[_ngcontent-xbj-3],\n      .right-arrow[_ngcontent-xbj-3] {\n        position: absolute;\n        top: 0;\n        bottom: 0;\n        width: 50%;\n      }\n      .left-arrow[_ngcontent-xbj-3] {\n        left: 0;\n        cursor: url(/assets/left.png), w-resize;\n      }\n      .right-arrow[_ngcontent-xbj-3] {\n        right: 0;\n        cursor: url(/assets/right.png), e-resize;\n      }\n      .image-wrapper[_ngcontent-xbj-3] {\n        align-items: center;\n        display: flex;\n        justify-content: center;\n        margin: 0 auto;\n        height: 100%;\n        width: 80vw;\n      }\n      svg[_ngcontent-xbj-3] {\n        fill: #333e48;\n      }\n      .title-wrapper[_ngcontent-xbj-3] {\n        flex: 0 1 40%;\n        height: 21vh;\n        max-width: 500px;\n        min-width: 360px;\n        text-align: right;\n      }\n      p[_ngcontent-xbj-3] {\n        margin: 0;\n      }\n      .title-wrapper[_ngcontent-xbj-3] hr[_ngcontent-xbj-3] {\n        display: none;\n      }\n      .image[_ngcontent-xbj-3] {\n        background-size: contain;\n        background-repeat: no-repeat;\n        background-position: 50%;\n        background-color: #fff;\n        height: 69vh;\n        max-width: 800px;\n        width: 80vw;\n      }\n    ".to_owned(), 
                                     "\n      @font-face {\n        font-family: franklin-gothic-urw;\n        src: url(https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/l?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&amp;fvd=n4&amp;v=3)\n            format(\"woff2\"),\n          url(https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/d?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&amp;fvd=n4&amp;v=3)\n            format(\"woff\"),\n          url(https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/a?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&amp;fvd=n4&amp;v=3)\n            format(\"opentype\");\n        font-weight: 400;\n        font-style: normal;\n        font-stretch: normal;\n        font-display: auto;\n      }\n    ".to_owned(), 
-                                    "\n      a[_ngcontent-xbj-1] {\n        text-decoration: none;\n      }\n      .hover[_ngcontent-xbj-1] a[_ngcontent-xbj-1]:hover {\n        text-decoration: underline;\n      }\n    ".to_owned()
-                                    ];
+                                    "\n      a[_ngcontent-xbj-1] {\n        text-decoration: none;\n      }\n      .hover[_ngcontent-xbj-1] a[_ngcontent-xbj-1]:hover {\n        text-decoration: underline;\n      }\n    ".to_owned()];
 
         let urls: Vec<String> = inline_css_strings
             .iter()
-            .filter_map(|inline_css| parse_css_doc(inline_css.as_bytes().to_vec()).ok())
+            .filter_map(|inline_css| parse_css_doc(inline_css).ok())
             .flatten()
+            .flat_map(|font_face| font_face.urls())
             .collect();
 
         let expected_results = vec![
@@ -122,10 +597,13 @@ mod tests {
 
     #[test]
     fn check_src_parsing() -> Result<()> {
-        let css_file =
-            fs::read("test_files/test_check_src_parsing.css").expect("Could not load css file");
+        let css_file = fs::read_to_string("test_files/test_check_src_parsing.css")
+            .expect("Could not load css file");
 
-        let urls = parse_css_doc(css_file)?;
+        let urls: Vec<String> = parse_css_doc(&css_file)?
+            .iter()
+            .flat_map(FontFace::urls)
+            .collect();
 
         let expected_result = vec!["data:application/x-font-woff;base64,testest"];
 
@@ -133,4 +611,118 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn get_font_faces_from_nested_rules() -> Result<()> {
+        let css = r#"
+            /* @font-face { src: url(commented-out.woff) } */
+            .icon::before { content: "}"; }
+            @media (min-width: 700px) {
+                @supports (font-variation-settings: normal) {
+                    @font-face {
+                        font-family: "Open Sans";
+                        src: url("open-sans.woff2") format("woff2"), local(Open Sans);
+                        font-weight: 300 800 !important;
+                    }
+                }
+            }
+            @FONT-FACE { FONT-FAMILY: Icons; src: url( icons.woff ) }
+        "#;
+
+        let font_faces = parse_font_faces(css);
+
+        assert_eq!(font_faces.len(), 2);
+
+        let open_sans = &font_faces[0];
+        let family = open_sans.get("font-family").expect("has font-family");
+        assert_eq!(family.value_to_string(), "\"Open Sans\"");
+
+        let weight = open_sans.get("font-weight").expect("has font-weight");
+        assert_eq!(weight.value_to_string(), "300 800");
+        assert!(weight.important);
+
+        let src = open_sans.get("src").expect("has src");
+        assert_eq!(
+            src.value_to_string(),
+            "url(\"open-sans.woff2\") format(\"woff2\"), local(Open Sans)"
+        );
+        assert_eq!(FontFace::from(open_sans).urls(), vec!["open-sans.woff2"]);
+
+        let icons = &font_faces[1];
+        assert_eq!(
+            icons.get("font-family").map(|d| d.value_to_string()),
+            Some("Icons".to_owned())
+        );
+        assert_eq!(FontFace::from(icons).urls(), vec!["icons.woff"]);
+
+        let font_faces = parse_css_doc(css)?;
+        let families: Vec<Option<&str>> = font_faces
+            .iter()
+            .map(|font_face| font_face.family.as_deref())
+            .collect();
+        assert_eq!(families, vec![Some("Open Sans"), Some("Icons")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_imports_from_css() -> Result<()> {
+        let css = r#"
+            @charset "utf-8";
+            @import url("fonts/lato-font.css?rioeov");
+            @import 'print.css' print;
+            @IMPORT url(theme.css) layer(theme) supports(display: grid);
+            .a { background: url(image.png) }
+            @media screen { @import "ignored.css"; }
+        "#;
+
+        let imports = parse_css_imports(css);
+
+        assert_eq!(
+            imports,
+            vec!["fonts/lato-font.css?rioeov", "print.css", "theme.css"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_deeply_nested_blocks() -> Result<()> {
+        // Would overflow the stack if it was parsed
+        let css = format!(
+            "@import \"a.css\"; @font-face {{ src: url(a.woff2) }} a {}",
+            "(".repeat(100_000)
+        );
+        assert_eq!(parse_css_doc(&css)?.len(), 1);
+        assert_eq!(parse_css_imports(&css), vec!["a.css"]);
+
+        // The rules after a block that is too deep are still read
+        let css = format!(
+            "a {{ b: {}{} }} @font-face {{ src: url(b.woff2) }}",
+            "(".repeat(100_000),
+            ")".repeat(100_000)
+        );
+        assert_eq!(parse_css_doc(&css)?.len(), 1);
+
+        // Only ) closes (
+        assert!(parse_css_doc(&"(]".repeat(100_000)).is_err());
+
+        let declarations = parse_style_attribute(&format!(
+            "font-family: Inter; font: {}",
+            "[".repeat(100_000)
+        ));
+        assert_eq!(declarations.len(), 2);
+        assert_eq!(declarations[0].value_to_string(), "Inter");
+
+        // As deep as allowed, with the outer {} block
+        let depth = MAX_NESTING_DEPTH - 1;
+        let css = format!(
+            "@font-face {{ src: url(a.woff2) }} a {{ b: {}{} }}",
+            "(".repeat(depth),
+            ")".repeat(depth)
+        );
+        assert_eq!(parse_css_doc(&css)?.len(), 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,515 @@
+// Tokenizer following CSS Syntax Module Level 3, section 4.
+// https://www.w3.org/TR/css-syntax-3/#tokenization
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    // The name of a function, the opening parenthesis is part of the token
+    Function(String),
+    AtKeyword(String),
+    // is_id is true when the hash would be a valid id selector
+    Hash {
+        value: String,
+        is_id: bool,
+    },
+    String(String),
+    BadString,
+    // Unquoted url(...). Quoted urls are a Function("url") with a String argument.
+    Url(String),
+    BadUrl,
+    Delim(char),
+    // repr is the number as written, e.g. "400" or "1.5e3"
+    Number {
+        value: f64,
+        repr: String,
+    },
+    Percentage {
+        value: f64,
+        repr: String,
+    },
+    Dimension {
+        value: f64,
+        repr: String,
+        unit: String,
+    },
+    Whitespace,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+pub fn tokenize(css: &str) -> Vec<Token> {
+    let mut tokenizer = Tokenizer {
+        input: preprocess(css),
+        position: 0,
+    };
+
+    let mut tokens: Vec<Token> = vec![];
+    while let Some(token) = tokenizer.consume_token() {
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+// https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+fn preprocess(css: &str) -> Vec<char> {
+    let css = css.replace("\r\n", "\n").replace(['\r', '\x0C'], "\n");
+
+    css.chars()
+        .map(|c| match c {
+            '\0' => char::REPLACEMENT_CHARACTER,
+            c => c,
+        })
+        .collect()
+}
+
+struct Tokenizer {
+    input: Vec<char>,
+    position: usize,
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\n' | '\t' | ' ')
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_ident(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+// https://www.w3.org/TR/css-syntax-3/#starts-with-a-valid-escape
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second != Some('\n')
+}
+
+// https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+fn would_start_ident(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => {
+            second.is_some_and(|c| is_ident_start(c) || c == '-') || is_valid_escape(second, third)
+        }
+        Some('\\') => is_valid_escape(first, second),
+        Some(c) => is_ident_start(c),
+        None => false,
+    }
+}
+
+// https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+fn would_start_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+
+    match first {
+        Some('+' | '-') => is_digit(second) || (second == Some('.') && is_digit(third)),
+        Some('.') => is_digit(second),
+        c => is_digit(c),
+    }
+}
+
+impl Tokenizer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input.get(self.position + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        self.position += 1;
+        c
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn consume_comments(&mut self) {
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            self.position += 2;
+
+            while self.position < self.input.len()
+                && !(self.peek(0) == Some('*') && self.peek(1) == Some('/'))
+            {
+                self.position += 1;
+            }
+
+            // Unterminated comments run to the end of the input
+            self.position = (self.position + 2).min(self.input.len());
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-token
+    fn consume_token(&mut self) -> Option<Token> {
+        self.consume_comments();
+
+        let c = self.next()?;
+
+        let token = match c {
+            c if is_whitespace(c) => {
+                self.consume_whitespace();
+                Token::Whitespace
+            }
+            '"' | '\'' => self.consume_string(c),
+            '#' if self.peek(0).is_some_and(is_ident)
+                || is_valid_escape(self.peek(0), self.peek(1)) =>
+            {
+                let is_id = would_start_ident(self.peek(0), self.peek(1), self.peek(2));
+                Token::Hash {
+                    value: self.consume_ident_sequence(),
+                    is_id,
+                }
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            '+' | '.' if would_start_number(Some(c), self.peek(0), self.peek(1)) => {
+                self.position -= 1;
+                self.consume_numeric()
+            }
+            '-' if would_start_number(Some(c), self.peek(0), self.peek(1)) => {
+                self.position -= 1;
+                self.consume_numeric()
+            }
+            '-' if self.peek(0) == Some('-') && self.peek(1) == Some('>') => {
+                self.position += 2;
+                Token::Cdc
+            }
+            '-' if would_start_ident(Some(c), self.peek(0), self.peek(1)) => {
+                self.position -= 1;
+                self.consume_ident_like()
+            }
+            '<' if self.peek(0) == Some('!')
+                && self.peek(1) == Some('-')
+                && self.peek(2) == Some('-') =>
+            {
+                self.position += 3;
+                Token::Cdo
+            }
+            '@' if would_start_ident(self.peek(0), self.peek(1), self.peek(2)) => {
+                Token::AtKeyword(self.consume_ident_sequence())
+            }
+            '\\' if is_valid_escape(Some(c), self.peek(0)) => {
+                self.position -= 1;
+                self.consume_ident_like()
+            }
+            c if c.is_ascii_digit() => {
+                self.position -= 1;
+                self.consume_numeric()
+            }
+            c if is_ident_start(c) => {
+                self.position -= 1;
+                self.consume_ident_like()
+            }
+            c => Token::Delim(c),
+        };
+
+        Some(token)
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-string-token
+    fn consume_string(&mut self, ending: char) -> Token {
+        let mut value = String::new();
+
+        loop {
+            match self.next() {
+                None => return Token::String(value),
+                Some(c) if c == ending => return Token::String(value),
+                Some('\n') => {
+                    self.position -= 1;
+                    return Token::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    None => {}
+                    Some('\n') => self.position += 1,
+                    Some(_) => value.push(self.consume_escape()),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    // The backslash has already been consumed
+    fn consume_escape(&mut self) -> char {
+        let Some(c) = self.next() else {
+            return char::REPLACEMENT_CHARACTER;
+        };
+
+        if !c.is_ascii_hexdigit() {
+            return c;
+        }
+
+        let mut hex = String::from(c);
+        while hex.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(self.next().unwrap());
+        }
+
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+
+        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+            Some('\0') | None => char::REPLACEMENT_CHARACTER,
+            Some(c) => c,
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn consume_ident_sequence(&mut self) -> String {
+        let mut value = String::new();
+
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident(c) => {
+                    self.position += 1;
+                    value.push(c);
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(1)) => {
+                    self.position += 1;
+                    value.push(self.consume_escape());
+                }
+                _ => return value,
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-numeric-token
+    fn consume_numeric(&mut self) -> Token {
+        let (value, repr) = self.consume_number();
+
+        if would_start_ident(self.peek(0), self.peek(1), self.peek(2)) {
+            return Token::Dimension {
+                value,
+                repr,
+                unit: self.consume_ident_sequence(),
+            };
+        }
+
+        if self.peek(0) == Some('%') {
+            self.position += 1;
+            return Token::Percentage { value, repr };
+        }
+
+        Token::Number { value, repr }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-number
+    fn consume_number(&mut self) -> (f64, String) {
+        let mut repr = String::new();
+        let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+
+        if let Some(c @ ('+' | '-')) = self.peek(0) {
+            repr.push(c);
+            self.position += 1;
+        }
+
+        let consume_digits = |tokenizer: &mut Tokenizer, repr: &mut String| {
+            while is_digit(tokenizer.peek(0)) {
+                repr.push(tokenizer.next().unwrap());
+            }
+        };
+
+        consume_digits(self, &mut repr);
+
+        if self.peek(0) == Some('.') && is_digit(self.peek(1)) {
+            repr.push(self.next().unwrap());
+            consume_digits(self, &mut repr);
+        }
+
+        let has_exponent = matches!(self.peek(0), Some('e' | 'E'))
+            && (is_digit(self.peek(1))
+                || (matches!(self.peek(1), Some('+' | '-')) && is_digit(self.peek(2))));
+
+        if has_exponent {
+            repr.push(self.next().unwrap());
+            if !is_digit(self.peek(0)) {
+                repr.push(self.next().unwrap());
+            }
+            consume_digits(self, &mut repr);
+        }
+
+        (repr.parse::<f64>().unwrap_or(0.0), repr)
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-ident-like-token
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_ident_sequence();
+
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.position += 1;
+
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+
+        while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+
+        let is_quote = |c: Option<char>| matches!(c, Some('"' | '\''));
+        if is_quote(self.peek(0))
+            || (self.peek(0).is_some_and(is_whitespace) && is_quote(self.peek(1)))
+        {
+            return Token::Function(name);
+        }
+
+        self.consume_url()
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-url-token
+    fn consume_url(&mut self) -> Token {
+        let mut value = String::new();
+        self.consume_whitespace();
+
+        loop {
+            match self.next() {
+                None | Some(')') => return Token::Url(value),
+                Some(c) if is_whitespace(c) => {
+                    self.consume_whitespace();
+                    match self.peek(0) {
+                        None => return Token::Url(value),
+                        Some(')') => {
+                            self.position += 1;
+                            return Token::Url(value);
+                        }
+                        Some(_) => {
+                            self.consume_bad_url_remnants();
+                            return Token::BadUrl;
+                        }
+                    }
+                }
+                Some('"' | '\'' | '(') => {
+                    self.consume_bad_url_remnants();
+                    return Token::BadUrl;
+                }
+                Some(c) if is_non_printable(c) => {
+                    self.consume_bad_url_remnants();
+                    return Token::BadUrl;
+                }
+                Some('\\') => {
+                    if is_valid_escape(Some('\\'), self.peek(0)) {
+                        value.push(self.consume_escape());
+                    } else {
+                        self.consume_bad_url_remnants();
+                        return Token::BadUrl;
+                    }
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/css-syntax-3/#consume-remnants-of-bad-url
+    fn consume_bad_url_remnants(&mut self) {
+        loop {
+            match self.next() {
+                None | Some(')') => return,
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Token};
+
+    #[test]
+    fn tokenize_font_face() {
+        let tokens = tokenize(
+            "@font-face{font-family:\"Open Sans\";/* } */src:url( a.woff2 ) format('woff2'),url(\"b\\\".woff\");font-weight:1 950}",
+        );
+
+        let expected_results = vec![
+            Token::AtKeyword("font-face".to_owned()),
+            Token::OpenCurly,
+            Token::Ident("font-family".to_owned()),
+            Token::Colon,
+            Token::String("Open Sans".to_owned()),
+            Token::Semicolon,
+            Token::Ident("src".to_owned()),
+            Token::Colon,
+            Token::Url("a.woff2".to_owned()),
+            Token::Whitespace,
+            Token::Function("format".to_owned()),
+            Token::String("woff2".to_owned()),
+            Token::CloseParen,
+            Token::Comma,
+            Token::Function("url".to_owned()),
+            Token::String("b\".woff".to_owned()),
+            Token::CloseParen,
+            Token::Semicolon,
+            Token::Ident("font-weight".to_owned()),
+            Token::Colon,
+            Token::Number {
+                value: 1.0,
+                repr: "1".to_owned(),
+            },
+            Token::Whitespace,
+            Token::Number {
+                value: 950.0,
+                repr: "950".to_owned(),
+            },
+            Token::CloseCurly,
+        ];
+
+        assert_eq!(tokens, expected_results);
+    }
+
+    #[test]
+    fn tokenize_numbers_and_escapes() {
+        let tokens = tokenize("-1.5e2px 50% \\31 0 #fff 'unterminated\n");
+
+        let expected_results = vec![
+            Token::Dimension {
+                value: -150.0,
+                repr: "-1.5e2".to_owned(),
+                unit: "px".to_owned(),
+            },
+            Token::Whitespace,
+            Token::Percentage {
+                value: 50.0,
+                repr: "50".to_owned(),
+            },
+            Token::Whitespace,
+            // The escape ends at the space after it
+            Token::Ident("10".to_owned()),
+            Token::Whitespace,
+            Token::Hash {
+                value: "fff".to_owned(),
+                is_id: true,
+            },
+            Token::Whitespace,
+            Token::BadString,
+            Token::Whitespace,
+        ];
+
+        assert_eq!(tokens, expected_results);
+    }
+}
@@ -0,0 +1,372 @@
+use super::{
+    css_parser::{get_urls, ComponentValue, FontFaceRule},
+    css_tokenizer::Token,
+};
+
+// https://www.w3.org/TR/css-fonts-4/#font-face-rule
+
+// The descriptors of one @font-face rule. Descriptor values are kept as written,
+// e.g. "700", "bold" or "1 950" for font-weight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFace {
+    pub family: Option<String>,
+    pub weight: Option<String>,
+    pub style: Option<String>,
+    pub stretch: Option<String>,
+    pub unicode_range: Option<String>,
+    pub display: Option<String>,
+    // In order of preference
+    pub sources: Vec<FontSource>,
+    // Sources of earlier src descriptors, which the last one overrides
+    pub overridden_sources: Vec<FontSource>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontSource {
+    Url {
+        url: String,
+        // format("woff2"), lowercased
+        format: Option<String>,
+        // tech(variations, color-COLRv1), lowercased
+        tech: Vec<String>,
+    },
+    // local("Open Sans"), a font installed on the device
+    Local(String),
+}
+
+// Which src entries of an @font-face rule to fetch
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SourceSelection {
+    // Like a browser, the first supported source, and the next ones only if it fails to load
+    #[default]
+    Browser,
+    // Every url, to audit all the variants a site serves
+    All,
+}
+
+// format() values of the files we are able to parse
+const SUPPORTED_FORMATS: [&str; 5] = ["woff2", "woff", "truetype", "opentype", "collection"];
+
+// Every tech() a browser may support, except incremental, which needs a server
+// that supports incremental font transfer
+const SUPPORTED_TECHS: [&str; 10] = [
+    "features-opentype",
+    "features-aat",
+    "features-graphite",
+    "color-colrv0",
+    "color-colrv1",
+    "color-svg",
+    "color-sbix",
+    "color-cbdt",
+    "variations",
+    "palettes",
+];
+
+// Used when there is no format(), e.g. url(font.eot)
+const UNSUPPORTED_EXTENSIONS: [&str; 3] = ["eot", "svg", "svgz"];
+
+// Generic families, and keywords that font-family or font can be set to instead of families
+const NOT_FAMILY_NAMES: [&str; 24] = [
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+    "ui-serif",
+    "ui-sans-serif",
+    "ui-monospace",
+    "ui-rounded",
+    "math",
+    "emoji",
+    "fangsong",
+    "inherit",
+    "initial",
+    "unset",
+    "revert",
+    "revert-layer",
+    "caption",
+    "icon",
+    "menu",
+    "message-box",
+    "small-caption",
+    "status-bar",
+];
+
+impl From<&FontFaceRule> for FontFace {
+    fn from(rule: &FontFaceRule) -> Self {
+        let descriptor = |name: &str| {
+            rule.get(name)
+                .map(|declaration| declaration.value_to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let mut sources: Vec<Vec<FontSource>> = rule
+            .declarations
+            .iter()
+            .filter(|declaration| declaration.name == "src")
+            .map(|declaration| get_sources(&declaration.value))
+            .collect();
+
+        FontFace {
+            family: rule
+                .get("font-family")
+                .and_then(|declaration| get_name(&declaration.value)),
+            weight: descriptor("font-weight"),
+            style: descriptor("font-style"),
+            stretch: descriptor("font-stretch"),
+            unicode_range: descriptor("unicode-range"),
+            display: descriptor("font-display"),
+            sources: sources.pop().unwrap_or_default(),
+            overridden_sources: sources.concat(),
+        }
+    }
+}
+
+impl FontFace {
+    // Urls of every src descriptor, also the ones a later src overrides
+    pub fn urls(&self) -> Vec<String> {
+        self.overridden_sources
+            .iter()
+            .chain(&self.sources)
+            .filter_map(|source| match source {
+                FontSource::Url { url, .. } => Some(url.to_owned()),
+                FontSource::Local(_) => None,
+            })
+            .collect()
+    }
+
+    // Urls of the sources a browser would be able to load, in the order it tries them
+    pub fn supported_urls(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .filter(|source| source.is_supported())
+            .filter_map(|source| match source {
+                FontSource::Url { url, .. } => Some(url.to_owned()),
+                FontSource::Local(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl FontSource {
+    // Whether a browser that only reads the formats we parse would load this source.
+    // local() never is, since no fonts are installed where the crawler runs.
+    pub fn is_supported(&self) -> bool {
+        let FontSource::Url { url, format, tech } = self else {
+            return false;
+        };
+
+        let is_format_supported = match format {
+            // "woff2 supports variations" and "woff2-variations" are older ways
+            // of writing format(woff2) tech(variations)
+            Some(format) => {
+                let format = format.split_whitespace().next().unwrap_or_default();
+                let format = format.strip_suffix("-variations").unwrap_or(format);
+                SUPPORTED_FORMATS.contains(&format)
+            }
+            None => {
+                let path = url.split(['?', '#']).next().unwrap_or_default();
+                let extension = path
+                    .rsplit_once('.')
+                    .map(|(_, extension)| extension.to_ascii_lowercase())
+                    .unwrap_or_default();
+                !UNSUPPORTED_EXTENSIONS.contains(&extension.as_str())
+            }
+        };
+
+        is_format_supported
+            && tech
+                .iter()
+                .all(|tech| SUPPORTED_TECHS.contains(&tech.as_str()))
+    }
+}
+
+// The src descriptor is a comma separated list of
+// url(...) [format(...)]? [tech(...)]? or local(...)
+fn get_sources(values: &[ComponentValue]) -> Vec<FontSource> {
+    values
+        .split(|value| *value == ComponentValue::Token(Token::Comma))
+        .filter_map(|entry| {
+            let mut entry = entry
+                .iter()
+                .filter(|value| **value != ComponentValue::Token(Token::Whitespace));
+
+            let first = entry.next()?;
+
+            if let ComponentValue::Function { name, arguments } = first {
+                if name.eq_ignore_ascii_case("local") {
+                    return get_name(arguments).map(FontSource::Local);
+                }
+            }
+
+            let url = get_urls(std::slice::from_ref(first)).into_iter().next()?;
+            let mut format: Option<String> = None;
+            let mut tech: Vec<String> = vec![];
+
+            for value in entry {
+                let ComponentValue::Function { name, arguments } = value else {
+                    continue;
+                };
+
+                let hints = get_hints(arguments);
+
+                if name.eq_ignore_ascii_case("format") {
+                    format = hints.into_iter().next();
+                } else if name.eq_ignore_ascii_case("tech") {
+                    tech = hints;
+                }
+            }
+
+            Some(FontSource::Url { url, format, tech })
+        })
+        .collect()
+}
+
+// Comma separated strings or keywords, lowercased
+fn get_hints(arguments: &[ComponentValue]) -> Vec<String> {
+    arguments
+        .iter()
+        .filter_map(|argument| match argument {
+            ComponentValue::Token(Token::String(value) | Token::Ident(value)) => {
+                Some(value.to_lowercase())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// The family names in a font-family value, or at the end of a font shorthand, like
+// font: bold 16px/1.5 "Open Sans", sans-serif
+pub fn get_family_names(values: &[ComponentValue]) -> Vec<String> {
+    values
+        .split(|value| *value == ComponentValue::Token(Token::Comma))
+        .filter_map(|family| {
+            // Leaves out the size, and what comes before it, in a font shorthand
+            let start = family
+                .iter()
+                .rposition(|value| {
+                    !matches!(
+                        value,
+                        ComponentValue::Token(
+                            Token::String(_) | Token::Ident(_) | Token::Whitespace
+                        )
+                    )
+                })
+                .map_or(0, |position| position + 1);
+
+            get_name(&family[start..])
+        })
+        .filter(|name| !NOT_FAMILY_NAMES.contains(&name.to_ascii_lowercase().as_str()))
+        .collect()
+}
+
+// A family name is either a string or a sequence of identifiers, like
+// font-family: "Open Sans" or font-family: Open Sans
+fn get_name(values: &[ComponentValue]) -> Option<String> {
+    let mut name: Vec<&str> = vec![];
+
+    for value in values {
+        match value {
+            ComponentValue::Token(Token::String(value)) => return Some(value.to_owned()),
+            ComponentValue::Token(Token::Ident(value)) => name.push(value),
+            ComponentValue::Token(Token::Whitespace) => {}
+            _ => return None,
+        }
+    }
+
+    match name.is_empty() {
+        true => None,
+        false => Some(name.join(" ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+
+    use crate::parsers::css_parser::parse_font_faces;
+
+    use super::{FontFace, FontSource};
+
+    #[test]
+    fn get_font_face_descriptors() -> Result<()> {
+        let css = r#"
+            @font-face {
+                font-family: NRK Sans Variable;
+                font-weight: 1 950;
+                font-style: italic;
+                font-display: swap;
+                unicode-range: U+0000-00FF, U+0131;
+                src: local("NRK Sans"),
+                    url(NRKSans_Variable.woff2) format("woff2") tech(variations),
+                    url('NRKSans_Variable.ttf') format(truetype);
+            }
+        "#;
+
+        let font_faces: Vec<FontFace> = parse_font_faces(css).iter().map(FontFace::from).collect();
+
+        let expected_results = vec![FontFace {
+            family: Some("NRK Sans Variable".to_owned()),
+            weight: Some("1 950".to_owned()),
+            style: Some("italic".to_owned()),
+            stretch: None,
+            unicode_range: Some("U+0000-00FF, U+0131".to_owned()),
+            display: Some("swap".to_owned()),
+            sources: vec![
+                FontSource::Local("NRK Sans".to_owned()),
+                FontSource::Url {
+                    url: "NRKSans_Variable.woff2".to_owned(),
+                    format: Some("woff2".to_owned()),
+                    tech: vec!["variations".to_owned()],
+                },
+                FontSource::Url {
+                    url: "NRKSans_Variable.ttf".to_owned(),
+                    format: Some("truetype".to_owned()),
+                    tech: vec![],
+                },
+            ],
+            overridden_sources: vec![],
+        }];
+
+        assert_eq!(font_faces, expected_results);
+        assert_eq!(
+            font_faces[0].urls(),
+            vec!["NRKSans_Variable.woff2", "NRKSans_Variable.ttf"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn select_supported_sources() -> Result<()> {
+        let css = r#"
+            @font-face {
+                font-family: Selection;
+                src: local(Selection),
+                    url(font.eot?#iefix),
+                    url(font.svg#selection) format("svg"),
+                    url(font-incremental.woff2) format(woff2) tech(incremental),
+                    url(font-variable.woff2) format("woff2 supports variations"),
+                    url(font-variable.ttf) format("truetype-variations"),
+                    url(font.woff) format(woff) tech(color-COLRv1, variations),
+                    url(font.otf);
+            }
+        "#;
+
+        let font_faces: Vec<FontFace> = parse_font_faces(css).iter().map(FontFace::from).collect();
+
+        assert_eq!(
+            font_faces[0].supported_urls(),
+            vec![
+                "font-variable.woff2",
+                "font-variable.ttf",
+                "font.woff",
+                "font.otf"
+            ]
+        );
+        assert_eq!(font_faces[0].urls().len(), 7);
+
+        Ok(())
+    }
+}
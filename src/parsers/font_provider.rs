@@ -0,0 +1,334 @@
+use url::Url;
+
+// Web font services, recognised by the host of a stylesheet, script or font url
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontProvider {
+    GoogleFonts,
+    AdobeFonts,
+    FontsCom,
+    CloudTypography,
+    BunnyFonts,
+    FontAwesome,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderUrl {
+    pub provider: FontProvider,
+    pub url: String,
+    // What the url asks for, e.g. family=Roboto:ital,wght@0,400;1,700
+    pub families: Vec<ProviderFamily>,
+    pub subsets: Vec<String>,
+    // Adobe Fonts, Fonts.com, Cloud.typography and Font Awesome serve a kit
+    // or project made for the site
+    pub kit_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderFamily {
+    // None when the url only tells the weight and style, like Adobe Fonts files
+    pub name: Option<String>,
+    // As written, e.g. "700" or "100..900"
+    pub weights: Vec<String>,
+    pub styles: Vec<String>,
+}
+
+pub fn detect_provider(url: &Url) -> Option<ProviderUrl> {
+    let host = url.host_str()?.to_ascii_lowercase();
+
+    let provider = match host.as_str() {
+        "fonts.googleapis.com" | "fonts.gstatic.com" => FontProvider::GoogleFonts,
+        "use.typekit.net" | "use.typekit.com" | "p.typekit.net" => FontProvider::AdobeFonts,
+        "fast.fonts.net" | "fast.fonts.com" => FontProvider::FontsCom,
+        "cloud.typography.com" => FontProvider::CloudTypography,
+        "fonts.bunny.net" => FontProvider::BunnyFonts,
+        host if host.ends_with(".fontawesome.com") => FontProvider::FontAwesome,
+        _ => return None,
+    };
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+
+    let mut provider_url = ProviderUrl {
+        provider,
+        url: url.to_string(),
+        families: vec![],
+        subsets: vec![],
+        kit_id: None,
+    };
+
+    match (provider, segments.as_slice()) {
+        // https://developers.google.com/fonts/docs/css2
+        (FontProvider::GoogleFonts | FontProvider::BunnyFonts, ["css2"]) => {
+            provider_url.families = get_query_values(url, "family")
+                .iter()
+                .map(|family| parse_css2_family(family))
+                .collect();
+            provider_url.subsets = get_subsets(url);
+        }
+        // https://developers.google.com/fonts/docs/getting_started, which Bunny Fonts also uses
+        (FontProvider::GoogleFonts | FontProvider::BunnyFonts, ["css"]) => {
+            provider_url.families = get_query_values(url, "family")
+                .iter()
+                .flat_map(|families| families.split('|'))
+                .filter(|family| !family.is_empty())
+                .map(parse_css1_family)
+                .collect();
+            provider_url.subsets = get_subsets(url);
+        }
+        // Font files, like /s/roboto/v30/KFOmCnqEu92Fr1Mu4mxK.woff2
+        (FontProvider::GoogleFonts, ["s", family, ..]) => {
+            provider_url.families = vec![ProviderFamily {
+                name: Some(family.to_string()),
+                weights: vec![],
+                styles: vec![],
+            }];
+        }
+        // Font files, like /af/9cb78a/0000000000000000000118ad/27/l?primer=...&fvd=n4&v=3
+        (FontProvider::AdobeFonts, ["af", ..]) => {
+            provider_url.families = get_query_values(url, "fvd")
+                .iter()
+                .filter_map(|fvd| parse_fvd(fvd))
+                .collect();
+        }
+        // Kits and projects, like use.typekit.net/abc1def.css, fast.fonts.net/cssapi/<id>.css
+        // or kit.fontawesome.com/<id>.js
+        (FontProvider::AdobeFonts, [kit])
+        | (FontProvider::FontsCom, ["cssapi" | "jsapi", kit])
+        | (FontProvider::FontAwesome, [kit]) => {
+            provider_url.kit_id = kit.split('.').next().map(|kit| kit.to_owned());
+        }
+        // Like cloud.typography.com/7654321/123456/css/fonts.css
+        (FontProvider::CloudTypography, [account, ..]) => {
+            provider_url.kit_id = Some(account.to_string());
+        }
+        _ => {}
+    }
+
+    Some(provider_url)
+}
+
+fn get_query_values(url: &Url, key: &str) -> Vec<String> {
+    url.query_pairs()
+        .filter(|(name, _)| name == key)
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+fn get_subsets(url: &Url) -> Vec<String> {
+    get_query_values(url, "subset")
+        .iter()
+        .flat_map(|subsets| subsets.split(','))
+        .filter(|subset| !subset.is_empty())
+        .map(|subset| subset.to_owned())
+        .collect()
+}
+
+// Name[:axes@tuples], like Roboto:ital,wght@0,400;1,700 or Inter:wght@100..900
+fn parse_css2_family(family: &str) -> ProviderFamily {
+    let (name, axes) = family.split_once(':').unwrap_or((family, ""));
+    let (axes, tuples) = axes.split_once('@').unwrap_or(("", ""));
+
+    let axes: Vec<&str> = axes.split(',').collect();
+    let weight_index = axes.iter().position(|axis| *axis == "wght");
+    let ital_index = axes.iter().position(|axis| *axis == "ital");
+
+    let mut weights: Vec<String> = vec![];
+    let mut styles: Vec<String> = vec![];
+
+    for tuple in tuples.split(';').filter(|tuple| !tuple.is_empty()) {
+        let values: Vec<&str> = tuple.split(',').collect();
+
+        let weight = weight_index
+            .and_then(|i| values.get(i))
+            .map(|weight| weight.to_string())
+            .unwrap_or_else(|| "400".to_owned());
+
+        let style = match ital_index.and_then(|i| values.get(i)) {
+            Some(&"1") => "italic",
+            _ => "normal",
+        };
+
+        push_unique(&mut weights, weight);
+        push_unique(&mut styles, style.to_owned());
+    }
+
+    if weights.is_empty() {
+        weights.push("400".to_owned());
+        styles.push("normal".to_owned());
+    }
+
+    ProviderFamily {
+        name: Some(name.trim().to_owned()),
+        weights,
+        styles,
+    }
+}
+
+// Name[:variants[:subsets]], like Roboto:400,700italic or Open Sans:regular,b,bi
+fn parse_css1_family(family: &str) -> ProviderFamily {
+    let mut parts = family.split(':');
+    let name = parts.next().unwrap_or_default();
+    let variants = parts.next().unwrap_or_default();
+
+    let mut weights: Vec<String> = vec![];
+    let mut styles: Vec<String> = vec![];
+
+    for variant in variants.split(',').filter(|variant| !variant.is_empty()) {
+        let variant = variant.trim().to_ascii_lowercase();
+        let digits: String = variant.chars().take_while(char::is_ascii_digit).collect();
+        let rest = &variant[digits.len()..];
+
+        let (weight, is_italic) = match (digits.as_str(), rest) {
+            ("", "regular" | "r") => ("400", false),
+            ("", "italic" | "i") => ("400", true),
+            ("", "bold" | "b") => ("700", false),
+            ("", "bolditalic" | "bi") => ("700", true),
+            ("", _) => continue,
+            (digits, rest) => (digits, rest == "italic" || rest == "i"),
+        };
+
+        push_unique(&mut weights, weight.to_owned());
+        push_unique(
+            &mut styles,
+            match is_italic {
+                true => "italic".to_owned(),
+                false => "normal".to_owned(),
+            },
+        );
+    }
+
+    if weights.is_empty() {
+        weights.push("400".to_owned());
+        styles.push("normal".to_owned());
+    }
+
+    ProviderFamily {
+        name: Some(name.trim().to_owned()),
+        weights,
+        styles,
+    }
+}
+
+// Font variation description, a style letter and a weight digit, like n4 or i7
+fn parse_fvd(fvd: &str) -> Option<ProviderFamily> {
+    let mut chars = fvd.chars();
+
+    let style = match chars.next()? {
+        'n' => "normal",
+        'i' => "italic",
+        'o' => "oblique",
+        _ => return None,
+    };
+    let weight = chars.next()?.to_digit(10)? * 100;
+
+    Some(ProviderFamily {
+        name: None,
+        weights: vec![weight.to_string()],
+        styles: vec![style.to_owned()],
+    })
+}
+
+fn push_unique(values: &mut Vec<String>, value: String) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+    use url::Url;
+
+    use super::{detect_provider, FontProvider, ProviderFamily};
+
+    fn family(name: Option<&str>, weights: &[&str], styles: &[&str]) -> ProviderFamily {
+        ProviderFamily {
+            name: name.map(|name| name.to_owned()),
+            weights: weights.iter().map(|weight| weight.to_string()).collect(),
+            styles: styles.iter().map(|style| style.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn detect_google_and_bunny_fonts() -> Result<()> {
+        let url = Url::parse("https://fonts.googleapis.com/css2?family=Roboto:ital,wght@0,400;0,700;1,400&family=Open+Sans&family=Inter:wght@100..900&display=swap")?;
+        let provider_url = detect_provider(&url).expect("is a provider url");
+
+        assert_eq!(provider_url.provider, FontProvider::GoogleFonts);
+        assert_eq!(
+            provider_url.families,
+            vec![
+                family(Some("Roboto"), &["400", "700"], &["normal", "italic"]),
+                family(Some("Open Sans"), &["400"], &["normal"]),
+                family(Some("Inter"), &["100..900"], &["normal"]),
+            ]
+        );
+
+        let url = Url::parse(
+            "https://fonts.googleapis.com/css?family=Lato:400,700italic|Open+Sans:b&subset=latin,latin-ext",
+        )?;
+        let provider_url = detect_provider(&url).expect("is a provider url");
+
+        assert_eq!(
+            provider_url.families,
+            vec![
+                family(Some("Lato"), &["400", "700"], &["normal", "italic"]),
+                family(Some("Open Sans"), &["700"], &["normal"]),
+            ]
+        );
+        assert_eq!(provider_url.subsets, vec!["latin", "latin-ext"]);
+
+        let url = Url::parse("https://fonts.bunny.net/css?family=inter:400,500")?;
+        let provider_url = detect_provider(&url).expect("is a provider url");
+
+        assert_eq!(provider_url.provider, FontProvider::BunnyFonts);
+        assert_eq!(
+            provider_url.families,
+            vec![family(Some("inter"), &["400", "500"], &["normal"])]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_kits() -> Result<()> {
+        let url = Url::parse("https://use.typekit.net/af/9cb78a/0000000000000000000118ad/27/l?primer=7cdcb44be4a7db8877ffa5c0007b8dd865b3bbc383831fe2ea177f62257a9191&fvd=i7&v=3")?;
+        let provider_url = detect_provider(&url).expect("is a provider url");
+
+        assert_eq!(provider_url.provider, FontProvider::AdobeFonts);
+        assert_eq!(
+            provider_url.families,
+            vec![family(None, &["700"], &["italic"])]
+        );
+
+        let kits = [
+            ("https://use.typekit.net/abc1def.css", "abc1def"),
+            (
+                "https://fast.fonts.net/cssapi/e8a1c4d2-1d5b-4a1e-9f7c-000000000000.css",
+                "e8a1c4d2-1d5b-4a1e-9f7c-000000000000",
+            ),
+            (
+                "https://cloud.typography.com/7654321/123456/css/fonts.css",
+                "7654321",
+            ),
+            ("https://kit.fontawesome.com/0123abcd45.js", "0123abcd45"),
+        ];
+
+        for (url, kit_id) in kits {
+            let provider_url = detect_provider(&Url::parse(url)?).expect("is a provider url");
+            assert_eq!(provider_url.kit_id.as_deref(), Some(kit_id));
+        }
+
+        assert_eq!(
+            detect_provider(&Url::parse(
+                "https://static.nrk.no/nrk-sans/1.2.1/NRKSans_Variable.woff2"
+            )?),
+            None
+        );
+
+        Ok(())
+    }
+}
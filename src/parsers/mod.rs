@@ -1,3 +1,8 @@
+pub mod content_type;
 pub mod css_parser;
+pub mod css_tokenizer;
+pub mod font_face;
+pub mod font_provider;
 pub mod html_parser;
+pub mod robots_parser;
 pub mod url_parser;
@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use url::Url;
+
+// https://www.rfc-editor.org/rfc/rfc9309
+
+// The rules of a robots.txt that apply to one user-agent
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RobotsTxt {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    is_allowed: bool,
+    // A path prefix, where * matches any characters and a trailing $ the end of the path
+    pattern: String,
+}
+
+// A group of rules, for the user-agents listed before them
+#[derive(Debug, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsTxt {
+    // When there is no robots.txt
+    pub fn allow_all() -> Self {
+        RobotsTxt::default()
+    }
+
+    // When robots.txt could not be read, e.g. the server failed
+    pub fn disallow_all() -> Self {
+        RobotsTxt {
+            rules: vec![Rule {
+                is_allowed: false,
+                pattern: "/".to_owned(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    // The groups for the user-agent token are used, or else the groups for *.
+    // Groups for the same user-agent are combined.
+    pub fn parse(content: &str, user_agent: &str) -> Self {
+        let groups = get_groups(content);
+
+        let is_for = |group: &&Group, user_agent: &str| {
+            group
+                .user_agents
+                .iter()
+                .any(|group_agent| group_agent.eq_ignore_ascii_case(user_agent))
+        };
+
+        let mut matching_groups: Vec<&Group> = groups
+            .iter()
+            .filter(|group| is_for(group, user_agent))
+            .collect();
+
+        if matching_groups.is_empty() {
+            matching_groups = groups.iter().filter(|group| is_for(group, "*")).collect();
+        }
+
+        RobotsTxt {
+            rules: matching_groups
+                .iter()
+                .flat_map(|group| group.rules.to_owned())
+                .collect(),
+            crawl_delay: matching_groups.iter().find_map(|group| group.crawl_delay),
+        }
+    }
+
+    // The longest matching rule decides, and allow wins a tie
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+
+        if path == "/robots.txt" {
+            return true;
+        }
+
+        self.rules
+            .iter()
+            .filter(|rule| matches_pattern(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.is_allowed))
+            .is_none_or(|rule| rule.is_allowed)
+    }
+}
+
+fn get_groups(content: &str) -> Vec<Group> {
+    let mut groups: Vec<Group> = vec![];
+    let mut current = Group::default();
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "user-agent" => {
+                // A user-agent after rules starts a new group
+                if !current.rules.is_empty() || current.crawl_delay.is_some() {
+                    groups.push(std::mem::take(&mut current));
+                }
+
+                // Only the product token counts, e.g. fontsbot in fontsbot/1.0
+                let token = value.split(['/', ' ']).next().unwrap_or_default();
+                current.user_agents.push(token.to_owned());
+            }
+            // An empty disallow allows everything, so it adds nothing
+            "disallow" if !value.is_empty() => current.rules.push(Rule {
+                is_allowed: false,
+                pattern: value.to_owned(),
+            }),
+            "allow" if !value.is_empty() => current.rules.push(Rule {
+                is_allowed: true,
+                pattern: value.to_owned(),
+            }),
+            "crawl-delay" => {
+                current.crawl_delay = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                    .map(Duration::from_secs_f64);
+            }
+            _ => {}
+        }
+    }
+
+    groups.push(current);
+
+    // Rules before the first user-agent belong to no group
+    groups
+        .into_iter()
+        .filter(|group| !group.user_agents.is_empty())
+        .collect()
+}
+
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let (pattern, is_anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return !is_anchored || rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    match is_anchored {
+        true => rest.ends_with(last),
+        false => rest.contains(last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use eyre::Result;
+    use url::Url;
+
+    use super::RobotsTxt;
+
+    #[test]
+    fn parse_robots_txt() -> Result<()> {
+        let content = r#"
+            # Everyone else
+            User-agent: *
+            Disallow: /
+
+            User-agent: googlebot
+            User-agent: fontsbot/1.0
+            Disallow: /private/
+            Allow: /private/fonts/
+            Disallow: /*.woff2$
+            Disallow: /search?
+            Crawl-delay: 1.5
+
+            User-agent: FontsBot
+            Disallow: /tmp # scratch files
+        "#;
+
+        let robots = RobotsTxt::parse(content, "fontsbot");
+        let is_allowed = |path: &str| -> Result<bool> {
+            Ok(robots.is_allowed(&Url::parse("https://example.com")?.join(path)?))
+        };
+
+        assert!(is_allowed("/")?);
+        assert!(is_allowed("/robots.txt")?);
+        assert!(!is_allowed("/private/page.html")?);
+        assert!(is_allowed("/private/fonts/Inter.ttf")?);
+        assert!(!is_allowed("/static/Inter.woff2")?);
+        assert!(is_allowed("/static/Inter.woff2?v=2")?);
+        assert!(!is_allowed("/search?q=fonts")?);
+        assert!(is_allowed("/search")?);
+        assert!(!is_allowed("/tmp/font.css")?);
+        assert_eq!(robots.crawl_delay, Some(Duration::from_millis(1500)));
+
+        let robots = RobotsTxt::parse(content, "otherbot");
+        assert!(!robots.is_allowed(&Url::parse("https://example.com/")?));
+        assert!(robots.is_allowed(&Url::parse("https://example.com/robots.txt")?));
+        assert_eq!(robots.crawl_delay, None);
+
+        let robots = RobotsTxt::parse("User-agent: *\nDisallow:\n", "fontsbot");
+        assert!(robots.is_allowed(&Url::parse("https://example.com/fonts.css")?));
+
+        Ok(())
+    }
+}
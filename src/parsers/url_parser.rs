@@ -1,45 +1,119 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use percent_encoding::percent_decode_str;
 use tap::TapFallible;
 use url::{ParseError, Url};
 
-use eyre::{Context, Result};
+use eyre::{eyre, Context, Result};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FontUrl {
     Http(Url),
     Data(Url),
 }
 
+// The content of a data: url
+#[derive(Debug, PartialEq)]
+pub struct DataUrl {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+// Browsers don't require padding, and ignore bits left over at the end
+const FORGIVING_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
 pub fn parse_to_font_urls(urls: Vec<String>, base_url: &str) -> Result<Vec<FontUrl>> {
     // Parse using Url to map to FontUrl enum
     let urls: Vec<FontUrl> = urls
         .into_iter()
         .filter_map(|url| {
-            parse_to_url(&url, &base_url)
+            parse_to_url(&url, base_url)
                 .tap_err(|err| tracing::error!(error = ?err, "Unable to parse url: {url}"))
                 .ok()
         })
-        .filter_map(|parsed| match parsed.scheme() {
-            "http" | "https" => Some(FontUrl::Http(parsed)),
-            "data" => Some(FontUrl::Data(parsed)),
-            _ => {
-                tracing::error!("Unknown scheme: {}", parsed.scheme());
-                None
-            }
-        })
+        .filter_map(to_font_url)
         .collect();
 
     Ok(urls)
 }
 
+pub fn to_font_url(url: Url) -> Option<FontUrl> {
+    match url.scheme() {
+        "http" | "https" => Some(FontUrl::Http(url)),
+        "data" => Some(FontUrl::Data(url)),
+        _ => {
+            tracing::error!("Unknown scheme: {}", url.scheme());
+            None
+        }
+    }
+}
+
+// https://fetch.spec.whatwg.org/#data-url-processor
+// data:[<mime type>][;base64],<data>
+pub fn decode_data_url(url: &Url) -> Result<DataUrl> {
+    if url.scheme() != "data" {
+        return Err(eyre!("Not a data url: {}", url.scheme()));
+    }
+
+    // Everything after "data:", without the fragment
+    let content = &url.as_str()["data:".len()..];
+    let content = content.split('#').next().unwrap_or_default();
+
+    let (mime_type, data) = content
+        .split_once(',')
+        .ok_or_else(|| eyre!("Could not find ',' in data url"))?;
+
+    let mime_type = mime_type.trim();
+    let data = percent_decode_str(data).collect::<Vec<u8>>();
+
+    let (mime_type, data) = match mime_type
+        .len()
+        .checked_sub(";base64".len())
+        .filter(|&i| mime_type[i..].eq_ignore_ascii_case(";base64"))
+    {
+        Some(i) => {
+            let data: Vec<u8> = data
+                .into_iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+
+            let data = FORGIVING_BASE64
+                .decode(data)
+                .wrap_err("Unable to decode base64 in data url")?;
+
+            (mime_type[..i].trim_end(), data)
+        }
+        None => (mime_type, data),
+    };
+
+    // Just the type and subtype, e.g. font/woff2 from font/woff2;charset=utf-8
+    let mime_type = match mime_type.split(';').next().unwrap_or_default().trim() {
+        "" => "text/plain".to_owned(),
+        mime_type => percent_decode_str(mime_type)
+            .decode_utf8_lossy()
+            .to_ascii_lowercase(),
+    };
+
+    Ok(DataUrl { mime_type, data })
+}
+
 pub fn parse_to_url(url: &str, base_url: &str) -> Result<Url> {
-    let maybe_not_base = Url::parse(&url);
+    let maybe_not_base = Url::parse(url);
 
     let parsed_url = match maybe_not_base {
         Ok(url) => url,
         Err(err) => {
             if err == ParseError::RelativeUrlWithoutBase {
                 return Url::parse(base_url)
-                    .and_then(|base| base.join(&url))
+                    .and_then(|base| base.join(url))
                     .wrap_err(err);
             }
             return Err(err).wrap_err(format!("Unable to parse font url correctly for {}", url));
@@ -55,17 +129,25 @@ mod tests {
     use std::fs;
 
     use eyre::Result;
+    use url::Url;
 
-    use crate::parsers::{css_parser::parse_css_doc, url_parser::FontUrl};
+    use crate::{
+        font_parser::FontData,
+        parsers::{css_parser::parse_css_doc, font_face::FontFace, url_parser::FontUrl},
+    };
 
-    use super::parse_to_font_urls;
+    use super::{decode_data_url, parse_to_font_urls, DataUrl};
 
     #[test]
     fn parse_base64_url() -> Result<()> {
-        let css_file = fs::read("test_files/test_base64_url.css").expect("Could not load css file");
+        let css_file =
+            fs::read_to_string("test_files/test_base64_url.css").expect("Could not load css file");
 
         let base_url = "http://test.no";
-        let urls = parse_css_doc(css_file)?;
+        let urls = parse_css_doc(&css_file)?
+            .iter()
+            .flat_map(FontFace::urls)
+            .collect();
 
         let font_urls = parse_to_font_urls(urls, base_url)?;
 
@@ -81,4 +163,43 @@ mod tests {
         println!("font url: {:?}", font_url);
         Err(eyre::eyre!("Did not parse to FontUrl::Data"))
     }
+
+    #[test]
+    fn decode_data_urls() -> Result<()> {
+        let url = Url::parse("data:font/woff2;base64,d09GMg")?;
+        let expected_result = DataUrl {
+            mime_type: "font/woff2".to_owned(),
+            data: b"wOF2".to_vec(),
+        };
+        assert_eq!(decode_data_url(&url)?, expected_result);
+
+        let url = Url::parse("data:,wOF2%00%01")?;
+        let expected_result = DataUrl {
+            mime_type: "text/plain".to_owned(),
+            data: b"wOF2\x00\x01".to_vec(),
+        };
+        assert_eq!(decode_data_url(&url)?, expected_result);
+
+        let url = Url::parse("data:font/woff2;base64")?;
+        assert!(decode_data_url(&url).is_err());
+
+        let css_file =
+            fs::read_to_string("test_files/test_base64_url.css").expect("Could not load css file");
+        let urls = parse_css_doc(&css_file)?
+            .iter()
+            .flat_map(FontFace::urls)
+            .collect();
+
+        let Some(FontUrl::Data(url)) = parse_to_font_urls(urls, "http://test.no")?.pop() else {
+            return Err(eyre::eyre!("Did not parse to FontUrl::Data"));
+        };
+
+        let data_url = decode_data_url(&url)?;
+        assert_eq!(data_url.mime_type, "application/x-font-ttf");
+
+        let font_data = FontData::from_bytes(&data_url.data)?;
+        assert_eq!(font_data.family_name, "webflow-icons");
+
+        Ok(())
+    }
 }
@@ -61,7 +61,7 @@ impl<T> Injector for ChannelMessage<T> {
 
 impl<T> Extractor for ChannelMessage<T> {
     fn get(&self, key: &str) -> Option<&str> {
-        self.context.get(&key.to_owned()).map(|val| val.as_str())
+        self.context.get(key).map(|val| val.as_str())
     }
 
     fn keys(&self) -> Vec<&str> {
@@ -1,10 +1,16 @@
+use std::sync::Arc;
+
 use async_channel::{Receiver, Sender};
 use eyre::Context;
 use tokio::task::JoinHandle;
 use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::crawler::browser_crawler::BrowserCrawler;
+use crate::crawler::{
+    browser_crawler::{BrowserCrawler, BrowserOptions},
+    download::DownloadOptions,
+    host_limiter::HostLimiter,
+};
 
 use super::{channel_message::ChannelMessage, Page};
 
@@ -12,9 +18,25 @@ pub fn start_html_browser_tasks(
     html_browser_node_rx: &Receiver<ChannelMessage<String>>,
     page_node_tx: &Sender<ChannelMessage<Page>>,
     no_of_tasks: i32,
+    browser_options: BrowserOptions,
+    host_limiter: &Arc<HostLimiter>,
+    download_options: DownloadOptions,
 ) -> Vec<JoinHandle<()>> {
+    // One browser for all the tasks, with a tab for each
+    let crawler: BrowserCrawler = BrowserCrawler::new(no_of_tasks as usize)
+        .with_options(browser_options)
+        .with_host_limiter(host_limiter.clone())
+        .with_download_options(download_options);
+
     (0..no_of_tasks)
-        .map(|i| start_html_browser_task(html_browser_node_rx.clone(), page_node_tx.clone(), i))
+        .map(|i| {
+            start_html_browser_task(
+                html_browser_node_rx.clone(),
+                page_node_tx.clone(),
+                i,
+                crawler.clone(),
+            )
+        })
         .collect()
 }
 
@@ -22,9 +44,8 @@ fn start_html_browser_task(
     html_browser_node_rx: Receiver<ChannelMessage<String>>,
     page_node_tx: Sender<ChannelMessage<Page>>,
     i: i32,
+    crawler: BrowserCrawler,
 ) -> JoinHandle<()> {
-    let crawler: BrowserCrawler = BrowserCrawler::new().unwrap();
-
     tokio::spawn(async move {
         while let Ok(message) = html_browser_node_rx.recv().await {
             let span = tracing::info_span!("html_browser_job");
@@ -59,13 +80,14 @@ async fn fetch_html_content_with_browser(
 ) -> eyre::Result<()> {
     tracing::info!("Received job on task {}.", i);
 
-    let content = crawler
-        .get_page_content(&url)
+    let browser_page = crawler
+        .get_page(&url)
+        .await
         .wrap_err(format!("Unable to get page content for {}.", &url))?;
 
     tracing::info!("gotten page content for url: {}", &url);
 
-    let page = Page::new(url.clone(), content);
+    let page = Page::from_browser_page(url.clone(), browser_page);
     let mut message = ChannelMessage::new(root_span.to_owned(), page);
     message.inject(&tracing::Span::current().context());
 
@@ -1,20 +1,46 @@
+use std::sync::Arc;
+
 use async_channel::{Receiver, Sender};
 use eyre::Context;
 use tokio::task::JoinHandle;
 use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::crawler::http_crawler::HttpCrawler;
+use crate::crawler::{
+    download::DownloadOptions,
+    fetch_error::FetchError,
+    host_limiter::HostLimiter,
+    http_crawler::{get_fetch_error, is_disallowed, HttpCrawler},
+};
 
 use super::{channel_message::ChannelMessage, Page};
 
+// Pages that never got to the verifier, so that a site that was down is not
+// counted as a site without fonts
+#[derive(Debug, Default)]
+pub struct FailedPages {
+    // Pages robots.txt does not let us fetch
+    pub disallowed_urls: Vec<String>,
+    pub fetch_errors: Vec<(String, FetchError)>,
+}
+
 pub fn start_html_http_tasks(
     html_http_node_rx: &Receiver<ChannelMessage<String>>,
     verifier_node_tx: &Sender<ChannelMessage<Page>>,
     no_of_tasks: i32,
-) -> Vec<JoinHandle<()>> {
+    host_limiter: &Arc<HostLimiter>,
+    download_options: DownloadOptions,
+) -> Vec<JoinHandle<FailedPages>> {
     (0..no_of_tasks)
-        .map(|i| start_html_http_task(html_http_node_rx.clone(), verifier_node_tx.clone(), i))
+        .map(|i| {
+            start_html_http_task(
+                html_http_node_rx.clone(),
+                verifier_node_tx.clone(),
+                i,
+                host_limiter.clone(),
+                download_options,
+            )
+        })
         .collect()
 }
 
@@ -22,10 +48,16 @@ fn start_html_http_task(
     html_http_node_rx: Receiver<ChannelMessage<String>>,
     verifier_node_tx: Sender<ChannelMessage<Page>>,
     i: i32,
-) -> JoinHandle<()> {
-    let crawler: HttpCrawler = HttpCrawler::new().unwrap();
+    host_limiter: Arc<HostLimiter>,
+    download_options: DownloadOptions,
+) -> JoinHandle<FailedPages> {
+    let crawler: HttpCrawler = HttpCrawler::new()
+        .unwrap()
+        .with_host_limiter(host_limiter)
+        .with_download_options(download_options);
 
     tokio::spawn(async move {
+        let mut failed_pages = FailedPages::default();
         while let Ok(message) = html_http_node_rx.recv().await {
             let span = tracing::info_span!("html_http_job");
             span.set_parent(message.extract());
@@ -42,10 +74,18 @@ fn start_html_http_task(
             .instrument(span)
             .await
             {
+                if is_disallowed(&err) {
+                    failed_pages.disallowed_urls.push(content.to_owned());
+                } else if let Some(fetch_error) = get_fetch_error(&err) {
+                    failed_pages
+                        .fetch_errors
+                        .push((content.to_owned(), fetch_error.to_owned()));
+                }
                 tracing::error!(error = ?err, "Failed to perform html http job");
             }
         }
         tracing::info!("http html task {} done.", i);
+        failed_pages
     })
 }
 
@@ -1,6 +1,28 @@
-use crate::{crawler::http_crawler::HttpCrawler, font_parser::FontData};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-use super::Result;
+use eyre::Context;
+use tap::TapFallible;
+use url::Url;
+
+use crate::{
+    crawler::{
+        browser_crawler::{BrowserPage, LoadedFont},
+        fetch_error::FetchError,
+        font_usage::FontUsage,
+        http_crawler::{get_fetch_error, is_disallowed, HttpCrawler, PageFonts},
+    },
+    font_parser::FontData,
+    parsers::{
+        font_face::FontFace,
+        font_provider::{detect_provider, ProviderUrl},
+        url_parser::{decode_data_url, to_font_url, DataUrl, FontUrl},
+    },
+};
+
+use super::{CustomError, Result};
 
 pub mod channel_message;
 pub mod html_browser;
@@ -12,6 +34,10 @@ pub mod verifier;
 pub struct Page {
     pub base_url: String,
     pub page_content: String,
+    // Fonts the browser loaded while rendering the page, empty when fetched with http
+    pub loaded_fonts: Vec<LoadedFont>,
+    // Which fonts the text is rendered with, when the browser was asked for it
+    pub font_usage: Option<FontUsage>,
 }
 
 impl Page {
@@ -19,6 +45,17 @@ impl Page {
         Page {
             base_url,
             page_content,
+            loaded_fonts: vec![],
+            font_usage: None,
+        }
+    }
+
+    pub fn from_browser_page(base_url: String, browser_page: BrowserPage) -> Page {
+        Page {
+            base_url,
+            page_content: browser_page.content,
+            loaded_fonts: browser_page.loaded_fonts,
+            font_usage: browser_page.font_usage,
         }
     }
 }
@@ -26,7 +63,41 @@ impl Page {
 #[derive(Debug)]
 pub struct SiteData {
     pub url: String,
-    pub fonts: Vec<FontData>,
+    pub fonts: Vec<SiteFont>,
+    // Web font services the site loads from, and what it asks them for
+    pub providers: Vec<ProviderUrl>,
+    // Families set in style attributes on the page
+    pub style_families: Vec<String>,
+    pub font_usage: Option<FontUsage>,
+    // Stylesheets and fonts robots.txt does not let us fetch
+    pub disallowed_urls: Vec<String>,
+    // Stylesheets and fonts that could not be fetched, and why
+    pub failed_urls: Vec<(String, FetchError)>,
+}
+
+// A font the site uses, and how it was declared, e.g. family X at weight 700 from file Y
+#[derive(Debug)]
+pub struct SiteFont {
+    pub location: FontLocation,
+    // None when the font was linked directly from the html
+    pub font_face: Option<FontFace>,
+    pub font_data: FontData,
+}
+
+#[derive(Debug, Clone)]
+pub enum FontLocation {
+    Url(String),
+    // Embedded in the css as a data: url, with the MIME type it was given
+    Data { mime_type: String },
+}
+
+impl fmt::Display for FontLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontLocation::Url(url) => write!(f, "{}", url),
+            FontLocation::Data { mime_type } => write!(f, "data:{}", mime_type),
+        }
+    }
 }
 
 impl SiteData {
@@ -34,37 +105,224 @@ impl SiteData {
         // Get page content to find links to follow
         // let page_content = crawler.get_page_content(base_url).await?;
 
-        let font_urls = crawler.get_font_urls_from_page(page).await?;
+        if !page.loaded_fonts.is_empty() {
+            return SiteData::from_loaded_fonts(crawler, page).await;
+        }
 
-        let mut font_contents: Vec<Vec<u8>> = vec![];
+        let PageFonts {
+            font_references,
+            providers,
+            style_families,
+            mut disallowed_urls,
+            mut failed_urls,
+        } = crawler.get_font_urls_from_page(page).await?;
 
-        for font_url in &font_urls {
-            let font_content = match crawler.get_content_as_bytes(font_url.as_str()).await {
-                Ok(font_content) => font_content,
-                Err(err) => {
-                    tracing::error!(error = ?err, "Failed to get font content. Continuing...");
-                    continue;
+        // The same file is often declared by more than one @font-face rule,
+        // so each url is only loaded once. Urls that failed are kept as None.
+        let mut font_data_by_url: HashMap<FontUrl, Option<(FontLocation, Vec<FontData>)>> =
+            HashMap::new();
+
+        let mut fonts: Vec<SiteFont> = vec![];
+
+        for font_reference in font_references {
+            // Like a browser, fall back to the next url when one fails to load
+            let mut loaded_url: Option<&FontUrl> = None;
+
+            for font_url in &font_reference.urls {
+                if !font_data_by_url.contains_key(font_url) {
+                    let font_data = load_font(crawler, font_url)
+                        .await
+                        .tap_err(|err| {
+                            if let FontUrl::Http(url) = font_url {
+                                if is_disallowed(err) {
+                                    disallowed_urls.push(url.to_string());
+                                } else if let Some(fetch_error) = get_fetch_error(err) {
+                                    failed_urls.push((url.to_string(), fetch_error.to_owned()));
+                                }
+                            }
+                            tracing::error!(error = ?err, "Failed to load font. Continuing...")
+                        })
+                        .ok();
+                    font_data_by_url.insert(font_url.to_owned(), font_data);
                 }
+
+                if let Some(Some(_)) = font_data_by_url.get(font_url) {
+                    loaded_url = Some(font_url);
+                    break;
+                }
+            }
+
+            let Some(Some((location, all_font_data))) =
+                loaded_url.and_then(|font_url| font_data_by_url.get(font_url))
+            else {
+                continue;
             };
-            font_contents.push(font_content);
-        }
 
-        //println!("Found {} font urls", font_urls.len());
+            for font_data in all_font_data {
+                // Only keep a font once per declaration, e.g. when the same url is repeated
+                // in src or a collection and a single file both hold it
+                let is_duplicate = fonts.iter().any(|font| {
+                    font.font_data.fingerprint == font_data.fingerprint
+                        && font.font_face == font_reference.font_face
+                });
 
-        let all_font_data: Vec<FontData> = font_contents
+                if is_duplicate {
+                    continue;
+                }
+
+                fonts.push(SiteFont {
+                    location: location.to_owned(),
+                    font_face: font_reference.font_face.clone(),
+                    font_data: font_data.clone(),
+                });
+            }
+        }
+
+        // A font that is preloaded is usually declared by a rule as well, and then
+        // the one without a rule adds nothing
+        let declared_fingerprints: HashSet<String> = fonts
             .iter()
-            .filter_map(|font_content| match FontData::from_bytes(font_content) {
-                Ok(font_content) => Some(font_content),
+            .filter(|font| font.font_face.is_some())
+            .map(|font| font.font_data.fingerprint.to_owned())
+            .collect();
+        fonts.retain(|font| {
+            font.font_face.is_some() || !declared_fingerprints.contains(&font.font_data.fingerprint)
+        });
+
+        Ok(SiteData {
+            url: page.base_url.to_owned(),
+            fonts,
+            providers,
+            style_families,
+            font_usage: page.font_usage.clone(),
+            disallowed_urls,
+            failed_urls,
+        })
+    }
+
+    // Uses the fonts the browser loaded, rather than the ones the html and css declare,
+    // which also finds fonts added by scripts. The declarations are only used to tell
+    // which @font-face rule a loaded font belongs to.
+    async fn from_loaded_fonts(crawler: &HttpCrawler, page: &Page) -> Result<SiteData> {
+        let PageFonts {
+            font_references,
+            mut providers,
+            style_families,
+            disallowed_urls,
+            failed_urls,
+        } = match crawler.get_font_urls_from_page(page).await {
+            Ok(page_fonts) => page_fonts,
+            Err(CustomError::NoElementsFound(_) | CustomError::NoFontUrlsFound(_)) => PageFonts {
+                font_references: vec![],
+                providers: vec![],
+                style_families: vec![],
+                disallowed_urls: vec![],
+                failed_urls: vec![],
+            },
+            Err(err) => return Err(err),
+        };
+
+        let mut fonts: Vec<SiteFont> = vec![];
+
+        for loaded_font in &page.loaded_fonts {
+            let all_font_data = match FontData::all_from_bytes(&loaded_font.data) {
+                Ok(all_font_data) => all_font_data,
                 Err(err) => {
-                    tracing::error!(error = ?err, "Failed to parse font data. Continuing...");
-                    None
+                    tracing::error!(
+                        error = ?err,
+                        url = loaded_font.url,
+                        mime_type = loaded_font.mime_type,
+                        "Failed to parse loaded font. Continuing..."
+                    );
+                    continue;
                 }
-            })
-            .collect();
+            };
+
+            let url = Url::parse(&loaded_font.url).ok();
+            let font_url = url.clone().and_then(to_font_url);
+
+            let mut font_faces: Vec<Option<FontFace>> = font_references
+                .iter()
+                .filter(|font_reference| {
+                    font_url
+                        .as_ref()
+                        .is_some_and(|font_url| font_reference.urls.contains(font_url))
+                })
+                .map(|font_reference| font_reference.font_face.clone())
+                .collect();
+
+            if font_faces.is_empty() {
+                font_faces.push(None);
+            }
+
+            for font_face in font_faces {
+                for font_data in &all_font_data {
+                    let is_duplicate = fonts.iter().any(|font| {
+                        font.font_data.fingerprint == font_data.fingerprint
+                            && font.font_face == font_face
+                    });
+
+                    if is_duplicate {
+                        continue;
+                    }
+
+                    fonts.push(SiteFont {
+                        location: FontLocation::Url(loaded_font.url.to_owned()),
+                        font_face: font_face.clone(),
+                        font_data: font_data.clone(),
+                    });
+                }
+            }
+
+            // Fonts from kits loaded by a script are only seen here
+            if let Some(provider_url) = url.as_ref().and_then(detect_provider) {
+                if !providers
+                    .iter()
+                    .any(|provider| provider.url == provider_url.url)
+                {
+                    providers.push(provider_url);
+                }
+            }
+        }
+
+        if fonts.is_empty() {
+            return Err(CustomError::NoFontUrlsFound(page.base_url.to_owned()));
+        }
 
         Ok(SiteData {
             url: page.base_url.to_owned(),
-            fonts: all_font_data,
+            fonts,
+            providers,
+            style_families,
+            font_usage: page.font_usage.clone(),
+            disallowed_urls,
+            failed_urls,
         })
     }
 }
+
+// Downloads or decodes the font file, and parses every font in it
+async fn load_font(
+    crawler: &HttpCrawler,
+    font_url: &FontUrl,
+) -> eyre::Result<(FontLocation, Vec<FontData>)> {
+    let (location, font_content) = match font_url {
+        FontUrl::Http(url) => (
+            FontLocation::Url(url.to_string()),
+            crawler
+                .get_font_content(url.as_str())
+                .await
+                .wrap_err("Failed to get font content")?,
+        ),
+        FontUrl::Data(url) => {
+            let DataUrl { mime_type, data } =
+                decode_data_url(url).wrap_err("Failed to decode data url")?;
+            (FontLocation::Data { mime_type }, data)
+        }
+    };
+
+    let font_data =
+        FontData::all_from_bytes(&font_content).wrap_err("Failed to parse font data")?;
+
+    Ok((location, font_data))
+}
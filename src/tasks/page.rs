@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use async_channel::Receiver;
 use eyre::Context;
 use tap::Tap;
@@ -5,24 +7,45 @@ use tokio::task::JoinHandle;
 use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::crawler::http_crawler::HttpCrawler;
+use crate::{
+    crawler::{download::DownloadOptions, host_limiter::HostLimiter, http_crawler::HttpCrawler},
+    parsers::font_face::SourceSelection,
+};
 
 use super::{channel_message::ChannelMessage, Page, SiteData};
 
 pub fn start_page_tasks(
     page_node_rx: &Receiver<ChannelMessage<Page>>,
     no_of_tasks: i32,
+    source_selection: SourceSelection,
+    host_limiter: &Arc<HostLimiter>,
+    download_options: DownloadOptions,
 ) -> Vec<JoinHandle<Vec<SiteData>>> {
     (0..no_of_tasks)
-        .map(|i| start_page_task(page_node_rx.clone(), i))
+        .map(|i| {
+            start_page_task(
+                page_node_rx.clone(),
+                i,
+                source_selection,
+                host_limiter.clone(),
+                download_options,
+            )
+        })
         .collect()
 }
 
 fn start_page_task(
     page_node_rx: Receiver<ChannelMessage<Page>>,
     i: i32,
+    source_selection: SourceSelection,
+    host_limiter: Arc<HostLimiter>,
+    download_options: DownloadOptions,
 ) -> JoinHandle<Vec<SiteData>> {
-    let crawler: HttpCrawler = HttpCrawler::new().unwrap();
+    let crawler: HttpCrawler = HttpCrawler::new()
+        .unwrap()
+        .with_source_selection(source_selection)
+        .with_host_limiter(host_limiter)
+        .with_download_options(download_options);
 
     tokio::spawn(async move {
         let mut thread_site_data: Vec<SiteData> = vec![];
@@ -49,7 +72,7 @@ fn start_page_task(
 async fn get_site_data(page: &Page, i: i32, crawler: &HttpCrawler) -> eyre::Result<SiteData> {
     tracing::info!("Received job on task {}.", i);
 
-    SiteData::from_page(&crawler, &page)
+    SiteData::from_page(crawler, page)
         .await
         .tap(|_| tracing::info!("Success! url: {}", &page.base_url))
         .wrap_err(format!(
@@ -1,10 +1,18 @@
+use std::sync::Arc;
+
 use async_channel::{Receiver, Sender};
 use eyre::Context;
 use tokio::task::JoinHandle;
 use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::{crawler::http_crawler::HttpCrawler, CustomError};
+use crate::{
+    crawler::{
+        browser_crawler::BrowserMode, download::DownloadOptions, host_limiter::HostLimiter,
+        http_crawler::HttpCrawler,
+    },
+    CustomError,
+};
 
 use super::{channel_message::ChannelMessage, Page};
 
@@ -13,6 +21,9 @@ pub fn start_verifier_tasks(
     browser_html_node_tx: &Sender<ChannelMessage<String>>,
     page_node_tx: &Sender<ChannelMessage<Page>>,
     no_of_tasks: i32,
+    browser_mode: BrowserMode,
+    host_limiter: &Arc<HostLimiter>,
+    download_options: DownloadOptions,
 ) -> Vec<JoinHandle<()>> {
     (0..no_of_tasks)
         .map(|i| {
@@ -21,6 +32,9 @@ pub fn start_verifier_tasks(
                 browser_html_node_tx.clone(),
                 page_node_tx.clone(),
                 i,
+                browser_mode,
+                host_limiter.clone(),
+                download_options,
             )
         })
         .collect()
@@ -31,8 +45,14 @@ fn start_verifier_task(
     browser_html_node_tx: Sender<ChannelMessage<String>>,
     page_node_tx: Sender<ChannelMessage<Page>>,
     i: i32,
+    browser_mode: BrowserMode,
+    host_limiter: Arc<HostLimiter>,
+    download_options: DownloadOptions,
 ) -> JoinHandle<()> {
-    let crawler: HttpCrawler = HttpCrawler::new().unwrap();
+    let crawler: HttpCrawler = HttpCrawler::new()
+        .unwrap()
+        .with_host_limiter(host_limiter)
+        .with_download_options(download_options);
 
     tokio::spawn(async move {
         while let Ok(message) = verifier_node_rx.recv().await {
@@ -47,7 +67,8 @@ fn start_verifier_task(
                 &crawler,
                 &page_node_tx,
                 &browser_html_node_tx,
-                &root_span,
+                root_span,
+                browser_mode,
             )
             .instrument(span)
             .await
@@ -67,10 +88,17 @@ async fn verify(
     page_node_tx: &Sender<ChannelMessage<Page>>,
     browser_html_node_tx: &Sender<ChannelMessage<String>>,
     root_span: &tracing::Span,
+    browser_mode: BrowserMode,
 ) -> eyre::Result<()> {
     tracing::info!("Received job on task {}.", i);
 
-    match crawler.get_font_urls_from_page(&page).await {
+    // Font usage is read from the rendered page, so every page goes to the browser
+    if browser_mode == BrowserMode::FontUsage {
+        tracing::info!("Sending url {} to browser task.", page.base_url);
+        return send_to_browser(page, browser_html_node_tx, root_span).await;
+    }
+
+    match crawler.get_font_urls_from_page(page).await {
         Ok(_) => {
             // Ignore the result, and the data to page job to finish the process.
             // We do this do make sure the event-driven architecture is DAG
@@ -91,16 +119,23 @@ async fn verify(
                     page.base_url
                 );
 
-                let mut message =
-                    ChannelMessage::new(root_span.to_owned(), page.base_url.to_owned());
-                message.inject(&root_span.context());
-
-                browser_html_node_tx.send(message).await.wrap_err(format!(
-                    "Could not send data to browser html job for url: {}",
-                    &page.base_url
-                ))
+                send_to_browser(page, browser_html_node_tx, root_span).await
             }
             err => Err(err).wrap_err(format!("Unable to get site data for {}.", &page.base_url)),
         },
     }
 }
+
+async fn send_to_browser(
+    page: &Page,
+    browser_html_node_tx: &Sender<ChannelMessage<String>>,
+    root_span: &tracing::Span,
+) -> eyre::Result<()> {
+    let mut message = ChannelMessage::new(root_span.to_owned(), page.base_url.to_owned());
+    message.inject(&root_span.context());
+
+    browser_html_node_tx.send(message).await.wrap_err(format!(
+        "Could not send data to browser html job for url: {}",
+        &page.base_url
+    ))
+}